@@ -25,6 +25,7 @@ class ElementType(IntEnum):
     MAP = 0x03
     ARRAY = 0x04
     BINARY = 0x05
+    PACKED = 0x06
     FALSE = 0x08
     TRUE = 0x09
     NONE = 0x0A
@@ -91,6 +92,8 @@ def decode(view: memoryview):
         return decode_map_chd(view)
     elif element_type == ElementType.BINARY:
         return bytes(view[1:])
+    elif element_type == ElementType.PACKED:
+        return decode_packed(view)
     else:
         raise ValueError(f"Unknown element type {element_type}")
 
@@ -279,3 +282,24 @@ def decode_array(view: memoryview) -> list:
     
     return items
 
+
+PACKED_ITEM_FORMATS = {
+    ElementType.DOUBLE: 'd',
+    ElementType.INT32: 'i',
+    ElementType.UINT32: 'I',
+    ElementType.INT64: 'q',
+    ElementType.UINT64: 'Q',
+}
+
+
+def decode_packed(view: memoryview) -> list:
+    _element_type, item_type, padding = struct.unpack_from('<BBB', view)
+    item_format = PACKED_ITEM_FORMATS.get(item_type)
+    if item_format is None:
+        raise ValueError(f"Element type {item_type} cannot be packed")
+
+    items = view[3 + padding:]
+    item_size = struct.calcsize(item_format)
+    if len(items) % item_size != 0:
+        raise ValueError("Packed array is truncated")
+    return list(struct.unpack(f'<{len(items) // item_size}{item_format}', items))
@@ -1,13 +1,32 @@
+// `#[pymethods]` in pyo3 0.17 expands to an impl block nested inside a const item.
+#![allow(non_local_definitions)]
+
 use std::sync::Arc;
 
 use pyo3::{
     prelude::*,
     types::{IntoPyDict, PyList},
 };
-use sbson::{Cursor, CursorError, ElementTypeCode};
+use sbson::{Cursor, CursorError, ElementTypeCode, MmapBuffer, PackedArray, PackedItem};
+
+/// Documents are either handed over from Python or mapped from a file.
+#[derive(Clone)]
+enum Buffer {
+    Owned(Arc<[u8]>),
+    Mapped(MmapBuffer),
+}
+
+impl AsRef<[u8]> for Buffer {
+    fn as_ref(&self) -> &[u8] {
+        match self {
+            Buffer::Owned(buffer) => buffer,
+            Buffer::Mapped(buffer) => buffer.as_ref(),
+        }
+    }
+}
 
 enum CursorImpl {
-    Generic(sbson::Cursor<Arc<[u8]>>),
+    Generic(sbson::Cursor<Buffer>),
     // CachedMap(sbson::CachedMapCursor),
 }
 
@@ -27,7 +46,7 @@ struct PyCursor {
 impl PyCursor {
     #[new]
     fn new(data: Vec<u8>) -> PyResult<Self> {
-        let cursor = sbson::Cursor::new(data.into())?;
+        let cursor = sbson::Cursor::new(Buffer::Owned(data.into()))?;
         Ok(PyCursor {
             path_segments: vec![],
             cursor_impl: CursorImpl::Generic(cursor),
@@ -36,8 +55,10 @@ impl PyCursor {
 
     #[staticmethod]
     fn new_from_file(file_name: &str) -> PyResult<Self> {
-        let data = std::fs::read(file_name)?;
-        let cursor = Cursor::new(data.into())?;
+        // SAFETY: Python code is expected not to modify documents while they're open,
+        // just like with any other memory-mapped file.
+        let data = unsafe { MmapBuffer::open(file_name)? };
+        let cursor = Cursor::new(Buffer::Mapped(data))?;
         Ok(PyCursor {
             path_segments: vec![],
             cursor_impl: CursorImpl::Generic(cursor),
@@ -59,13 +80,13 @@ impl PyCursor {
         let mut path_segments = self.path_segments.clone();
         path_segments.push(PathSegment::Key(attr.into()));
         let cursor = PyCursor {
-            path_segments: path_segments,
+            path_segments,
             cursor_impl: CursorImpl::Generic(cursor),
         };
         Ok(cursor)
     }
 
-    fn __getitem__<'a>(&'a self, index: PathSegment) -> PyResult<Self> {
+    fn __getitem__(&self, index: PathSegment) -> PyResult<Self> {
         let cursor = match (&index, &self.cursor_impl) {
             // (PathSegment::Index(_), CursorImpl::CachedMap(_)) => {
             //     return Err(pyo3::exceptions::PyNotImplementedError::new_err(
@@ -83,7 +104,7 @@ impl PyCursor {
         let mut path_segments = self.path_segments.clone();
         path_segments.push(index);
         let cursor = PyCursor {
-            path_segments: path_segments,
+            path_segments,
             cursor_impl: CursorImpl::Generic(cursor),
         };
         Ok(cursor)
@@ -119,14 +140,7 @@ impl PyCursor {
 
     #[getter]
     fn value(&self, py: Python<'_>) -> PyResult<PyObject> {
-        let cursor = match &self.cursor_impl {
-            // CursorImpl::CachedMap(_) => {
-            //     return Err(pyo3::exceptions::PyTypeError::new_err(
-            //         "Cannot get the value of a non-leaf node.",
-            //     ))
-            // }
-            CursorImpl::Generic(g) => g,
-        };
+        let CursorImpl::Generic(cursor) = &self.cursor_impl;
 
         let value = match cursor.get_element_type() {
             ElementTypeCode::Map | ElementTypeCode::Array | ElementTypeCode::MapCHD => {
@@ -144,15 +158,14 @@ impl PyCursor {
             ElementTypeCode::UInt64 => unimplemented!(),
             ElementTypeCode::Double => unimplemented!(),
             ElementTypeCode::Binary => unimplemented!(),
+            ElementTypeCode::PackedArray => packed_to_list(py, cursor)?,
         };
         Ok(value)
     }
 
     /// Query along the given path and return a cursor pointing to the specified node.
     fn goto(&self, path_segments: Vec<PathSegment>) -> PyResult<Self> {
-        let current_node = match &self.cursor_impl {
-            CursorImpl::Generic(g) => g,
-        };
+        let CursorImpl::Generic(current_node) = &self.cursor_impl;
         let cursor = current_node.goto(path_segments.iter().map(|seg| match seg {
             PathSegment::Key(k) => sbson::PathSegment::Key(k.as_str()),
             PathSegment::Index(i) => sbson::PathSegment::Index(*i),
@@ -168,13 +181,28 @@ impl PyCursor {
         Ok(cursor)
     }
 
+    /// Follow a textual path, such as `/top/items/3` or `top.items[3]`.
+    fn query(&self, path: &str) -> PyResult<Self> {
+        let CursorImpl::Generic(current_node) = &self.cursor_impl;
+        let path = sbson::Path::parse(path)?;
+        let cursor = current_node.query_path(&path)?;
+
+        let mut path_segments = self.path_segments.clone();
+        path_segments.extend(path.segments().iter().map(|segment| match segment {
+            sbson::OwnedPathSegment::Key(k) => PathSegment::Key(k.clone()),
+            sbson::OwnedPathSegment::Index(i) => PathSegment::Index(*i),
+        }));
+
+        Ok(PyCursor {
+            path_segments,
+            cursor_impl: CursorImpl::Generic(cursor),
+        })
+    }
+
     fn pythonize(&self, py: Python<'_>) -> PyResult<PyObject> {
         // If this is a map, we don't really need it to be cached,
         // since we're going to iterate the elements by order.
-        let cursor = match &self.cursor_impl {
-            CursorImpl::Generic(g) => g,
-            // CursorImpl::CachedMap(cache) => &cache.cursor,
-        };
+        let CursorImpl::Generic(cursor) = &self.cursor_impl;
         pythonize(py, cursor.borrow())
     }
 
@@ -196,10 +224,7 @@ fn pythonize(py: Python<'_>, cursor: Cursor<&[u8]>) -> PyResult<PyObject> {
     let value = match cursor.get_element_type() {
         ElementTypeCode::Map | ElementTypeCode::MapCHD => cursor
             .iter_map()?
-            .flat_map(|(key, cursor)| {
-                let value = pythonize(py, cursor).ok().map(|obj| (key, obj));
-                value
-            })
+            .flat_map(|(key, cursor)| pythonize(py, cursor).ok().map(|obj| (key, obj)))
             .into_py_dict(py)
             .into(),
         ElementTypeCode::Array => {
@@ -220,10 +245,33 @@ fn pythonize(py: Python<'_>, cursor: Cursor<&[u8]>) -> PyResult<PyObject> {
         ElementTypeCode::UInt64 => cursor.get_u64()?.into_py(py),
         ElementTypeCode::Double => cursor.get_double()?.into_py(py),
         ElementTypeCode::Binary => unimplemented!(),
+        ElementTypeCode::PackedArray => packed_to_list(py, &cursor)?,
     };
     Ok(value)
 }
 
+fn packed_to_list<T: Clone + AsRef<[u8]>>(
+    py: Python<'_>,
+    cursor: &Cursor<T>,
+) -> PyResult<PyObject> {
+    fn collect<E: PackedItem + IntoPy<PyObject>>(
+        py: Python<'_>,
+        items: PackedArray<'_, E>,
+    ) -> PyObject {
+        PyList::new(py, items.iter().map(|item| item.into_py(py))).into()
+    }
+
+    let list = match cursor.get_packed_item_type()? {
+        ElementTypeCode::Double => collect(py, cursor.get_packed_array::<f64>()?),
+        ElementTypeCode::Int32 => collect(py, cursor.get_packed_array::<i32>()?),
+        ElementTypeCode::UInt32 => collect(py, cursor.get_packed_array::<u32>()?),
+        ElementTypeCode::Int64 => collect(py, cursor.get_packed_array::<i64>()?),
+        ElementTypeCode::UInt64 => collect(py, cursor.get_packed_array::<u64>()?),
+        _ => unreachable!("packed arrays only hold numeric items"),
+    };
+    Ok(list)
+}
+
 #[pymodule]
 #[pyo3(name = "sbson")]
 fn top_level_module(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
@@ -0,0 +1,284 @@
+use clap::{Parser, Subcommand};
+use sbson::serializer::{SeekWriter, SerializationOptions, Serialize};
+use sbson::{Cursor, CursorError, ElementTypeCode, MmapBuffer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+/// Encodes, decodes and inspects SBSON documents.
+#[derive(Parser)]
+#[command(name = "sbson", version)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Encodes a JSON document as SBSON.
+    Encode {
+        /// The JSON document to encode, or `-` for stdin.
+        #[arg(default_value = "-")]
+        input: PathBuf,
+        /// Where to write the SBSON document. Defaults to stdout.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// The minimal amount of entries for a map to be encoded with CHD
+        /// instead of as an Eytzinger-ordered binary search tree.
+        #[arg(long, default_value_t = SerializationOptions::default().chd_threshold)]
+        chd_threshold: usize,
+    },
+    /// Decodes an SBSON document into pretty-printed JSON.
+    Decode {
+        /// The SBSON document to decode, or `-` for stdin.
+        input: PathBuf,
+    },
+    /// Prints a single node as JSON, without decoding the rest of the document.
+    Get {
+        /// The SBSON document to read, or `-` for stdin.
+        input: PathBuf,
+        /// A JSON Pointer such as `/items/3/name`, or a dotted path such as `items[3].name`.
+        path: String,
+    },
+    /// Prints statistics about the nodes of a document.
+    Stat {
+        /// The SBSON document to inspect, or `-` for stdin.
+        input: PathBuf,
+    },
+    /// Checks that a document is well-formed.
+    Validate {
+        /// The SBSON document to validate, or `-` for stdin.
+        input: PathBuf,
+    },
+}
+
+/// The raw bytes of an input document.
+enum Document {
+    Mapped(MmapBuffer),
+    Owned(Vec<u8>),
+}
+
+impl Document {
+    fn open(path: &Path) -> std::io::Result<Self> {
+        if path == Path::new("-") {
+            let mut buffer = vec![];
+            std::io::stdin().read_to_end(&mut buffer)?;
+            return Ok(Document::Owned(buffer));
+        }
+        // SAFETY: Documents aren't expected to be modified while they are being read,
+        // same as with any other tool working on memory-mapped files.
+        Ok(Document::Mapped(unsafe { MmapBuffer::open(path)? }))
+    }
+
+    fn cursor(&self) -> Result<Cursor<&[u8]>, CursorError> {
+        Cursor::new(match self {
+            Document::Mapped(buffer) => buffer.as_ref(),
+            Document::Owned(buffer) => buffer,
+        })
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+struct Stats {
+    /// The amount of nodes of each type, and the amount of bytes they take up, excluding their children.
+    nodes: BTreeMap<String, (usize, usize)>,
+    max_depth: usize,
+    largest_map: usize,
+    largest_array: usize,
+}
+
+fn stat(root: Cursor<&[u8]>) -> Result<Stats, CursorError> {
+    let mut stats = Stats::default();
+    let mut stack = vec![(root, 0)];
+    while let Some((cursor, depth)) = stack.pop() {
+        let element_type = cursor.get_element_type();
+        let children_count = cursor.get_children_count();
+        let mut own_size = cursor.scoped_buffer().len();
+        for index in 0..children_count {
+            let child = cursor.get_value_by_index(index)?;
+            own_size = own_size.saturating_sub(child.scoped_buffer().len());
+            stack.push((child, depth + 1));
+        }
+
+        match element_type {
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                stats.largest_map = stats.largest_map.max(children_count)
+            }
+            ElementTypeCode::Array => stats.largest_array = stats.largest_array.max(children_count),
+            _ => {}
+        }
+        let entry = stats.nodes.entry(format!("{element_type:?}")).or_default();
+        entry.0 += 1;
+        entry.1 += own_size;
+        stats.max_depth = stats.max_depth.max(depth);
+    }
+    Ok(stats)
+}
+
+/// Writes a node as pretty-printed JSON.
+///
+/// The JSON is rendered in full before any of it is written, so a malformed document
+/// results in an error rather than in truncated output.
+///
+/// Binary nodes become arrays of bytes, and non-finite doubles become `null`.
+fn write_json(cursor: &Cursor<&[u8]>, output: &mut dyn Write) -> Result<(), Box<dyn Error>> {
+    let mut json = serde_json::to_vec_pretty(cursor)?;
+    json.push(b'\n');
+    output.write_all(&json)?;
+    Ok(())
+}
+
+fn run(args: Args, stdout: &mut dyn Write) -> Result<ExitCode, Box<dyn Error>> {
+    match args.command {
+        Command::Encode {
+            input,
+            output,
+            chd_threshold,
+        } => {
+            let json = match Document::open(&input)? {
+                Document::Mapped(buffer) => serde_json::from_slice::<Value>(buffer.as_ref())?,
+                Document::Owned(buffer) => serde_json::from_slice::<Value>(&buffer)?,
+            };
+            let options = SerializationOptions { chd_threshold };
+            match output {
+                Some(path) => {
+                    let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                    let mut writer = SeekWriter::new(file)?;
+                    json.serialize(&options, &mut writer)?;
+                    writer.flush()?;
+                }
+                None => {
+                    let mut buffer = vec![];
+                    json.serialize(&options, &mut buffer)?;
+                    stdout.write_all(&buffer)?;
+                }
+            }
+        }
+        Command::Decode { input } => {
+            let document = Document::open(&input)?;
+            write_json(&document.cursor()?, stdout)?;
+        }
+        Command::Get { input, path } => {
+            let document = Document::open(&input)?;
+            write_json(&document.cursor()?.query(&path)?, stdout)?;
+        }
+        Command::Stat { input } => {
+            let document = Document::open(&input)?;
+            let cursor = document.cursor()?;
+            let size = cursor.scoped_buffer().len();
+            let stats = stat(cursor)?;
+            let node_count: usize = stats.nodes.values().map(|(count, _size)| count).sum();
+            writeln!(stdout, "size:          {size} bytes")?;
+            writeln!(stdout, "nodes:         {node_count}")?;
+            writeln!(stdout, "max depth:     {}", stats.max_depth)?;
+            writeln!(stdout, "largest map:   {} entries", stats.largest_map)?;
+            writeln!(stdout, "largest array: {} items", stats.largest_array)?;
+            for (element_type, (count, size)) in &stats.nodes {
+                writeln!(
+                    stdout,
+                    "  {element_type:<8} {count:>10} nodes {size:>12} bytes"
+                )?;
+            }
+        }
+        Command::Validate { input } => {
+            let document = Document::open(&input)?;
+            if let Err(err) = document.cursor()?.validate() {
+                eprintln!("{err}");
+                return Ok(ExitCode::FAILURE);
+            }
+            writeln!(stdout, "OK")?;
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn main() -> ExitCode {
+    match run(Args::parse(), &mut std::io::stdout().lock()) {
+        Ok(code) => code,
+        Err(err) => {
+            eprintln!("sbson: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(json: &Value, chd_threshold: usize) -> Vec<u8> {
+        let mut buffer = vec![];
+        json.serialize(&SerializationOptions { chd_threshold }, &mut buffer)
+            .unwrap();
+        buffer
+    }
+
+    #[test]
+    fn test_decode() {
+        let json = serde_json::json!({
+            "name": "sbson",
+            "items": [1, -2, 3.5, null, {"enabled": true}],
+        });
+        let buffer = encode(&json, 2);
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        assert_eq!(json, cursor.to_json_value().unwrap());
+
+        let node = cursor.query("/items/4/enabled").unwrap();
+        assert_eq!(Value::Bool(true), node.to_json_value().unwrap());
+    }
+
+    /// Runs the CLI with the given arguments, returning its result and everything it printed.
+    fn run_cli(args: &[&str]) -> (Result<ExitCode, Box<dyn Error>>, String) {
+        let args = Args::try_parse_from(["sbson"].iter().chain(args)).unwrap();
+        let mut stdout = vec![];
+        let result = run(args, &mut stdout);
+        (result, String::from_utf8(stdout).unwrap())
+    }
+
+    #[test]
+    fn test_decode_and_get_files() {
+        let json = serde_json::json!({
+            "name": "sbson",
+            "items": [1, -2, 3.5, null, {"enabled": true}],
+        });
+        let buffer = encode(&json, 2);
+        let path = std::env::temp_dir().join(format!("sbson-cli-{}", std::process::id()));
+        let path_str = path.to_str().unwrap();
+
+        std::fs::write(&path, &buffer).unwrap();
+        let (result, decoded) = run_cli(&["decode", path_str]);
+        let (get_result, item) = run_cli(&["get", path_str, "items[4].enabled"]);
+        let (missing_result, missing) = run_cli(&["get", path_str, "/items/5"]);
+
+        // Cut the document short, so that only its last node is out of bounds.
+        std::fs::write(&path, &buffer[..buffer.len() - 2]).unwrap();
+        let (truncated_result, truncated) = run_cli(&["decode", path_str]);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(result.is_ok());
+        assert_eq!(json, serde_json::from_str::<Value>(&decoded).unwrap());
+        assert!(get_result.is_ok());
+        assert_eq!("true\n", item);
+        assert!(missing_result.is_err());
+        assert_eq!("", missing);
+        assert!(truncated_result.is_err());
+        assert_eq!("", truncated);
+    }
+
+    #[test]
+    fn test_stat() {
+        let json = serde_json::json!({"a": [1, 2], "b": {"c": "d"}});
+        let buffer = encode(&json, 1);
+        let stats = stat(Cursor::new(&buffer[..]).unwrap()).unwrap();
+        assert_eq!(2, stats.max_depth);
+        assert_eq!(2, stats.largest_map);
+        assert_eq!(Some(&(2, 18)), stats.nodes.get("UInt64"));
+        assert_eq!(Some(&(1, 3)), stats.nodes.get("String"));
+        assert_eq!(2, stats.nodes.get("MapCHD").unwrap().0);
+        let total: usize = stats.nodes.values().map(|(_count, size)| size).sum();
+        assert_eq!(buffer.len(), total);
+    }
+}
@@ -3,14 +3,25 @@ use std::sync::Arc;
 use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
 use sbson::{Cursor, PathSegment};
 
-const GOTO_TREE: &[u8] = include_bytes!("../../../test_vectors/goto.sbson");
-const GOTO_TREE_PHF: &[u8] = include_bytes!("../../../test_vectors/goto_phf.sbson");
+/// Reads one of the test vectors used by the benchmarks.
+///
+/// The goto vectors are too large to keep in the repository, so they have to be generated
+/// first by running `generate_test_vectors.py` from the `python-refimpl` directory.
+fn read_test_vector(name: &str) -> Vec<u8> {
+    let path = format!("{}/../../test_vectors/{name}", env!("CARGO_MANIFEST_DIR"));
+    std::fs::read(&path).unwrap_or_else(|err| {
+        panic!("failed to read {path} ({err}); run python-refimpl/generate_test_vectors.py")
+    })
+}
 
 fn bench_goto_item(c: &mut Criterion) {
-    let cur_borrow: Cursor<&[u8]> = Cursor::new(GOTO_TREE).unwrap();
+    let goto_tree = read_test_vector("goto.sbson");
+    let goto_tree_phf = read_test_vector("goto_phf.sbson");
+
+    let cur_borrow: Cursor<&[u8]> = Cursor::new(&goto_tree[..]).unwrap();
     let top_borrow = cur_borrow.get_value_by_key("top").unwrap();
 
-    let cur_borrow_chd: Cursor<&[u8]> = Cursor::new(GOTO_TREE_PHF).unwrap();
+    let cur_borrow_chd: Cursor<&[u8]> = Cursor::new(&goto_tree_phf[..]).unwrap();
     let top_borrow_chd = cur_borrow_chd.get_value_by_key("top").unwrap();
 
     let mut group = c.benchmark_group("goto");
@@ -92,8 +103,8 @@ fn bench_goto_all_items(c: &mut Criterion) {
     let item_names: Vec<_> = (0..8000).map(|i| format!("item_{i:04}")).collect();
     let mut group = c.benchmark_group("goto_all_items");
 
-    let goto_tree_arc: Arc<[u8]> = GOTO_TREE.into();
-    let goto_tree_phf_arc: Arc<[u8]> = GOTO_TREE_PHF.into();
+    let goto_tree_arc: Arc<[u8]> = read_test_vector("goto.sbson").into();
+    let goto_tree_phf_arc: Arc<[u8]> = read_test_vector("goto_phf.sbson").into();
 
     let cur_borrow: Cursor<&[u8]> = Cursor::new(goto_tree_arc.as_ref()).unwrap();
     let top_borrow = cur_borrow.get_value_by_key("top").unwrap();
@@ -108,7 +119,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
         b.iter(|| {
             for item_name in item_names.iter() {
                 let integer = top_borrow
-                    .get_value_by_key(&item_name)
+                    .get_value_by_key(item_name)
                     .unwrap()
                     .get_value_by_key("something")
                     .unwrap()
@@ -126,7 +137,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
                 let integer = top_borrow
                     .goto(
                         [
-                            PathSegment::Key(&item_name),
+                            PathSegment::Key(item_name),
                             PathSegment::Key("something"),
                             PathSegment::Index(3),
                         ]
@@ -143,7 +154,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
         b.iter(|| {
             for item_name in item_names.iter() {
                 let integer = top_borrow_chd
-                    .get_value_by_key(&item_name)
+                    .get_value_by_key(item_name)
                     .unwrap()
                     .get_value_by_key("something")
                     .unwrap()
@@ -161,7 +172,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
                 let integer = top_borrow_chd
                     .goto(
                         [
-                            PathSegment::Key(&item_name),
+                            PathSegment::Key(item_name),
                             PathSegment::Key("something"),
                             PathSegment::Index(3),
                         ]
@@ -178,7 +189,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
         b.iter(|| {
             for item_name in item_names.iter() {
                 let integer = top_arc_chd
-                    .get_value_by_key(&item_name)
+                    .get_value_by_key(item_name)
                     .unwrap()
                     .get_value_by_key("something")
                     .unwrap()
@@ -196,7 +207,7 @@ fn bench_goto_all_items(c: &mut Criterion) {
                 let integer = top_arc_chd
                     .goto(
                         [
-                            PathSegment::Key(&item_name),
+                            PathSegment::Key(item_name),
                             PathSegment::Key("something"),
                             PathSegment::Index(3),
                         ]
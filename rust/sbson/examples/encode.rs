@@ -1,25 +1,26 @@
-use sbson;
-use sbson::serializer::{SerializationOptions, Serialize};
-use serde_json;
+use sbson::serializer::{SeekWriter, SerializationOptions, Serialize};
+use std::io::Write;
 use std::str::FromStr;
 
 fn main() {
-    let foo: Vec<_> = std::env::args().collect();
-    if foo.len() != 3 {
+    let args: Vec<_> = std::env::args().collect();
+    if args.len() != 3 {
         eprintln!("Usage: encode <input>.json <output>.json");
         std::process::exit(1);
     }
 
-    let s = std::fs::read_to_string(&foo[1]).unwrap();
+    let s = std::fs::read_to_string(&args[1]).unwrap();
 
     let js_start = std::time::Instant::now();
     let value = serde_json::Value::from_str(&s).unwrap();
     let js_end = std::time::Instant::now();
 
     let options = SerializationOptions { chd_threshold: 512 };
-    let mut output = Vec::<u8>::new();
+    let file = std::fs::File::create(&args[2]).unwrap();
+    let mut output = SeekWriter::new(std::io::BufWriter::new(file)).unwrap();
     let sb_start = std::time::Instant::now();
     value.serialize(&options, &mut output).unwrap();
+    output.flush().unwrap();
     let sb_end = std::time::Instant::now();
 
     eprintln!(
@@ -27,5 +28,4 @@ fn main() {
         js_end.duration_since(js_start),
         sb_end.duration_since(sb_start)
     );
-    std::fs::write(&foo[2], output).unwrap();
 }
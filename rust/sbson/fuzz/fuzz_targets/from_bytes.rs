@@ -0,0 +1,26 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use serde::Deserialize;
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+struct Nested {
+    id: i32,
+    label: String,
+}
+
+#[allow(dead_code)]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Document<'a> {
+    name: &'a str,
+    values: Vec<i64>,
+    flags: (bool, bool),
+    nested: Vec<Nested>,
+}
+
+fuzz_target!(|data: &[u8]| {
+    let _ = sbson::from_bytes::<Document>(data);
+    let _ = sbson::from_bytes::<serde_json::Value>(data);
+});
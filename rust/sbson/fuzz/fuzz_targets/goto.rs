@@ -0,0 +1,28 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sbson::{Cursor, PathSegment};
+
+// The first byte selects a path out of a small fixed set, the rest is the document.
+fuzz_target!(|data: &[u8]| {
+    let Some((selector, document)) = data.split_first() else {
+        return;
+    };
+    let Ok(cursor) = Cursor::new(document) else {
+        return;
+    };
+
+    let paths: [&[PathSegment]; 4] = [
+        &[PathSegment::Key("BLARG"), PathSegment::Index(1)],
+        &[PathSegment::Key("FLORP"), PathSegment::Key("X")],
+        &[PathSegment::Index(0), PathSegment::Index(0), PathSegment::Key("")],
+        &[PathSegment::Key("3")],
+    ];
+    let path = paths[*selector as usize % paths.len()];
+    if let Ok(node) = cursor.goto(path.iter().copied()) {
+        let _ = node.get_children_count();
+        let _ = node.get_str();
+        let _ = node.get_binary();
+        let _ = node.get_i64();
+    }
+});
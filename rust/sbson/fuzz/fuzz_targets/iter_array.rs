@@ -0,0 +1,20 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sbson::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(cursor) = Cursor::new(data) else {
+        return;
+    };
+    let Ok(items) = cursor.iter_array() else {
+        return;
+    };
+    for item in items {
+        let _ = item.get_children_count();
+        let _ = item.get_value_by_index(0);
+        let _ = item.get_storage_str();
+        let _ = item.get_storage_binary();
+    }
+    let _ = cursor.get_value_by_index(cursor.get_children_count().wrapping_sub(1));
+});
@@ -0,0 +1,23 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sbson::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(cursor) = Cursor::new(data) else {
+        return;
+    };
+    let Ok(items) = cursor.iter_map() else {
+        return;
+    };
+    for (key, value) in items {
+        let _ = cursor.get_value_by_key(key);
+        let _ = value.get_children_count();
+        let _ = value.get_str();
+        let _ = value.get_double();
+    }
+    for index in 0..cursor.get_children_count() {
+        let _ = cursor.get_key_by_index(index);
+        let _ = cursor.get_value_by_index(index);
+    }
+});
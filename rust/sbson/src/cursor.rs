@@ -18,10 +18,18 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+#[cfg(feature = "alloc")]
+use super::raw_cursor::EytzingerInOrder;
 use super::raw_cursor::{get_byte_array_at, RawCursor};
-use super::{CursorError, ElementTypeCode, PathSegment};
+use super::{CursorError, ElementTypeCode, PathSegment, RawGotoError};
+#[cfg(feature = "alloc")]
+use super::{GotoError, Path, MAP_TYPES};
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
 use core::ffi::CStr;
 use core::ops::Range;
+#[cfg(feature = "alloc")]
+use core::ops::{Bound, RangeBounds};
 
 /// An SBSON cursor over a buffer-type.
 ///
@@ -36,8 +44,8 @@ pub struct Cursor<T> {
     pub(crate) raw_cursor: RawCursor,
 }
 
-impl<T> std::fmt::Debug for Cursor<T> {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+impl<T> core::fmt::Debug for Cursor<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("Cursor")
             .field("range", &self.range)
             .field("raw_cursor", &self.raw_cursor)
@@ -115,22 +123,82 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
         Ok(cursor)
     }
 
+    /// Follows a sequence of path segments from this node.
+    ///
+    /// Keys only select map entries, while indices select array items, or map entries by their
+    /// position in storage.
+    ///
+    /// On failure, the error describes the segment that couldn't be followed, the path leading
+    /// to it, and the offset of the node it was applied to.
+    #[cfg(feature = "alloc")]
     pub fn goto<'a>(
         &self,
         path_segments: impl Iterator<Item = PathSegment<'a>>,
-    ) -> Result<Self, CursorError> {
+    ) -> Result<Self, GotoError> {
+        self.goto_with(path_segments, false)
+    }
+
+    /// Like `goto`, but as in JSON Pointers, a key that is a valid array index (such as `"3"`)
+    /// selects an item when it is applied to an array.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn goto_with<'a>(
+        &self,
+        path_segments: impl Iterator<Item = PathSegment<'a>>,
+        keys_as_indices: bool,
+    ) -> Result<Self, GotoError> {
+        let mut path = Vec::new();
+        self.follow(path_segments, keys_as_indices, |segment| {
+            path.push(segment.into())
+        })
+        .map_err(|(err, segment)| GotoError {
+            path: Path::from(path),
+            segment: segment.into(),
+            offset: err.offset,
+            kind: err.kind,
+        })
+    }
+
+    /// Like `goto`, but never allocates, and only reports the index of the failing segment.
+    pub fn goto_raw<'a>(
+        &self,
+        path_segments: impl Iterator<Item = PathSegment<'a>>,
+    ) -> Result<Self, RawGotoError> {
+        self.follow(path_segments, false, |_segment| {})
+            .map_err(|(err, _segment)| err)
+    }
+
+    /// Follows the path segments, passing each one to `on_step` once it has been followed,
+    /// and returning the segment that couldn't be followed on failure.
+    fn follow<'a>(
+        &self,
+        path_segments: impl Iterator<Item = PathSegment<'a>>,
+        keys_as_indices: bool,
+        mut on_step: impl FnMut(PathSegment<'a>),
+    ) -> Result<Self, (RawGotoError, PathSegment<'a>)> {
         let mut buffer = &self.buffer.as_ref()[self.range.clone()];
         let mut raw_cursor = self.raw_cursor.clone();
         let mut range = self.range.clone();
-        for segment in path_segments {
-            let (mut sub_range, sub_cursor) = match segment {
-                PathSegment::Key(key) => {
-                    let (_index, sub_range, sub_cursor) =
-                        raw_cursor.get_value_and_index_by_key(buffer, key)?;
-                    (sub_range, sub_cursor)
+        for (depth, segment) in path_segments.enumerate() {
+            let step = match segment {
+                PathSegment::Key(key) if keys_as_indices => {
+                    array_index_of(&raw_cursor, key).map_or(segment, PathSegment::Index)
                 }
-                PathSegment::Index(index) => raw_cursor.get_value_by_index(buffer, index)?,
+                _ => segment,
             };
+            let (mut sub_range, sub_cursor) = match step {
+                PathSegment::Key(key) => raw_cursor
+                    .get_value_and_index_by_key(buffer, key)
+                    .map(|(_index, sub_range, sub_cursor)| (sub_range, sub_cursor)),
+                PathSegment::Index(index) => raw_cursor.get_value_by_index(buffer, index),
+            }
+            .map_err(|kind| {
+                let err = RawGotoError {
+                    depth,
+                    offset: range.start,
+                    kind,
+                };
+                (err, segment)
+            })?;
 
             buffer = &buffer[sub_range.clone()];
             raw_cursor = sub_cursor;
@@ -138,6 +206,7 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
             sub_range.start += range.start;
             sub_range.end += range.start;
             range = sub_range;
+            on_step(segment);
         }
         Ok(Cursor {
             buffer: self.buffer.clone(),
@@ -165,7 +234,8 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
     }
 
     /// Returns the key of a key-value pair in map nodes by its index.
-    /// Note that the exact position of a certain key is implementation defined.
+    /// Note that the exact position of a certain key is implementation defined;
+    /// use `iter_map_sorted` to go over the keys in order.
     pub fn get_key_by_index(&self, index: usize) -> Result<&str, CursorError> {
         self.raw_cursor
             .get_key_by_index(self.scoped_buffer(), index)
@@ -176,6 +246,7 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
             ElementTypeCode::True => Ok(true),
             ElementTypeCode::False => Ok(false),
             _ => Err(CursorError::WrongElementType {
+                expected: &[ElementTypeCode::True, ElementTypeCode::False],
                 actual: self.raw_cursor.element_type,
             }),
         }
@@ -292,6 +363,107 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
             }))
     }
 
+    /// Iterate over the children of this map node, in lexicographic order of their keys.
+    ///
+    /// Eytzinger-ordered maps are traversed in-order without allocating, while the keys of
+    /// CHD maps are collected and sorted up front.
+    /// Malformed children are silently dropped.
+    #[cfg(feature = "alloc")]
+    pub fn iter_map_sorted(&self) -> Result<impl Iterator<Item = (&str, Self)>, CursorError> {
+        self.iter_map_sorted_from(|_key| false)
+    }
+
+    /// Iterate over the children of this map node whose keys fall within `range`,
+    /// in lexicographic order of their keys.
+    ///
+    /// For Eytzinger-ordered maps, the first key within the range is found by a binary search,
+    /// and the iteration stops right after the last one. CHD maps are unordered, so their keys
+    /// are collected and sorted up front, as in `iter_map_sorted`.
+    /// Malformed children are silently dropped.
+    #[cfg(feature = "alloc")]
+    pub fn map_range<'a, R: RangeBounds<&'a str> + 'a>(
+        &'a self,
+        range: R,
+    ) -> Result<impl Iterator<Item = (&'a str, Self)> + 'a, CursorError> {
+        let (start, end) = (range.start_bound().cloned(), range.end_bound().cloned());
+        let is_before = move |key: &str| match start {
+            Bound::Included(start) => key < start,
+            Bound::Excluded(start) => key <= start,
+            Bound::Unbounded => false,
+        };
+        let is_within = move |key: &str| match end {
+            Bound::Included(end) => key <= end,
+            Bound::Excluded(end) => key < end,
+            Bound::Unbounded => true,
+        };
+        Ok(self
+            .iter_map_sorted_from(is_before)?
+            .take_while(move |(key, _value)| is_within(key)))
+    }
+
+    /// Iterate over the children of this map node whose keys start with `prefix`,
+    /// in lexicographic order of their keys.
+    ///
+    /// See `map_range` for how this works for each type of map.
+    #[cfg(feature = "alloc")]
+    pub fn map_prefix<'a>(
+        &'a self,
+        prefix: &'a str,
+    ) -> Result<impl Iterator<Item = (&'a str, Self)> + 'a, CursorError> {
+        Ok(self
+            .map_range((Bound::Included(prefix), Bound::Unbounded))?
+            .take_while(move |(key, _value)| key.starts_with(prefix)))
+    }
+
+    /// Iterate over the children of this map node in order, skipping the keys `is_before`.
+    #[cfg(feature = "alloc")]
+    fn iter_map_sorted_from<'a>(
+        &'a self,
+        is_before: impl Fn(&str) -> bool + 'a,
+    ) -> Result<impl Iterator<Item = (&'a str, Self)> + 'a, CursorError> {
+        Ok(self.sorted_map_indices(is_before)?.flat_map(|index| {
+            let key = self.get_key_by_index(index).ok()?;
+            let value = self.get_value_by_index(index).ok()?;
+            Some((key, value))
+        }))
+    }
+
+    /// Returns the indices of the children of this map node in the order of their keys,
+    /// skipping the keys `is_before`.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn sorted_map_indices<'a>(
+        &'a self,
+        is_before: impl Fn(&str) -> bool + 'a,
+    ) -> Result<impl Iterator<Item = usize> + 'a, CursorError> {
+        let count = self.get_children_count();
+        let (in_order, sorted) = match self.raw_cursor.element_type {
+            ElementTypeCode::Map => {
+                let in_order = EytzingerInOrder::lower_bound(count, |index| {
+                    Ok::<_, CursorError>(is_before(self.get_key_by_index(index)?))
+                })?;
+                (Some(in_order), None)
+            }
+            ElementTypeCode::MapCHD => {
+                let mut keys: Vec<(&str, usize)> = (0..count)
+                    .flat_map(|index| self.get_key_by_index(index).map(|key| (key, index)))
+                    .filter(|(key, _index)| !is_before(key))
+                    .collect();
+                keys.sort_unstable();
+                (None, Some(keys.into_iter().map(|(_key, index)| index)))
+            }
+            actual => {
+                return Err(CursorError::WrongElementType {
+                    expected: MAP_TYPES,
+                    actual,
+                })
+            }
+        };
+        Ok(in_order
+            .into_iter()
+            .flatten()
+            .chain(sorted.into_iter().flatten()))
+    }
+
     /// Iterate over the children of this map node, returning borrowed cursors.
     /// Malformed children are silently dropped.
     pub fn iter_map_borrowed(
@@ -314,6 +486,7 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
         Ok(self
             .raw_cursor
             .iter_array(self.range.clone(), self.scoped_buffer())?
+            .flat_map(|range| range.ok())
             .flat_map(|range| Cursor::new_with_range(self.buffer.as_ref(), range).ok()))
     }
 
@@ -329,7 +502,32 @@ impl<T: Clone + AsRef<[u8]>> Cursor<T> {
     }
 }
 
+/// Returns the index a key refers to when it's applied to the given node, if it is an array.
+fn array_index_of(raw_cursor: &RawCursor, key: &str) -> Option<usize> {
+    match raw_cursor.element_type {
+        ElementTypeCode::Array => parse_array_index(key),
+        _ => None,
+    }
+}
+
+/// Parses an array index the way RFC 6901 defines them: decimal, without leading zeros.
+pub(crate) fn parse_array_index(key: &str) -> Option<usize> {
+    if key.len() > 1 && key.starts_with('0') || !key.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    key.parse().ok()
+}
+
 impl<'data> Cursor<&'data [u8]> {
+    /// Returns the key of a key-value pair in map nodes by its index.
+    ///
+    /// This reference is lifetime-bound to the backing storage referenced by this cursor,
+    /// and may outlive the cursor.
+    pub fn get_storage_key_by_index(&self, index: usize) -> Result<&'data str, CursorError> {
+        self.raw_cursor
+            .get_key_by_index(&self.buffer[self.range.clone()], index)
+    }
+
     /// Returns a reference to the null-terminated string pointed to by the cursor.
     ///
     /// This reference is lifetime-bound to the backing storage referenced by this cursor,
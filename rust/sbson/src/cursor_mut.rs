@@ -0,0 +1,295 @@
+use super::raw_cursor::{RawCursor, ELEMENT_TYPE_SIZE};
+#[cfg(feature = "alloc")]
+use super::GotoError;
+use super::{Cursor, CursorError, ElementTypeCode, PathSegment, RawGotoError};
+use core::ops::Range;
+
+/// The element types of the leaves that can be overwritten in place, by their size.
+const ONE_BYTE_TYPES: &[ElementTypeCode] = &[
+    ElementTypeCode::False,
+    ElementTypeCode::True,
+    ElementTypeCode::None,
+];
+const FOUR_BYTE_TYPES: &[ElementTypeCode] = &[ElementTypeCode::Int32, ElementTypeCode::UInt32];
+const EIGHT_BYTE_TYPES: &[ElementTypeCode] = &[
+    ElementTypeCode::Int64,
+    ElementTypeCode::UInt64,
+    ElementTypeCode::Double,
+];
+
+/// An SBSON cursor over a mutable buffer, for patching fixed-size leaves in place.
+///
+/// A leaf can only be replaced by a value of the same size, so no other node has to move:
+/// booleans and nulls can replace each other, and so can 32-bit integers, as well as 64-bit
+/// integers and doubles. Anything else fails with `WrongElementType`, listing the element
+/// types the node could have been replaced with.
+pub struct CursorMut<T> {
+    pub(crate) buffer: T,
+    range: Range<usize>,
+    raw_cursor: RawCursor,
+}
+
+impl<T> core::fmt::Debug for CursorMut<T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("CursorMut")
+            .field("range", &self.range)
+            .field("raw_cursor", &self.raw_cursor)
+            .finish()
+    }
+}
+
+impl<T: AsRef<[u8]> + AsMut<[u8]>> CursorMut<T> {
+    pub fn new(buffer: T) -> Result<Self, CursorError> {
+        let raw_cursor = RawCursor::new(buffer.as_ref())?;
+        let range = 0..buffer.as_ref().len();
+        Ok(Self {
+            buffer,
+            range,
+            raw_cursor,
+        })
+    }
+
+    /// Returns the underlying buffer.
+    pub fn into_inner(self) -> T {
+        self.buffer
+    }
+
+    /// Returns a read-only cursor pointing to the same node.
+    pub fn as_cursor(&self) -> Cursor<&[u8]> {
+        Cursor {
+            buffer: self.buffer.as_ref(),
+            range: self.range.clone(),
+            raw_cursor: self.raw_cursor.clone(),
+        }
+    }
+
+    pub fn get_element_type(&self) -> ElementTypeCode {
+        self.raw_cursor.element_type
+    }
+
+    /// Returns a mutable subcursor by indexing into a specific array/map item.
+    pub fn get_value_by_index(
+        &mut self,
+        index: usize,
+    ) -> Result<CursorMut<&mut [u8]>, CursorError> {
+        let child = self.as_cursor().get_value_by_index(index)?;
+        Ok(self.reborrow(child.range, child.raw_cursor))
+    }
+
+    /// Searches a map item by key, and returns a mutable cursor for that item.
+    pub fn get_value_by_key(&mut self, key: &str) -> Result<CursorMut<&mut [u8]>, CursorError> {
+        let child = self.as_cursor().get_value_by_key(key)?;
+        Ok(self.reborrow(child.range, child.raw_cursor))
+    }
+
+    /// Follows a sequence of path segments from this node, like `Cursor::goto`.
+    #[cfg(feature = "alloc")]
+    pub fn goto<'a>(
+        &mut self,
+        path_segments: impl Iterator<Item = PathSegment<'a>>,
+    ) -> Result<CursorMut<&mut [u8]>, GotoError> {
+        let child = self.as_cursor().goto(path_segments)?;
+        Ok(self.reborrow(child.range, child.raw_cursor))
+    }
+
+    /// Like `goto`, but never allocates, and only reports the index of the failing segment.
+    pub fn goto_raw<'a>(
+        &mut self,
+        path_segments: impl Iterator<Item = PathSegment<'a>>,
+    ) -> Result<CursorMut<&mut [u8]>, RawGotoError> {
+        let child = self.as_cursor().goto_raw(path_segments)?;
+        Ok(self.reborrow(child.range, child.raw_cursor))
+    }
+
+    pub fn set_bool(&mut self, value: bool) -> Result<(), CursorError> {
+        let element_type = match value {
+            true => ElementTypeCode::True,
+            false => ElementTypeCode::False,
+        };
+        self.overwrite(element_type, ONE_BYTE_TYPES, &[])
+    }
+
+    pub fn set_none(&mut self) -> Result<(), CursorError> {
+        self.overwrite(ElementTypeCode::None, ONE_BYTE_TYPES, &[])
+    }
+
+    pub fn set_i32(&mut self, value: i32) -> Result<(), CursorError> {
+        self.overwrite(
+            ElementTypeCode::Int32,
+            FOUR_BYTE_TYPES,
+            &value.to_le_bytes(),
+        )
+    }
+
+    pub fn set_u32(&mut self, value: u32) -> Result<(), CursorError> {
+        self.overwrite(
+            ElementTypeCode::UInt32,
+            FOUR_BYTE_TYPES,
+            &value.to_le_bytes(),
+        )
+    }
+
+    pub fn set_i64(&mut self, value: i64) -> Result<(), CursorError> {
+        self.overwrite(
+            ElementTypeCode::Int64,
+            EIGHT_BYTE_TYPES,
+            &value.to_le_bytes(),
+        )
+    }
+
+    pub fn set_u64(&mut self, value: u64) -> Result<(), CursorError> {
+        self.overwrite(
+            ElementTypeCode::UInt64,
+            EIGHT_BYTE_TYPES,
+            &value.to_le_bytes(),
+        )
+    }
+
+    pub fn set_double(&mut self, value: f64) -> Result<(), CursorError> {
+        self.overwrite(
+            ElementTypeCode::Double,
+            EIGHT_BYTE_TYPES,
+            &value.to_le_bytes(),
+        )
+    }
+
+    fn reborrow(&mut self, range: Range<usize>, raw_cursor: RawCursor) -> CursorMut<&mut [u8]> {
+        CursorMut {
+            buffer: self.buffer.as_mut(),
+            range,
+            raw_cursor,
+        }
+    }
+
+    /// Replaces the current node, provided it is one of `same_size`.
+    fn overwrite(
+        &mut self,
+        element_type: ElementTypeCode,
+        same_size: &'static [ElementTypeCode],
+        payload: &[u8],
+    ) -> Result<(), CursorError> {
+        let actual = self.raw_cursor.element_type;
+        if !same_size.contains(&actual) {
+            return Err(CursorError::WrongElementType {
+                expected: same_size,
+                actual,
+            });
+        }
+        let node = self
+            .buffer
+            .as_mut()
+            .get_mut(self.range.clone())
+            .and_then(|node| node.get_mut(..ELEMENT_TYPE_SIZE + payload.len()))
+            .ok_or(CursorError::DocumentTooShort)?;
+        node[0] = element_type as u8;
+        node[ELEMENT_TYPE_SIZE..].copy_from_slice(payload);
+        self.raw_cursor.element_type = element_type;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_patch_leaves() {
+        let doc = BTreeMap::from([("enabled", vec![true]), ("counts", vec![false, false])]);
+        let mut buffer = serialize(&(doc, 7i64, 1.5, 3i32, "text"));
+        let original_len = buffer.len();
+
+        let mut cursor = CursorMut::new(&mut buffer[..]).unwrap();
+        let path = [
+            PathSegment::Index(0),
+            PathSegment::Key("enabled"),
+            PathSegment::Index(0),
+        ];
+        cursor
+            .goto(path.into_iter())
+            .unwrap()
+            .set_bool(false)
+            .unwrap();
+        {
+            let mut counts = cursor.get_value_by_index(0).unwrap();
+            let mut counts = counts.get_value_by_key("counts").unwrap();
+            counts.get_value_by_index(0).unwrap().set_none().unwrap();
+            counts
+                .get_value_by_index(1)
+                .unwrap()
+                .set_bool(true)
+                .unwrap();
+        }
+        cursor
+            .get_value_by_index(1)
+            .unwrap()
+            .set_i64(i64::MIN)
+            .unwrap();
+        // Replacing a node with another type of the same size is fine.
+        cursor
+            .get_value_by_index(2)
+            .unwrap()
+            .set_u64(u64::MAX)
+            .unwrap();
+        let mut int = cursor.get_value_by_index(3).unwrap();
+        int.set_u32(0xdead_beef).unwrap();
+        assert_eq!(ElementTypeCode::UInt32, int.get_element_type());
+        assert_eq!(Ok(0xdead_beef), int.as_cursor().get_u32());
+
+        assert_eq!(original_len, buffer.len());
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        let map = cursor.get_value_by_index(0).unwrap();
+        let enabled = map.get_value_by_key("enabled").unwrap();
+        assert_eq!(Ok(false), enabled.get_value_by_index(0).unwrap().get_bool());
+        let counts = map.get_value_by_key("counts").unwrap();
+        assert_eq!(Ok(()), counts.get_value_by_index(0).unwrap().get_none());
+        assert_eq!(Ok(true), counts.get_value_by_index(1).unwrap().get_bool());
+        assert_eq!(
+            Ok(i64::MIN),
+            cursor.get_value_by_index(1).unwrap().get_i64()
+        );
+        assert_eq!(
+            Ok(u64::MAX),
+            cursor.get_value_by_index(2).unwrap().get_u64()
+        );
+        assert_eq!(Ok("text"), cursor.get_value_by_index(4).unwrap().get_str());
+    }
+
+    #[test]
+    fn test_patch_size_mismatch() {
+        let mut buffer = serialize(&(3i32, 1.5, "text", true));
+        let original = buffer.clone();
+        let mut cursor = CursorMut::new(&mut buffer[..]).unwrap();
+
+        assert_eq!(
+            Err(CursorError::WrongElementType {
+                expected: &[
+                    ElementTypeCode::Int64,
+                    ElementTypeCode::UInt64,
+                    ElementTypeCode::Double
+                ],
+                actual: ElementTypeCode::Int32,
+            }),
+            cursor.get_value_by_index(0).unwrap().set_i64(1)
+        );
+        assert!(cursor.get_value_by_index(1).unwrap().set_i32(1).is_err());
+        assert!(cursor
+            .get_value_by_index(2)
+            .unwrap()
+            .set_bool(true)
+            .is_err());
+        assert!(cursor
+            .get_value_by_index(3)
+            .unwrap()
+            .set_double(1.0)
+            .is_err());
+        assert!(cursor.set_none().is_err());
+        assert_eq!(original, buffer);
+
+        // A leaf that's been cut short can't be patched either.
+        let mut truncated = *b"\x12\x01\x02";
+        let mut cursor = CursorMut::new(&mut truncated[..]).unwrap();
+        assert_eq!(Err(CursorError::DocumentTooShort), cursor.set_i64(1));
+    }
+}
@@ -0,0 +1,327 @@
+use crate::packed::split_packed_array;
+use crate::{Cursor, CursorError, ElementTypeCode, OwnedPathSegment, Path, MAP_TYPES};
+use alloc::vec::Vec;
+
+/// A difference between two documents, as found by [`diff`].
+#[derive(Clone, Debug)]
+pub enum Change<'a> {
+    /// A key or an array item that is only present in the new document.
+    Added { path: Path, new: Cursor<&'a [u8]> },
+    /// A key or an array item that is only present in the old document.
+    Removed { path: Path, old: Cursor<&'a [u8]> },
+    /// A node whose value is different in the new document, including its element type.
+    Changed {
+        path: Path,
+        old: Cursor<&'a [u8]>,
+        new: Cursor<&'a [u8]>,
+    },
+}
+
+impl Change<'_> {
+    /// Returns the path of the node that was added, removed or changed.
+    pub fn path(&self) -> &Path {
+        match self {
+            Change::Added { path, .. } | Change::Removed { path, .. } => path,
+            Change::Changed { path, .. } => path,
+        }
+    }
+}
+
+/// Lists the differences between two documents, ordered by their paths.
+///
+/// Maps are compared by their keys regardless of their layout, so an Eytzinger map and a CHD map
+/// holding the same entries are equal. Arrays are compared item by item; removing an item from
+/// the middle of an array shows up as changes to the items following it, and the removal of the
+/// last one.
+///
+/// Subtrees that are stored as the same bytes are skipped without descending into them. A pair
+/// of containers whose children can't be read is reported as changed as a whole.
+pub fn diff<'a, A, B>(old: &'a Cursor<A>, new: &'a Cursor<B>) -> Diff<'a>
+where
+    A: Clone + AsRef<[u8]>,
+    B: Clone + AsRef<[u8]>,
+{
+    Diff {
+        stack: alloc::vec![Pending::Compare {
+            path: Vec::new(),
+            old: old.borrow(),
+            new: new.borrow(),
+        }],
+    }
+}
+
+/// A lazy iterator over the differences between two documents, returned by [`diff`].
+pub struct Diff<'a> {
+    /// The work left to do, with the next item on top.
+    stack: Vec<Pending<'a>>,
+}
+
+enum Pending<'a> {
+    Compare {
+        path: Vec<OwnedPathSegment>,
+        old: Cursor<&'a [u8]>,
+        new: Cursor<&'a [u8]>,
+    },
+    Change(Change<'a>),
+}
+
+impl<'a> Iterator for Diff<'a> {
+    type Item = Change<'a>;
+
+    fn next(&mut self) -> Option<Change<'a>> {
+        loop {
+            match self.stack.pop()? {
+                Pending::Change(change) => return Some(change),
+                Pending::Compare { path, old, new } => {
+                    if let Some(change) = self.compare(path, old, new) {
+                        return Some(change);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<'a> Diff<'a> {
+    /// Compares two nodes, either returning the change between them or scheduling their
+    /// children to be compared.
+    fn compare(
+        &mut self,
+        path: Vec<OwnedPathSegment>,
+        old: Cursor<&'a [u8]>,
+        new: Cursor<&'a [u8]>,
+    ) -> Option<Change<'a>> {
+        if old.scoped_buffer() == new.scoped_buffer() {
+            return None;
+        }
+        let children = match (old.get_element_type(), new.get_element_type()) {
+            (old_type, new_type)
+                if MAP_TYPES.contains(&old_type) && MAP_TYPES.contains(&new_type) =>
+            {
+                map_children(&path, &old, &new).ok()
+            }
+            (ElementTypeCode::Array, ElementTypeCode::Array) => {
+                array_children(&path, &old, &new).ok()
+            }
+            // The padding before the items depends on where the array is stored.
+            (ElementTypeCode::PackedArray, ElementTypeCode::PackedArray) => {
+                match (
+                    split_packed_array(old.payload_scoped_buffer()),
+                    split_packed_array(new.payload_scoped_buffer()),
+                ) {
+                    (Ok(old_items), Ok(new_items)) if old_items == new_items => Some(Vec::new()),
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        match children {
+            Some(children) => {
+                // Push the children in reverse, so the first one is popped first.
+                self.stack.extend(children.into_iter().rev());
+                None
+            }
+            None => Some(Change::Changed {
+                path: path.into(),
+                old,
+                new,
+            }),
+        }
+    }
+}
+
+/// Returns the work of comparing the entries of two maps, ordered by their keys.
+fn map_children<'a>(
+    path: &[OwnedPathSegment],
+    old: &Cursor<&'a [u8]>,
+    new: &Cursor<&'a [u8]>,
+) -> Result<Vec<Pending<'a>>, CursorError> {
+    let old_entries = sorted_entries(old)?;
+    let new_entries = sorted_entries(new)?;
+    let child_path = |key: &str| {
+        let mut path = path.to_vec();
+        path.push(OwnedPathSegment::Key(key.into()));
+        path
+    };
+
+    let mut children = Vec::new();
+    let mut old_entries = old_entries.into_iter().peekable();
+    let mut new_entries = new_entries.into_iter().peekable();
+    loop {
+        let child = match (old_entries.peek(), new_entries.peek()) {
+            (Some((old_key, _)), Some((new_key, _))) if old_key == new_key => {
+                let (key, old) = old_entries.next().expect("peeked");
+                let (_, new) = new_entries.next().expect("peeked");
+                Pending::Compare {
+                    path: child_path(key),
+                    old,
+                    new,
+                }
+            }
+            (Some((old_key, _)), Some((new_key, _))) if new_key < old_key => {
+                let (key, new) = new_entries.next().expect("peeked");
+                Pending::Change(Change::Added {
+                    path: child_path(key).into(),
+                    new,
+                })
+            }
+            (Some(_), _) => {
+                let (key, old) = old_entries.next().expect("peeked");
+                Pending::Change(Change::Removed {
+                    path: child_path(key).into(),
+                    old,
+                })
+            }
+            (None, Some(_)) => {
+                let (key, new) = new_entries.next().expect("peeked");
+                Pending::Change(Change::Added {
+                    path: child_path(key).into(),
+                    new,
+                })
+            }
+            (None, None) => return Ok(children),
+        };
+        children.push(child);
+    }
+}
+
+/// The entries of a map, ordered by their keys.
+type Entries<'a> = Vec<(&'a str, Cursor<&'a [u8]>)>;
+
+fn sorted_entries<'a>(map: &Cursor<&'a [u8]>) -> Result<Entries<'a>, CursorError> {
+    map.sorted_map_indices(|_key| false)?
+        .map(|index| {
+            let key = map.get_storage_key_by_index(index)?;
+            Ok((key, map.get_value_by_index(index)?))
+        })
+        .collect()
+}
+
+/// Returns the work of comparing the items of two arrays, by their position.
+fn array_children<'a>(
+    path: &[OwnedPathSegment],
+    old: &Cursor<&'a [u8]>,
+    new: &Cursor<&'a [u8]>,
+) -> Result<Vec<Pending<'a>>, CursorError> {
+    let (old_len, new_len) = (old.get_children_count(), new.get_children_count());
+    (0..old_len.max(new_len))
+        .map(|index| {
+            let mut path = path.to_vec();
+            path.push(OwnedPathSegment::Index(index));
+            Ok(if index >= new_len {
+                Pending::Change(Change::Removed {
+                    path: path.into(),
+                    old: old.get_value_by_index(index)?,
+                })
+            } else if index >= old_len {
+                Pending::Change(Change::Added {
+                    path: path.into(),
+                    new: new.get_value_by_index(index)?,
+                })
+            } else {
+                Pending::Compare {
+                    path,
+                    old: old.get_value_by_index(index)?,
+                    new: new.get_value_by_index(index)?,
+                }
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::{serialize, DOC, DOC_PHF};
+    use crate::serializer::Packed;
+    use crate::PathSegment;
+    use alloc::string::{String, ToString};
+    use std::collections::BTreeMap;
+
+    /// Summarizes each change as its kind and path.
+    fn changes(old: &[u8], new: &[u8]) -> Vec<String> {
+        let old = Cursor::new(old).unwrap();
+        let new = Cursor::new(new).unwrap();
+        diff(&old, &new)
+            .map(|change| {
+                let kind = match change {
+                    Change::Added { .. } => "+",
+                    Change::Removed { .. } => "-",
+                    Change::Changed { .. } => "~",
+                };
+                alloc::format!("{kind}{}", change.path())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical_documents() {
+        assert!(changes(DOC, DOC).is_empty());
+        // The same entries, stored as Eytzinger maps in one document and CHD maps in the other.
+        assert_ne!(DOC, DOC_PHF);
+        assert!(changes(DOC, DOC_PHF).is_empty());
+        assert!(changes(DOC_PHF, DOC).is_empty());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = serialize(&BTreeMap::from([
+            ("kept", (1i32, "a", (true, ()))),
+            ("changed", (1i32, "a", (true, ()))),
+            ("removed", (1i32, "a", (true, ()))),
+        ]));
+        let new = serialize(&BTreeMap::from([
+            ("kept", (1i32, "a", (true, ()))),
+            ("changed", (1i32, "b", (false, ()))),
+            ("added", (1i32, "a", (true, ()))),
+        ]));
+        assert_eq!(
+            ["+/added", "~/changed/1", "~/changed/2/0", "-/removed"],
+            &changes(&old, &new)[..]
+        );
+
+        let cursor = Cursor::new(&old[..]).unwrap();
+        let edited = crate::edit::push(&cursor, [PathSegment::Key("kept")], 2u32).unwrap();
+        let edited = crate::edit::remove_index(
+            &Cursor::new(&edited[..]).unwrap(),
+            [PathSegment::Key("changed")],
+            2,
+        )
+        .unwrap();
+        assert_eq!(["-/changed/2", "+/kept/3"], &changes(&old, &edited)[..]);
+
+        // A node that changes its type is reported once, rather than by its children.
+        let new = serialize(&BTreeMap::from([("kept", 1i64)]));
+        let old = serialize(&BTreeMap::from([("kept", 1i32)]));
+        let old = Cursor::new(&old[..]).unwrap();
+        let new = Cursor::new(&new[..]).unwrap();
+        let all: Vec<_> = diff(&old, &new).collect();
+        assert_eq!(1, all.len());
+        match &all[0] {
+            Change::Changed { path, old, new } => {
+                assert_eq!("/kept", path.to_string());
+                assert_eq!(Ok(1), old.get_i32());
+                assert_eq!(Ok(1), new.get_i64());
+            }
+            change => panic!("unexpected change {change:?}"),
+        }
+        assert_eq!(["~"], &changes(b"\x0a", &serialize(&(1i32,)))[..]);
+    }
+
+    #[test]
+    fn test_diff_packed_arrays() {
+        let items = Packed(&[1u64, 2, 3][..]);
+        // The items are padded differently depending on what comes before them.
+        let old = serialize(&(items,));
+        let new = serialize(&((), items));
+        let old = Cursor::new(&old[..]).unwrap();
+        let new = Cursor::new(&new[..]).unwrap();
+        let old = old.get_value_by_index(0).unwrap();
+        let new = new.get_value_by_index(1).unwrap();
+        assert_ne!(old.scoped_buffer(), new.scoped_buffer());
+        assert_eq!(0, diff(&old, &new).count());
+
+        let changed = serialize(&(Packed(&[1u64, 2, 4][..]),));
+        assert_eq!(["~/0"], &changes(&serialize(&(items,)), &changed)[..]);
+    }
+}
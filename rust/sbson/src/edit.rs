@@ -0,0 +1,449 @@
+//! Produces modified copies of documents, without decoding and re-encoding all of them.
+//!
+//! Each edit is applied to the node at the end of a path. Only the containers along that path
+//! are re-encoded; every other subtree is copied over byte-for-byte, and the whole document is
+//! written out in a single pass. Maps keep their layout, so an Eytzinger map stays one and a CHD
+//! map gets a new perfect hash for its new set of keys.
+//!
+//! Copied subtrees that move by an amount that would leave the items of their packed arrays
+//! unaligned are the exception: those packed arrays are re-padded, and the containers leading
+//! to them re-encoded, so `PackedArray::as_slice` keeps working on the edited document.
+
+use crate::serializer::{
+    layout_options, serialize_array, serialize_map, Output, SerializationOptions, Serialize, Stored,
+};
+use crate::{Cursor, CursorError, ElementTypeCode, GotoError, PathSegment, MAP_TYPES};
+
+#[derive(Debug)]
+pub enum EditError {
+    /// The path doesn't lead to a node.
+    Goto(GotoError),
+
+    /// The node at the end of the path isn't of the kind the edit expects, or is malformed.
+    Cursor(CursorError),
+
+    /// `insert_key` was given a key that is already in the map.
+    KeyExists(String),
+
+    /// `remove_key` was given a key that isn't in the map.
+    KeyNotFound(String),
+
+    /// `remove_index` was given an index past the end of the array.
+    IndexOutOfBounds { index: usize, len: usize },
+
+    /// The new value, or one of the re-encoded containers, couldn't be serialized.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for EditError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EditError::Goto(err) => write!(f, "{err}"),
+            EditError::Cursor(_) => {
+                write!(f, "the edited node is of the wrong type, or is malformed")
+            }
+            EditError::KeyExists(key) => write!(f, "key {key:?} already exists"),
+            EditError::KeyNotFound(key) => write!(f, "key {key:?} doesn't exist"),
+            EditError::IndexOutOfBounds { index, len } => write!(
+                f,
+                "index {index} is out of bounds for an array of {len} items"
+            ),
+            EditError::Io(_) => write!(f, "failed to serialize the edited document"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EditError::Goto(err) => err.source(),
+            EditError::Cursor(err) => Some(err),
+            EditError::KeyExists(_)
+            | EditError::KeyNotFound(_)
+            | EditError::IndexOutOfBounds { .. } => None,
+            EditError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<GotoError> for EditError {
+    fn from(err: GotoError) -> Self {
+        EditError::Goto(err)
+    }
+}
+
+impl From<CursorError> for EditError {
+    fn from(err: CursorError) -> Self {
+        EditError::Cursor(err)
+    }
+}
+
+impl From<std::io::Error> for EditError {
+    fn from(err: std::io::Error) -> Self {
+        EditError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, EditError>;
+
+/// Returns a copy of the document with the node at `path` replaced by `value`.
+pub fn replace<'a, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    path: impl IntoIterator<Item = PathSegment<'a>>,
+    value: impl Serialize,
+) -> Result<Vec<u8>> {
+    edit(cursor.borrow(), path, |_node| Ok(Node::Value(&value)))
+}
+
+/// Returns a copy of the document with `key` added to the map at `path`.
+///
+/// Fails with `KeyExists` if the key is already in the map; use `replace` to overwrite it.
+pub fn insert_key<'a, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    path: impl IntoIterator<Item = PathSegment<'a>>,
+    key: &str,
+    value: impl Serialize,
+) -> Result<Vec<u8>> {
+    edit(cursor.borrow(), path, |node| {
+        let mut entries = map_entries(node)?;
+        if node.get_value_by_key(key).is_ok() {
+            return Err(EditError::KeyExists(key.into()));
+        }
+        entries.push((key, Node::Value(&value)));
+        Ok(Node::Map(node.clone(), entries))
+    })
+}
+
+/// Returns a copy of the document with `key` removed from the map at `path`.
+pub fn remove_key<'a, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    path: impl IntoIterator<Item = PathSegment<'a>>,
+    key: &str,
+) -> Result<Vec<u8>> {
+    edit(cursor.borrow(), path, |node| {
+        let mut entries = map_entries(node)?;
+        let (index, _value) = node
+            .get_value_and_index_by_key(key)
+            .map_err(|err| match err {
+                CursorError::KeyNotFound => EditError::KeyNotFound(key.into()),
+                err => err.into(),
+            })?;
+        entries.remove(index);
+        Ok(Node::Map(node.clone(), entries))
+    })
+}
+
+/// Returns a copy of the document with `value` appended to the array at `path`.
+pub fn push<'a, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    path: impl IntoIterator<Item = PathSegment<'a>>,
+    value: impl Serialize,
+) -> Result<Vec<u8>> {
+    edit(cursor.borrow(), path, |node| {
+        let mut items = array_items(node)?;
+        items.push(Node::Value(&value));
+        Ok(Node::Array(items))
+    })
+}
+
+/// Returns a copy of the document with the item at `index` removed from the array at `path`.
+pub fn remove_index<'a, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+    path: impl IntoIterator<Item = PathSegment<'a>>,
+    index: usize,
+) -> Result<Vec<u8>> {
+    edit(cursor.borrow(), path, |node| {
+        let mut items = array_items(node)?;
+        if index >= items.len() {
+            return Err(EditError::IndexOutOfBounds {
+                index,
+                len: items.len(),
+            });
+        }
+        items.remove(index);
+        Ok(Node::Array(items))
+    })
+}
+
+/// A node of a document that is assembled out of parts of other documents.
+///
+/// The whole document is described before any of it is written, so it can then be
+/// serialized in a single pass into one output.
+pub(crate) enum Node<'a> {
+    /// A node copied from an existing document.
+    Stored(Cursor<&'a [u8]>),
+    /// A new value, serialized with the default options.
+    Value(&'a dyn Serialize),
+    /// A map with the same layout as the given map node.
+    Map(Cursor<&'a [u8]>, Vec<(&'a str, Node<'a>)>),
+    Array(Vec<Node<'a>>),
+}
+
+impl Serialize for Node<'_> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        match self {
+            Node::Stored(node) => Stored::new(node.clone()).serialize(options, output),
+            Node::Value(value) => value.serialize(options, output),
+            Node::Map(layout, entries) => {
+                // The layout options only apply to this map, and not to the values inside it.
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (*key, WithOptions(value, options)));
+                serialize_map(entries.len(), entries, &layout_options(layout), output)
+            }
+            Node::Array(items) => serialize_array(items.iter(), options, output),
+        }
+    }
+}
+
+/// Serializes a value with the given options, rather than those of its container.
+struct WithOptions<'a, T>(&'a T, &'a SerializationOptions);
+
+impl<T: Serialize> Serialize for WithOptions<'_, T> {
+    fn serialize(
+        &self,
+        _options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        self.0.serialize(self.1, output)
+    }
+}
+
+/// Applies `edit` to the node at the end of `path`, and re-encodes every container leading to it.
+fn edit<'a, 'p>(
+    root: Cursor<&'a [u8]>,
+    path: impl IntoIterator<Item = PathSegment<'p>>,
+    edit: impl FnOnce(&Cursor<&'a [u8]>) -> Result<Node<'a>>,
+) -> Result<Vec<u8>> {
+    let path: Vec<PathSegment> = path.into_iter().collect();
+    let mut nodes = vec![root];
+    for (depth, segment) in path.iter().enumerate() {
+        let child = nodes[depth]
+            .goto(core::iter::once(*segment))
+            .map_err(|err| GotoError {
+                path: path[..depth]
+                    .iter()
+                    .map(|&segment| segment.into())
+                    .collect::<Vec<_>>()
+                    .into(),
+                ..err
+            })?;
+        nodes.push(child);
+    }
+
+    let (target, ancestors) = nodes.split_last().expect("the root is always there");
+    let mut node = edit(target)?;
+    for (parent, child) in ancestors.iter().zip(&nodes[1..]).rev() {
+        node = replace_child(parent, child, node)?;
+    }
+
+    let mut output = vec![];
+    node.serialize(&SerializationOptions::default(), &mut output)?;
+    Ok(output)
+}
+
+/// Describes `parent` with `child` replaced by `node`, and every other child copied.
+fn replace_child<'a>(
+    parent: &Cursor<&'a [u8]>,
+    child: &Cursor<&'a [u8]>,
+    node: Node<'a>,
+) -> Result<Node<'a>> {
+    let is_child =
+        |stored: &Node| matches!(stored, Node::Stored(stored) if stored.range == child.range);
+    if MAP_TYPES.contains(&parent.get_element_type()) {
+        let mut entries = map_entries(parent)?;
+        if let Some(entry) = entries.iter_mut().find(|(_key, value)| is_child(value)) {
+            entry.1 = node;
+        }
+        Ok(Node::Map(parent.clone(), entries))
+    } else {
+        let mut items = array_items(parent)?;
+        if let Some(item) = items.iter_mut().find(|item| is_child(item)) {
+            *item = node;
+        }
+        Ok(Node::Array(items))
+    }
+}
+
+/// Returns the entries of a map node in storage order, copying their values as they are.
+fn map_entries<'a>(node: &Cursor<&'a [u8]>) -> Result<Vec<(&'a str, Node<'a>)>> {
+    let actual = node.get_element_type();
+    if !MAP_TYPES.contains(&actual) {
+        return Err(CursorError::WrongElementType {
+            expected: MAP_TYPES,
+            actual,
+        }
+        .into());
+    }
+    (0..node.get_children_count())
+        .map(|index| {
+            let key = node.get_storage_key_by_index(index)?;
+            let value = node.get_value_by_index(index)?;
+            Ok((key, Node::Stored(value)))
+        })
+        .collect()
+}
+
+fn array_items<'a>(node: &Cursor<&'a [u8]>) -> Result<Vec<Node<'a>>> {
+    node.raw_cursor
+        .ensure_element_type(ElementTypeCode::Array)?;
+    (0..node.get_children_count())
+        .map(|index| Ok(Node::Stored(node.get_value_by_index(index)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::{DOC, DOC_PHF};
+    use crate::Value;
+
+    fn key(key: &str) -> PathSegment<'_> {
+        PathSegment::Key(key)
+    }
+
+    #[test]
+    fn test_edits() {
+        for doc in [DOC, DOC_PHF] {
+            let cursor = Cursor::new(doc).unwrap();
+            let original = Value::try_from(&cursor).unwrap();
+            let check = |edited: Vec<u8>, edit: &dyn Fn(&mut Value)| {
+                let mut expected = original.clone();
+                edit(&mut expected);
+                let edited = Cursor::new(&edited[..]).unwrap();
+                assert!(edited.validate().is_ok());
+                assert_eq!(cursor.get_element_type(), edited.get_element_type());
+                assert_eq!(expected, edited);
+            };
+            check(
+                replace(&cursor, [key("FLORP"), key("X")], "replaced").unwrap(),
+                &|value| value["FLORP"]["X"] = Value::String("replaced".into()),
+            );
+            check(
+                insert_key(&cursor, [key("FLORP")], "Y", -1i32).unwrap(),
+                &|value| value["FLORP"]["Y"] = Value::Int32(-1),
+            );
+            check(remove_key(&cursor, [], "3").unwrap(), &|value| {
+                if let Value::Map(entries) = value {
+                    entries.remove("3");
+                }
+            });
+            check(
+                remove_key(&cursor, [key("FLORP")], "X").unwrap(),
+                &|value| value["FLORP"] = Value::Map(Default::default()),
+            );
+            check(push(&cursor, [key("BLARG")], 3u64).unwrap(), &|value| {
+                value["BLARG"] = Value::Array(vec![
+                    Value::Int64(1),
+                    Value::Int64(2),
+                    Value::Bool(true),
+                    Value::Bool(false),
+                    Value::Null,
+                    Value::UInt64(3),
+                ])
+            });
+            check(
+                remove_index(&cursor, [key("BLARG")], 0).unwrap(),
+                &|value| {
+                    value["BLARG"] = Value::Array(vec![
+                        Value::Int64(2),
+                        Value::Bool(true),
+                        Value::Bool(false),
+                        Value::Null,
+                    ])
+                },
+            );
+
+            let edited = replace(&cursor, [], Value::Null).unwrap();
+            assert_eq!(b"\x0a", &edited[..]);
+        }
+    }
+
+    #[test]
+    fn test_untouched_subtrees_are_copied() {
+        let cursor = Cursor::new(DOC).unwrap();
+        let edited = replace(&cursor, [key("BLARG"), PathSegment::Index(1)], 7i32).unwrap();
+        let edited = Cursor::new(&edited[..]).unwrap();
+        for sibling in [
+            "3",
+            "FLORP",
+            "help me i'm trapped in a format factory help me before they",
+        ] {
+            assert_eq!(
+                cursor.get_value_by_key(sibling).unwrap().scoped_buffer(),
+                edited.get_value_by_key(sibling).unwrap().scoped_buffer()
+            );
+        }
+        let blarg = edited.get_value_by_key("BLARG").unwrap();
+        assert_eq!(Ok(7), blarg.get_value_by_index(1).unwrap().get_i32());
+        assert_eq!(
+            cursor
+                .goto([key("BLARG"), PathSegment::Index(2)].into_iter())
+                .unwrap()
+                .scoped_buffer(),
+            blarg.get_value_by_index(2).unwrap().scoped_buffer()
+        );
+    }
+
+    #[test]
+    fn test_index_into_map() {
+        for doc in [DOC, DOC_PHF] {
+            let cursor = Cursor::new(doc).unwrap();
+            let index = (0..cursor.get_children_count())
+                .find(|index| cursor.get_key_by_index(*index) == Ok("FLORP"))
+                .unwrap();
+            let by_index = replace(&cursor, [PathSegment::Index(index), key("X")], 1i32).unwrap();
+            let by_key = replace(&cursor, [key("FLORP"), key("X")], 1i32).unwrap();
+            assert_eq!(by_key, by_index);
+        }
+    }
+
+    #[test]
+    fn test_edit_errors() {
+        let cursor = Cursor::new(DOC).unwrap();
+        assert!(matches!(
+            replace(&cursor, [key("missing"), key("X")], 1i32),
+            Err(EditError::Goto(err)) if err.path.segments().is_empty()
+        ));
+        assert!(matches!(
+            replace(&cursor, [key("FLORP"), key("Y"), key("Z")], 1i32),
+            Err(EditError::Goto(err)) if err.path.to_string() == "/FLORP" && err.kind == CursorError::KeyNotFound
+        ));
+        assert!(matches!(
+            insert_key(&cursor, [key("FLORP")], "X", 1i32),
+            Err(EditError::KeyExists(key)) if key == "X"
+        ));
+        assert!(matches!(
+            insert_key(&cursor, [key("BLARG")], "X", 1i32),
+            Err(EditError::Cursor(CursorError::WrongElementType { .. }))
+        ));
+        assert!(matches!(
+            remove_key(&cursor, [key("FLORP")], "Y"),
+            Err(EditError::KeyNotFound(key)) if key == "Y"
+        ));
+        assert!(matches!(
+            push(&cursor, [key("FLORP")], 1i32),
+            Err(EditError::Cursor(CursorError::WrongElementType { .. }))
+        ));
+        assert!(matches!(
+            remove_index(&cursor, [key("BLARG")], 5),
+            Err(EditError::IndexOutOfBounds { index: 5, len: 5 })
+        ));
+        assert_eq!(
+            "index 5 is out of bounds for an array of 5 items",
+            remove_index(&cursor, [key("BLARG")], 5)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "key \"Y\" doesn't exist",
+            remove_key(&cursor, [key("FLORP")], "Y")
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::serde::{DeserializationOptions, Deserializer};
+use crate::{Cursor, CursorError};
+use core::marker::PhantomData;
+use serde::de::value::{BoolDeserializer, BorrowedBytesDeserializer};
+use serde::de::{Deserialize, DeserializeSeed, SeqAccess, Visitor};
+
+/// The newtype-struct name through which `Lazy` asks the SBSON deserializer for the raw node.
+pub(crate) const LAZY_TOKEN: &str = "$sbson::private::Lazy";
+
+/// A subtree whose deserialization is deferred until it is actually needed.
+///
+/// When a `Lazy<T>` field appears in a `#[derive(Deserialize)]` struct, deserializing the
+/// struct only captures a cursor to the field's node. The node is deserialized into a `T`
+/// every time [`Lazy::get`] is called, with the same `DeserializationOptions` as the struct.
+///
+/// `Lazy` can only be deserialized by SBSON's own deserializer, which hands it the node through a
+/// private handshake, and not through serde's internal buffering (e.g. inside `#[serde(flatten)]` structs or internally tagged enums).
+pub struct Lazy<'de, T> {
+    cursor: Cursor<&'de [u8]>,
+    options: DeserializationOptions,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<'de, T> Lazy<'de, T> {
+    /// Returns a cursor to the captured node.
+    pub fn cursor(&self) -> &Cursor<&'de [u8]> {
+        &self.cursor
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Lazy<'de, T> {
+    /// Deserializes the captured node.
+    pub fn get(&self) -> Result<T, CursorError> {
+        let deserializer = Deserializer::from_cursor(self.cursor.clone());
+        T::deserialize(&mut deserializer.with_options(self.options.clone()))
+    }
+}
+
+impl<T> Clone for Lazy<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            cursor: self.cursor.clone(),
+            options: self.options.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T> core::fmt::Debug for Lazy<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Lazy").field(&self.cursor).finish()
+    }
+}
+
+impl<'de: 'a, 'a, T> Deserialize<'de> for Lazy<'a, T> {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        struct LazyVisitor<'a, T>(PhantomData<fn() -> Lazy<'a, T>>);
+
+        impl<'de: 'a, 'a, T> Visitor<'de> for LazyVisitor<'a, T> {
+            type Value = Lazy<'a, T>;
+
+            fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+                formatter.write_str("an SBSON node")
+            }
+
+            fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+            where
+                A: SeqAccess<'de>,
+            {
+                use serde::de::{Error, Unexpected};
+
+                let handshake: &'de [u8] = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(0, &self))?;
+                if !core::ptr::eq(handshake, core::slice::from_ref(&HANDSHAKE)) {
+                    return Err(A::Error::invalid_value(Unexpected::Bytes(handshake), &self));
+                }
+                let node: &'de [u8] = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(1, &self))?;
+                let lookup_struct_fields = seq
+                    .next_element()?
+                    .ok_or_else(|| A::Error::invalid_length(2, &self))?;
+                Ok(Lazy {
+                    cursor: Cursor::new(node).map_err(A::Error::custom)?,
+                    options: DeserializationOptions {
+                        lookup_struct_fields,
+                    },
+                    marker: PhantomData,
+                })
+            }
+        }
+
+        deserializer.deserialize_newtype_struct(LAZY_TOKEN, LazyVisitor(PhantomData))
+    }
+}
+
+/// Only the SBSON deserializer can hand out a reference to this static, so its address proves
+/// that a handshake wasn't forged by another deserializer.
+static HANDSHAKE: u8 = 0;
+
+/// What the SBSON deserializer hands over to `Lazy`: a reference to `HANDSHAKE`, the raw node,
+/// and the options it is deserializing with.
+pub(crate) struct Handshake<'de> {
+    node: &'de [u8],
+    options: DeserializationOptions,
+    position: usize,
+}
+
+impl<'de> Handshake<'de> {
+    pub(crate) fn new(node: &'de [u8], options: &DeserializationOptions) -> Self {
+        Self {
+            node,
+            options: options.clone(),
+            position: 0,
+        }
+    }
+}
+
+impl<'de> SeqAccess<'de> for Handshake<'de> {
+    type Error = CursorError;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, CursorError>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        self.position += 1;
+        match self.position {
+            1 => seed.deserialize(BorrowedBytesDeserializer::new(core::slice::from_ref(
+                &HANDSHAKE,
+            ))),
+            2 => seed.deserialize(BorrowedBytesDeserializer::new(self.node)),
+            3 => seed.deserialize(BoolDeserializer::new(self.options.lookup_struct_fields)),
+            _ => return Ok(None),
+        }
+        .map(Some)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, to_vec};
+    use std::collections::HashMap;
+
+    #[derive(serde::Serialize)]
+    struct Config {
+        name: String,
+        huge: HashMap<String, Vec<i32>>,
+    }
+
+    #[derive(serde::Deserialize, Debug)]
+    struct LazyConfig<'a> {
+        name: &'a str,
+        #[serde(borrow)]
+        huge: Lazy<'a, HashMap<String, Vec<i32>>>,
+    }
+
+    #[test]
+    fn test_lazy() {
+        let huge: HashMap<_, _> = (0..100).map(|i| (format!("k{i}"), vec![i; 3])).collect();
+        let buffer = to_vec(&Config {
+            name: "config".into(),
+            huge: huge.clone(),
+        })
+        .unwrap();
+
+        let config: LazyConfig = from_bytes(&buffer).unwrap();
+        assert_eq!("config", config.name);
+        assert_eq!(100, config.huge.cursor().get_children_count());
+        assert_eq!(
+            Ok(3),
+            config
+                .huge
+                .cursor()
+                .get_value_by_key("k5")
+                .map(|cursor| cursor.get_children_count())
+        );
+        assert_eq!(Ok(huge), config.huge.get());
+
+        // A mismatching type is only noticed once the node is deserialized.
+        let wrong: Lazy<i32> = from_bytes(&buffer).unwrap();
+        assert!(wrong.get().is_err());
+    }
+
+    #[test]
+    fn test_lazy_keeps_options() {
+        #[derive(serde::Serialize)]
+        struct Outer {
+            inner: HashMap<&'static str, i32>,
+        }
+
+        #[derive(serde::Deserialize, PartialEq, Debug)]
+        #[serde(deny_unknown_fields)]
+        struct Inner {
+            a: i32,
+        }
+
+        #[derive(serde::Deserialize)]
+        struct LazyOuter<'a> {
+            #[serde(borrow)]
+            inner: Lazy<'a, Inner>,
+        }
+
+        let buffer = to_vec(&Outer {
+            inner: HashMap::from([("a", 1), ("unknown", 2)]),
+        })
+        .unwrap();
+        // Looking fields up by key never visits the unknown one.
+        let options = DeserializationOptions {
+            lookup_struct_fields: true,
+        };
+        let outer: LazyOuter = crate::from_bytes_with_options(&buffer, &options).unwrap();
+        assert_eq!(Ok(Inner { a: 1 }), outer.inner.get());
+        let outer: LazyOuter = from_bytes(&buffer).unwrap();
+        assert!(outer.inner.get().is_err());
+    }
+
+    /// A deserializer that answers `Lazy` with look-alike data.
+    struct Forger;
+
+    impl<'de> serde::Deserializer<'de> for Forger {
+        type Error = serde::de::value::Error;
+
+        fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Self::Error> {
+            visitor.visit_seq(Forged(0))
+        }
+
+        serde::forward_to_deserialize_any! {
+            bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf
+            option unit unit_struct newtype_struct seq tuple tuple_struct map struct enum
+            identifier ignored_any
+        }
+    }
+
+    struct Forged(usize);
+
+    impl<'de> SeqAccess<'de> for Forged {
+        type Error = serde::de::value::Error;
+
+        fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, Self::Error>
+        where
+            S: DeserializeSeed<'de>,
+        {
+            self.0 += 1;
+            match self.0 {
+                1 => seed.deserialize(BorrowedBytesDeserializer::new(&b"\0"[..])),
+                2 => seed.deserialize(BorrowedBytesDeserializer::new(&b"\x10\x2a\0\0\0"[..])),
+                3 => seed.deserialize(BoolDeserializer::new(false)),
+                _ => return Ok(None),
+            }
+            .map(Some)
+        }
+    }
+
+    #[test]
+    fn test_lazy_from_other_deserializer() {
+        assert!(serde_json::from_str::<Lazy<i32>>("1").is_err());
+        assert!(Lazy::<i32>::deserialize(Forger).is_err());
+    }
+}
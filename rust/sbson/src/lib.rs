@@ -18,21 +18,81 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
+//! Reading SBSON documents only requires `core`, so the crate can be used on `no_std` targets
+//! by disabling its default features. The `alloc` feature adds paths, selectors, validation,
+//! owned values and errors describing where they occurred, while the serializer requires `std`.
+//!
+//! Numeric slices are only written as packed arrays when they're wrapped in
+//! [`serializer::Packed`]. Plain slices, vectors and serde sequences of numbers are always
+//! written as regular arrays, since both `Serialize` traits see their items one at a time, and
+//! readers that index into such arrays keep working. Packed arrays deserialize into any sequence,
+//! array or tuple of numbers.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
 extern crate core;
 
 mod raw_cursor;
 
 mod cursor;
+mod cursor_mut;
+#[cfg(feature = "alloc")]
+mod diff;
+#[cfg(feature = "std")]
+mod merge;
+#[cfg(feature = "mmap")]
+mod mmap;
+#[cfg(feature = "alloc")]
+mod overlay;
+mod packed;
+#[cfg(feature = "alloc")]
+mod path;
 #[cfg(feature = "pyo3")]
 mod pyo3;
+#[cfg(feature = "alloc")]
+mod selector;
+#[cfg(feature = "alloc")]
+mod validate;
+#[cfg(feature = "alloc")]
+mod value;
 pub use cursor::Cursor;
+pub use cursor_mut::CursorMut;
+#[cfg(feature = "alloc")]
+pub use diff::{diff, Change, Diff};
+#[cfg(feature = "std")]
+pub use merge::{merge, ArrayMerge, MergeError, MergePolicy};
+#[cfg(feature = "mmap")]
+pub use mmap::{MmapBuffer, OpenError};
+#[cfg(feature = "alloc")]
+pub use overlay::OverlayCursor;
+pub use packed::{PackedArray, PackedItem, PackedIter};
+#[cfg(feature = "alloc")]
+pub use path::{Path, PathError};
+#[cfg(feature = "alloc")]
+pub use selector::{Selection, Selector};
+#[cfg(feature = "alloc")]
+pub use validate::{ValidationError, ValidationErrorKind};
+#[cfg(feature = "alloc")]
+pub use value::{PackedValues, Value};
+#[cfg(feature = "std")]
+pub mod edit;
+#[cfg(feature = "serde")]
+mod lazy;
 #[cfg(feature = "serde")]
 mod serde;
 #[cfg(feature = "std")]
 pub mod serializer;
+#[cfg(feature = "serde")]
+mod to_json;
 
 #[cfg(feature = "serde")]
-pub use crate::serde::from_bytes;
+pub use crate::lazy::Lazy;
+#[cfg(feature = "serde")]
+pub use crate::serde::{from_bytes, from_bytes_with_options, DeserializationOptions};
+#[cfg(all(feature = "std", feature = "serde"))]
+pub use crate::serializer::{to_vec, to_writer};
 
 #[derive(Copy, Clone, PartialEq, Eq, Debug)]
 #[repr(u8)]
@@ -42,6 +102,8 @@ pub enum ElementTypeCode {
     Map = 0x03,
     Array = 0x04,
     Binary = 0x05,
+    /// An array of numbers of a single type, stored back to back.
+    PackedArray = 0x06,
     False = 0x08,
     True = 0x09,
     None = 0x0A,
@@ -52,6 +114,28 @@ pub enum ElementTypeCode {
     MapCHD = 0x20,
 }
 
+impl ElementTypeCode {
+    /// Returns a single-item slice holding this element type, for use in `WrongElementType`.
+    pub(crate) fn as_slice(self) -> &'static [ElementTypeCode] {
+        match self {
+            ElementTypeCode::Double => &[ElementTypeCode::Double],
+            ElementTypeCode::String => &[ElementTypeCode::String],
+            ElementTypeCode::Map => &[ElementTypeCode::Map],
+            ElementTypeCode::Array => &[ElementTypeCode::Array],
+            ElementTypeCode::Binary => &[ElementTypeCode::Binary],
+            ElementTypeCode::PackedArray => &[ElementTypeCode::PackedArray],
+            ElementTypeCode::False => &[ElementTypeCode::False],
+            ElementTypeCode::True => &[ElementTypeCode::True],
+            ElementTypeCode::None => &[ElementTypeCode::None],
+            ElementTypeCode::Int32 => &[ElementTypeCode::Int32],
+            ElementTypeCode::UInt32 => &[ElementTypeCode::UInt32],
+            ElementTypeCode::Int64 => &[ElementTypeCode::Int64],
+            ElementTypeCode::UInt64 => &[ElementTypeCode::UInt64],
+            ElementTypeCode::MapCHD => &[ElementTypeCode::MapCHD],
+        }
+    }
+}
+
 impl TryFrom<u8> for ElementTypeCode {
     type Error = CursorError;
 
@@ -62,6 +146,7 @@ impl TryFrom<u8> for ElementTypeCode {
             x if x == ElementTypeCode::Map as u8 => ElementTypeCode::Map,
             x if x == ElementTypeCode::Array as u8 => ElementTypeCode::Array,
             x if x == ElementTypeCode::Binary as u8 => ElementTypeCode::Binary,
+            x if x == ElementTypeCode::PackedArray as u8 => ElementTypeCode::PackedArray,
             x if x == ElementTypeCode::False as u8 => ElementTypeCode::False,
             x if x == ElementTypeCode::True as u8 => ElementTypeCode::True,
             x if x == ElementTypeCode::None as u8 => ElementTypeCode::None,
@@ -75,7 +160,12 @@ impl TryFrom<u8> for ElementTypeCode {
     }
 }
 
+/// Errors raised while reading a document.
+///
+/// Some variants only exist with certain features, so matches over this type need a wildcard
+/// arm to keep compiling when another crate in the build enables them.
 #[derive(Clone, PartialEq, Eq, Debug)]
+#[non_exhaustive]
 pub enum CursorError {
     DocumentTooShort,
 
@@ -84,6 +174,8 @@ pub enum CursorError {
 
     /// The user have asked for one element type, but the cursor points to another.
     WrongElementType {
+        /// The element types that would have been accepted.
+        expected: &'static [ElementTypeCode],
         actual: ElementTypeCode,
     },
 
@@ -93,30 +185,160 @@ pub enum CursorError {
     Utf8Error,
 
     EmbeddedOffsetOutOfBounds,
+
+    /// A descriptor describes an empty or inverted range, or one overlapping its parent's headers.
+    MalformedDescriptor,
+
     ItemIndexOutOfBounds,
     KeyNotFound,
+
+    /// The document is nested deeper than the deserializer or conversions are willing to descend.
+    RecursionLimitExceeded,
+
+    /// A free-form error raised by a `serde::Deserialize` implementation.
+    #[cfg(feature = "alloc")]
+    Custom(alloc::string::String),
+}
+
+impl core::fmt::Display for CursorError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            CursorError::DocumentTooShort => write!(f, "document is too short"),
+            CursorError::InvalidElementType(element_type) => {
+                write!(f, "invalid element type {element_type:#04x}")
+            }
+            CursorError::WrongElementType { expected, actual } => {
+                write!(f, "expected ")?;
+                for (index, element_type) in expected.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, " or ")?;
+                    }
+                    write!(f, "{element_type:?}")?;
+                }
+                write!(f, ", found {actual:?}")
+            }
+            CursorError::UnterminatedString => write!(f, "string is missing its null-terminator"),
+            CursorError::Utf8Error => write!(f, "string is not valid UTF-8"),
+            CursorError::EmbeddedOffsetOutOfBounds => write!(f, "embedded offset is out of bounds"),
+            CursorError::MalformedDescriptor => write!(f, "malformed descriptor"),
+            CursorError::ItemIndexOutOfBounds => write!(f, "item index is out of bounds"),
+            CursorError::KeyNotFound => write!(f, "key not found"),
+            CursorError::RecursionLimitExceeded => write!(f, "recursion limit exceeded"),
+            #[cfg(feature = "alloc")]
+            CursorError::Custom(msg) => write!(f, "{msg}"),
+        }
+    }
 }
 
+#[cfg(feature = "std")]
+impl std::error::Error for CursorError {}
+
+pub(crate) const MAP_TYPES: &[ElementTypeCode] = &[ElementTypeCode::Map, ElementTypeCode::MapCHD];
+pub(crate) const CONTAINER_TYPES: &[ElementTypeCode] = &[
+    ElementTypeCode::Map,
+    ElementTypeCode::MapCHD,
+    ElementTypeCode::Array,
+];
+
+/// How deep into nested arrays and maps the deserializer and owned conversions are willing to go.
+///
+/// Each nesting level takes up stack space, so hostile documents could otherwise overflow it.
+#[cfg(feature = "alloc")]
+pub(crate) const RECURSION_LIMIT: usize = 128;
+
+/// Describes where [`Cursor::goto`] failed, along with the path that led there.
+#[cfg(feature = "alloc")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GotoError {
+    /// The segments that were followed successfully before the failure.
+    pub path: Path,
+    /// The segment that couldn't be followed.
+    pub segment: OwnedPathSegment,
+    /// The offset of the node the segment was applied to, from the start of the underlying buffer.
+    pub offset: usize,
+    pub kind: CursorError,
+}
+
+#[cfg(feature = "alloc")]
+impl core::fmt::Display for GotoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.segment {
+            OwnedPathSegment::Key(key) => write!(f, "key {key:?}")?,
+            OwnedPathSegment::Index(index) => write!(f, "index {index}")?,
+        }
+        write!(f, " at offset {} (path: ", self.offset)?;
+        if self.path.segments().is_empty() {
+            write!(f, "/")?;
+        }
+        write!(f, "{}): {}", self.path, self.kind)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for GotoError {}
+
+/// The allocation-free counterpart of [`GotoError`], returned by [`Cursor::goto_raw`].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct RawGotoError {
+    /// The index of the segment that couldn't be followed.
+    pub depth: usize,
+    /// The offset of the node the segment was applied to, from the start of the underlying buffer.
+    pub offset: usize,
+    pub kind: CursorError,
+}
+
+impl core::fmt::Display for RawGotoError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "segment {} at offset {}: {}",
+            self.depth, self.offset, self.kind
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RawGotoError {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum PathSegment<'a> {
     Key(&'a str),
     Index(usize),
 }
 
+/// An owned version of `PathSegment`, used to describe where in a document something happened.
+#[cfg(feature = "alloc")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum OwnedPathSegment {
+    Key(alloc::string::String),
+    Index(usize),
+}
+
+#[cfg(feature = "alloc")]
+impl OwnedPathSegment {
+    pub fn as_path_segment(&self) -> PathSegment<'_> {
+        match self {
+            OwnedPathSegment::Key(key) => PathSegment::Key(key),
+            OwnedPathSegment::Index(index) => PathSegment::Index(*index),
+        }
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl From<PathSegment<'_>> for OwnedPathSegment {
+    fn from(segment: PathSegment<'_>) -> Self {
+        match segment {
+            PathSegment::Key(key) => OwnedPathSegment::Key(key.into()),
+            PathSegment::Index(index) => OwnedPathSegment::Index(index),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    /// This buffer is the serialized representation of:
-    /// ```python
-    /// {
-    ///     '3': b'beep boop',
-    ///     'BLARG': [1, 2, True, False, None],
-    ///     'FLORP': {'X': 255},
-    ///     "help me i'm trapped in a format factory help me before they": '...'
-    /// }
-    /// ```
-    const DOC: &[u8] = include_bytes!("../../../test_vectors/sanity.sbson");
-    const DOC_PHF: &[u8] = include_bytes!("../../../test_vectors/sanity_phf.sbson");
+    use crate::serializer::fixtures::{serialize_with, DOC, DOC_PHF};
+    use crate::serializer::SerializationOptions;
 
     fn test_impl_sanity<T: Clone + AsRef<[u8]>>(cursor: Cursor<T>) {
         assert_eq!(cursor.get_children_count(), 4);
@@ -176,6 +398,228 @@ mod tests {
         test_impl_sanity(cursor);
     }
 
+    #[test]
+    fn test_iter_map_sorted() {
+        use std::collections::BTreeMap;
+
+        let expected = [
+            "3",
+            "BLARG",
+            "FLORP",
+            "help me i'm trapped in a format factory help me before they",
+        ];
+        for doc in [DOC, DOC_PHF] {
+            let cursor = Cursor::new(doc).unwrap();
+            let keys: Vec<&str> = cursor.iter_map_sorted().unwrap().map(|(k, _)| k).collect();
+            assert_eq!(expected[..], keys[..]);
+            let (_, florp) = cursor.iter_map_sorted().unwrap().nth(2).unwrap();
+            assert_eq!(Ok(0xFF), florp.get_value_by_key("X").unwrap().get_i64());
+        }
+
+        // Eytzinger maps of every size up to a few full levels of the tree, and some CHD maps.
+        let layouts = (0..40)
+            .map(|len| (len, usize::MAX))
+            .chain([1, 7, 33].map(|len| (len, 1)));
+        for (len, chd_threshold) in layouts {
+            let map: BTreeMap<String, usize> = (0..len).map(|i| (format!("key{i}"), i)).collect();
+            let buffer = serialize_with(&map, &SerializationOptions { chd_threshold });
+            let cursor = Cursor::new(&buffer[..]).unwrap();
+            let items: Vec<(&str, u64)> = cursor
+                .iter_map_sorted()
+                .unwrap()
+                .map(|(key, value)| (key, value.get_u64().unwrap()))
+                .collect();
+            let expected: Vec<(&str, u64)> =
+                map.iter().map(|(k, v)| (k.as_str(), *v as u64)).collect();
+            assert_eq!(expected, items);
+        }
+
+        let blarg = Cursor::new(DOC).unwrap().get_value_by_key("BLARG").unwrap();
+        assert!(blarg.iter_map_sorted().is_err());
+    }
+
+    #[test]
+    fn test_map_range() {
+        use std::collections::BTreeMap;
+        use std::ops::Bound;
+
+        let map: BTreeMap<String, u32> = (0..300).map(|i| (format!("item_{i:04}"), i)).collect();
+        for chd_threshold in [usize::MAX, 1] {
+            let buffer = serialize_with(&map, &SerializationOptions { chd_threshold });
+            let cursor = Cursor::new(&buffer[..]).unwrap();
+            let keys = |items: &mut dyn Iterator<Item = (&str, Cursor<&[u8]>)>| {
+                items.map(|(key, _)| key.to_owned()).collect::<Vec<_>>()
+            };
+
+            let bounds = [
+                (Bound::Included("item_0100"), Bound::Excluded("item_0200")),
+                (Bound::Excluded("item_0100"), Bound::Included("item_0200")),
+                (Bound::Included("item_0099x"), Bound::Included("item_01")),
+                (Bound::Included("a"), Bound::Excluded("item_0003")),
+                (Bound::Excluded("item_0298"), Bound::Unbounded),
+                (Bound::Included("z"), Bound::Unbounded),
+                (Bound::Unbounded, Bound::Unbounded),
+            ];
+            for range in bounds {
+                let expected: Vec<String> =
+                    map.range::<str, _>(range).map(|(k, _)| k.clone()).collect();
+                assert_eq!(
+                    expected,
+                    keys(&mut cursor.map_range(range).unwrap()),
+                    "{range:?}"
+                );
+            }
+            assert_eq!(
+                keys(&mut cursor.map_range(bounds[0]).unwrap()),
+                keys(&mut cursor.map_range("item_0100".."item_0200").unwrap())
+            );
+
+            let item_01: Vec<String> = (100..200).map(|i| format!("item_{i:04}")).collect();
+            assert_eq!(item_01, keys(&mut cursor.map_prefix("item_01").unwrap()));
+            assert_eq!(300, keys(&mut cursor.map_prefix("").unwrap()).len());
+            assert!(keys(&mut cursor.map_prefix("item_1").unwrap()).is_empty());
+
+            let (key, value) = cursor.map_prefix("item_0123").unwrap().next().unwrap();
+            assert_eq!(("item_0123", Ok(123)), (key, value.get_u32()));
+        }
+    }
+
+    #[test]
+    fn test_goto_errors() {
+        let cursor = Cursor::new(DOC).unwrap();
+        let florp_x = cursor.goto(["FLORP", "X"].map(PathSegment::Key).into_iter());
+        let offset = florp_x.unwrap().range.start;
+
+        let path = [
+            PathSegment::Key("FLORP"),
+            PathSegment::Key("X"),
+            PathSegment::Key("Y"),
+        ];
+        let err = cursor.goto(path.into_iter()).unwrap_err();
+        let kind = CursorError::WrongElementType {
+            expected: &[ElementTypeCode::Map, ElementTypeCode::MapCHD],
+            actual: ElementTypeCode::Int64,
+        };
+        assert_eq!(
+            GotoError {
+                path: vec![
+                    OwnedPathSegment::Key("FLORP".into()),
+                    OwnedPathSegment::Key("X".into())
+                ]
+                .into(),
+                segment: OwnedPathSegment::Key("Y".into()),
+                offset,
+                kind: kind.clone(),
+            },
+            err
+        );
+        assert_eq!(
+            format!("key \"Y\" at offset {offset} (path: /FLORP/X): expected Map or MapCHD, found Int64"),
+            err.to_string()
+        );
+        assert_eq!(
+            Err(RawGotoError {
+                depth: 2,
+                offset,
+                kind
+            }),
+            cursor.goto_raw(path.into_iter()).map(|_| ())
+        );
+
+        let err = cursor
+            .goto([PathSegment::Key("BLARG"), PathSegment::Index(5)].into_iter())
+            .unwrap_err();
+        assert_eq!(OwnedPathSegment::Index(5), err.segment);
+        assert_eq!(CursorError::ItemIndexOutOfBounds, err.kind);
+        assert_eq!(
+            "index 5 at offset 135 (path: /BLARG): item index is out of bounds",
+            err.to_string()
+        );
+
+        // Keys in the path are escaped like in JSON Pointers.
+        let err = GotoError {
+            path: vec![OwnedPathSegment::Key("a/b".into())].into(),
+            ..err
+        };
+        assert_eq!(
+            "index 5 at offset 135 (path: /a~1b): item index is out of bounds",
+            err.to_string()
+        );
+
+        // Segments are recorded as they're followed, so the path can be any iterator.
+        let mut count = 0;
+        let path = std::iter::from_fn(|| {
+            count += 1;
+            (count < 10).then_some(PathSegment::Key("FLORP"))
+        });
+        let err = cursor.goto(path).unwrap_err();
+        assert_eq!(
+            "key \"FLORP\" at offset 111 (path: /FLORP): key not found",
+            err.to_string()
+        );
+    }
+
+    /// Recursively call every accessor on the given cursor, ignoring the results.
+    fn exercise<T: Clone + AsRef<[u8]>>(cursor: &Cursor<T>) {
+        let _ = cursor.get_bool();
+        let _ = cursor.get_none();
+        let _ = cursor.get_i32();
+        let _ = cursor.get_i64();
+        let _ = cursor.get_u32();
+        let _ = cursor.get_u64();
+        let _ = cursor.get_double();
+        let _ = cursor.get_str();
+        let _ = cursor.get_binary();
+        let _ = cursor.validate();
+        for key in ["3", "BLARG", "FLORP", "X", ""] {
+            let _ = cursor.get_value_by_key(key);
+        }
+        let _ = cursor.goto([PathSegment::Key("BLARG"), PathSegment::Index(4)].into_iter());
+
+        let count = cursor.get_children_count();
+        for index in 0..count.min(16) + 1 {
+            let _ = cursor.get_key_by_index(index);
+            if let Ok(child) = cursor.get_value_by_index(index) {
+                exercise(&child);
+            }
+        }
+        if let Ok(items) = cursor.iter_map() {
+            items.for_each(|(_key, child)| exercise(&child));
+        }
+        if let Ok(items) = cursor.iter_map_sorted() {
+            items.for_each(drop);
+        }
+        if let Ok(items) = cursor.map_range("B".."X") {
+            items.for_each(drop);
+        }
+        if let Ok(items) = cursor.iter_array() {
+            items.for_each(|child| exercise(&child));
+        }
+    }
+
+    /// Corrupt every byte of the test vectors in a few ways, and make sure that no accessor panics.
+    #[test]
+    fn test_corrupted_documents_dont_panic() {
+        for doc in [DOC, DOC_PHF] {
+            for length in 0..doc.len() {
+                if let Ok(cursor) = Cursor::new(&doc[..length]) {
+                    exercise(&cursor);
+                }
+            }
+
+            let mut corrupted = doc.to_vec();
+            for offset in 0..doc.len() {
+                for value in [0x00, 0x01, 0x7F, 0x80, 0xFF, doc[offset] ^ 0x04] {
+                    corrupted[offset] = value;
+                    if let Ok(cursor) = Cursor::new(&corrupted[..]) {
+                        exercise(&cursor);
+                    }
+                }
+                corrupted[offset] = doc[offset];
+            }
+        }
+    }
+
     /// Make sure our hand-rolled Python implementation matches that of `phf_shared`. (External crate)
     /// ```python
     /// In [2]: phf.Hashes('florp_blarg', 0xaabbccdd)
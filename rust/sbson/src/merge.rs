@@ -0,0 +1,340 @@
+//! Merges a stack of documents into one, such as a base configuration and its overrides.
+//!
+//! The layers are merged from the bottom up: maps are merged key by key, and any other node
+//! replaces whatever the layers below it had at the same path. A node that only one layer has,
+//! or that no layer above it overrides, is copied over byte-for-byte.
+//!
+//! Like with [`crate::edit`], copied packed arrays are re-padded whenever they'd otherwise end
+//! up unaligned.
+
+use crate::edit::Node;
+use crate::serializer::{SerializationOptions, Serialize};
+use crate::{Cursor, CursorError, ElementTypeCode, MAP_TYPES, RECURSION_LIMIT};
+use std::collections::BTreeMap;
+
+/// How arrays found at the same path in several layers are merged.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub enum ArrayMerge {
+    /// The array of the topmost layer replaces the others.
+    #[default]
+    Replace,
+
+    /// The items of the arrays are concatenated, from the bottom layer up.
+    ///
+    /// Packed arrays are always replaced.
+    Append,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MergePolicy {
+    pub arrays: ArrayMerge,
+
+    /// Whether a `None` value removes its key from the merged map, instead of being kept.
+    ///
+    /// This applies to the values of maps in every layer, but not to the items of arrays.
+    pub none_deletes: bool,
+}
+
+#[derive(Debug)]
+pub enum MergeError {
+    /// One of the layers couldn't be read.
+    Cursor(CursorError),
+
+    /// One of the merged containers couldn't be serialized.
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MergeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MergeError::Cursor(_) => write!(f, "failed to read a layer"),
+            MergeError::Io(_) => write!(f, "failed to serialize the merged document"),
+        }
+    }
+}
+
+impl std::error::Error for MergeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MergeError::Cursor(err) => Some(err),
+            MergeError::Io(err) => Some(err),
+        }
+    }
+}
+
+impl From<CursorError> for MergeError {
+    fn from(err: CursorError) -> Self {
+        MergeError::Cursor(err)
+    }
+}
+
+impl From<std::io::Error> for MergeError {
+    fn from(err: std::io::Error) -> Self {
+        MergeError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, MergeError>;
+
+/// Merges the layers, from the bottom one up, into a new document.
+///
+/// Merged maps take the layout of the map in the topmost layer. An empty list of layers, or a
+/// root that is deleted by `none_deletes`, merges into `None`.
+pub fn merge<T: Clone + AsRef<[u8]>>(layers: &[Cursor<T>], policy: MergePolicy) -> Result<Vec<u8>> {
+    let layers: Vec<Cursor<&[u8]>> = layers.iter().map(Cursor::borrow).collect();
+    let Some(merged) = merge_nodes(&layers, policy, RECURSION_LIMIT)? else {
+        return Ok(vec![ElementTypeCode::None as u8]);
+    };
+    let mut output = vec![];
+    merged.serialize(&SerializationOptions::default(), &mut output)?;
+    Ok(output)
+}
+
+/// Merges the nodes found at the same path in each layer, or returns `None` if the node is
+/// deleted.
+///
+/// Nothing is written yet; the result describes which nodes of the layers to copy, and which
+/// containers to re-encode around them.
+fn merge_nodes<'a>(
+    nodes: &[Cursor<&'a [u8]>],
+    policy: MergePolicy,
+    remaining_depth: usize,
+) -> Result<Option<Node<'a>>> {
+    let Some(top) = nodes.last() else {
+        return Ok(None);
+    };
+    match top.get_element_type() {
+        ElementTypeCode::None if policy.none_deletes => Ok(None),
+        element_type if MAP_TYPES.contains(&element_type) => {
+            let maps = topmost_run(nodes, |node| MAP_TYPES.contains(&node.get_element_type()));
+            merge_maps(maps, policy, remaining_depth)
+        }
+        ElementTypeCode::Array if policy.arrays == ArrayMerge::Append => {
+            let arrays = topmost_run(nodes, |node| {
+                node.get_element_type() == ElementTypeCode::Array
+            });
+            append_arrays(arrays).map(Some)
+        }
+        _ => Ok(Some(Node::Stored(top.clone()))),
+    }
+}
+
+/// Returns the nodes that are merged with the topmost one; anything below them was replaced.
+fn topmost_run<'n, 'a>(
+    nodes: &'n [Cursor<&'a [u8]>],
+    mergeable: impl Fn(&Cursor<&'a [u8]>) -> bool,
+) -> &'n [Cursor<&'a [u8]>] {
+    let start = nodes
+        .iter()
+        .rposition(|node| !mergeable(node))
+        .map_or(0, |index| index + 1);
+    &nodes[start..]
+}
+
+fn merge_maps<'a>(
+    maps: &[Cursor<&'a [u8]>],
+    policy: MergePolicy,
+    remaining_depth: usize,
+) -> Result<Option<Node<'a>>> {
+    let top = maps.last().expect("the topmost node is a map");
+    if maps.len() == 1 && !policy.none_deletes {
+        return Ok(Some(Node::Stored(top.clone())));
+    }
+    let remaining_depth = remaining_depth
+        .checked_sub(1)
+        .ok_or(CursorError::RecursionLimitExceeded)?;
+
+    let mut children: BTreeMap<&str, Vec<Cursor<&[u8]>>> = BTreeMap::new();
+    for map in maps {
+        for index in 0..map.get_children_count() {
+            let key = map.get_storage_key_by_index(index)?;
+            children
+                .entry(key)
+                .or_default()
+                .push(map.get_value_by_index(index)?);
+        }
+    }
+
+    // A map that only one layer has is still copied, unless one of its values was deleted.
+    let mut copied = maps.len() == 1;
+    let mut entries = Vec::with_capacity(children.len());
+    for (key, nodes) in children {
+        match merge_nodes(&nodes, policy, remaining_depth)? {
+            Some(merged) => {
+                copied &= matches!(merged, Node::Stored(_));
+                entries.push((key, merged));
+            }
+            None => copied = false,
+        }
+    }
+    if copied {
+        return Ok(Some(Node::Stored(top.clone())));
+    }
+    Ok(Some(Node::Map(top.clone(), entries)))
+}
+
+fn append_arrays<'a>(arrays: &[Cursor<&'a [u8]>]) -> Result<Node<'a>> {
+    if let [array] = arrays {
+        return Ok(Node::Stored(array.clone()));
+    }
+    let mut items = vec![];
+    for array in arrays {
+        for index in 0..array.get_children_count() {
+            items.push(Node::Stored(array.get_value_by_index(index)?));
+        }
+    }
+    Ok(Node::Array(items))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize_with;
+    use crate::serializer::SerializationOptions;
+    use crate::Value;
+
+    fn map<const N: usize>(entries: [(&str, Value); N]) -> Value {
+        Value::Map(entries.map(|(key, value)| (key.into(), value)).into())
+    }
+
+    fn layers() -> [Value; 3] {
+        let base = map([
+            ("name", Value::String("service".into())),
+            ("port", Value::Int32(80)),
+            (
+                "log",
+                map([
+                    ("level", Value::String("info".into())),
+                    ("file", Value::Null),
+                ]),
+            ),
+            ("hosts", Value::Array(vec![Value::String("a".into())])),
+            ("limits", map([("cpu", Value::Int32(1))])),
+        ]);
+        let env = map([
+            ("port", Value::Int32(8080)),
+            ("log", map([("level", Value::String("debug".into()))])),
+            ("hosts", Value::Array(vec![Value::String("b".into())])),
+            ("limits", Value::Null),
+        ]);
+        let host = map([
+            ("log", map([("file", Value::String("/var/log".into()))])),
+            ("limits", map([("memory", Value::Int32(2))])),
+            ("name", Value::Null),
+        ]);
+        [base, env, host]
+    }
+
+    fn merged(layers: &[Value], chd_threshold: usize, policy: MergePolicy) -> Vec<u8> {
+        let buffers: Vec<_> = layers
+            .iter()
+            .map(|layer| serialize_with(layer, &SerializationOptions { chd_threshold }))
+            .collect();
+        let cursors: Vec<_> = buffers
+            .iter()
+            .map(|buffer| Cursor::new(&buffer[..]).unwrap())
+            .collect();
+        merge(&cursors, policy).unwrap()
+    }
+
+    #[test]
+    fn test_merge() {
+        for chd_threshold in [1, usize::MAX] {
+            let merged = merged(&layers(), chd_threshold, MergePolicy::default());
+            let merged = Cursor::new(&merged[..]).unwrap();
+            assert!(merged.validate().is_ok());
+            let expected = map([
+                ("name", Value::Null),
+                ("port", Value::Int32(8080)),
+                (
+                    "log",
+                    map([
+                        ("level", Value::String("debug".into())),
+                        ("file", Value::String("/var/log".into())),
+                    ]),
+                ),
+                ("hosts", Value::Array(vec![Value::String("b".into())])),
+                // The null in between replaced the base map.
+                ("limits", map([("memory", Value::Int32(2))])),
+            ]);
+            assert_eq!(expected, merged);
+        }
+    }
+
+    #[test]
+    fn test_merge_policy() {
+        let policy = MergePolicy {
+            arrays: ArrayMerge::Append,
+            none_deletes: true,
+        };
+        let merged = merged(&layers(), usize::MAX, policy);
+        let expected = map([
+            ("port", Value::Int32(8080)),
+            (
+                "log",
+                map([
+                    ("level", Value::String("debug".into())),
+                    ("file", Value::String("/var/log".into())),
+                ]),
+            ),
+            (
+                "hosts",
+                Value::Array(vec![Value::String("a".into()), Value::String("b".into())]),
+            ),
+            ("limits", map([("memory", Value::Int32(2))])),
+        ]);
+        assert_eq!(expected, Cursor::new(&merged[..]).unwrap());
+
+        // Nulls are deleted even from maps that no other layer has.
+        let merged = self::merged(&layers()[..1], usize::MAX, policy);
+        let merged = Value::try_from(Cursor::new(&merged[..]).unwrap()).unwrap();
+        assert_eq!(
+            map([("level", Value::String("info".into()))]),
+            merged["log"]
+        );
+
+        assert_eq!(
+            b"\x0a",
+            &self::merged(&[Value::Null], usize::MAX, policy)[..]
+        );
+        assert_eq!(b"\x0a", &self::merged(&[], usize::MAX, policy)[..]);
+    }
+
+    #[test]
+    fn test_merge_copies_subtrees() {
+        let layers = layers();
+        let base = serialize_with(
+            &layers[0],
+            &SerializationOptions {
+                chd_threshold: usize::MAX,
+            },
+        );
+        let env = serialize_with(
+            &layers[1],
+            &SerializationOptions {
+                chd_threshold: usize::MAX,
+            },
+        );
+        let base = Cursor::new(&base[..]).unwrap();
+        let env = Cursor::new(&env[..]).unwrap();
+
+        let merged = merge(std::slice::from_ref(&base), MergePolicy::default()).unwrap();
+        assert_eq!(base.scoped_buffer(), &merged[..]);
+
+        let merged = merge(&[base.clone(), env.clone()], MergePolicy::default()).unwrap();
+        let merged = Cursor::new(&merged[..]).unwrap();
+        for (layer, key) in [(&base, "name"), (&env, "port"), (&env, "hosts")] {
+            assert_eq!(
+                layer.get_value_by_key(key).unwrap().scoped_buffer(),
+                merged.get_value_by_key(key).unwrap().scoped_buffer()
+            );
+        }
+        // The merged map is re-encoded, but not the values inside it.
+        let log = merged.get_value_by_key("log").unwrap();
+        let level = env.query("/log/level").unwrap();
+        assert_eq!(
+            level.scoped_buffer(),
+            log.get_value_by_key("level").unwrap().scoped_buffer()
+        );
+    }
+}
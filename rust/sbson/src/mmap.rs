@@ -0,0 +1,188 @@
+use crate::{Cursor, CursorError, CursorMut};
+use memmap2::{Mmap, MmapMut};
+use std::{
+    fs::{File, OpenOptions},
+    path::Path,
+    sync::Arc,
+};
+
+/// A read-only memory-mapped file, usable as a cursor buffer.
+///
+/// Cloning it only bumps a reference count, so sub-cursors share the same mapping
+/// just like they would with `Arc<[u8]>`.
+#[derive(Clone)]
+pub struct MmapBuffer(Arc<Mmap>);
+
+impl MmapBuffer {
+    /// Maps the file at the given path into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while the mapping is alive,
+    /// otherwise reads from the buffer may observe changing data or fault.
+    /// See [`memmap2::Mmap::map`].
+    pub unsafe fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::from(Mmap::map(&file)?))
+    }
+}
+
+impl From<Mmap> for MmapBuffer {
+    fn from(mmap: Mmap) -> Self {
+        Self(Arc::new(mmap))
+    }
+}
+
+impl AsRef<[u8]> for MmapBuffer {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+/// Errors returned when opening a document from a file.
+#[derive(Debug)]
+pub enum OpenError {
+    Io(std::io::Error),
+    Cursor(CursorError),
+}
+
+impl std::fmt::Display for OpenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OpenError::Io(_) => write!(f, "failed to open document"),
+            OpenError::Cursor(_) => write!(f, "failed to read document"),
+        }
+    }
+}
+
+impl std::error::Error for OpenError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OpenError::Io(e) => Some(e),
+            OpenError::Cursor(e) => Some(e),
+        }
+    }
+}
+
+impl From<std::io::Error> for OpenError {
+    fn from(e: std::io::Error) -> Self {
+        OpenError::Io(e)
+    }
+}
+
+impl From<CursorError> for OpenError {
+    fn from(e: CursorError) -> Self {
+        OpenError::Cursor(e)
+    }
+}
+
+impl Cursor<MmapBuffer> {
+    /// Memory-maps the document at the given path and returns a cursor to its root.
+    ///
+    /// Only the pages actually visited by the cursor are read from the disk.
+    ///
+    /// # Safety
+    ///
+    /// See [`MmapBuffer::open`].
+    pub unsafe fn open_mmap(path: impl AsRef<Path>) -> Result<Self, OpenError> {
+        Ok(Cursor::new(MmapBuffer::open(path)?)?)
+    }
+}
+
+impl CursorMut<MmapMut> {
+    /// Memory-maps the document at the given path for writing, and returns a cursor to its root.
+    ///
+    /// Patches are written back to the file by the operating system; call `flush` to wait
+    /// for them to reach the disk.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated by anyone else while the mapping is alive.
+    /// See [`memmap2::MmapMut::map_mut`].
+    pub unsafe fn open_mmap_mut(path: impl AsRef<Path>) -> Result<Self, OpenError> {
+        let file = OpenOptions::new().read(true).write(true).open(path)?;
+        Ok(CursorMut::new(MmapMut::map_mut(&file)?)?)
+    }
+
+    /// Flushes outstanding patches to the disk.
+    pub fn flush(&self) -> std::io::Result<()> {
+        self.buffer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOC_PATH: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/../../test_vectors/sanity_phf.sbson"
+    );
+
+    #[test]
+    fn test_open_mmap() {
+        let cursor = unsafe { Cursor::open_mmap(DOC_PATH) }.unwrap();
+        let expected = std::fs::read(DOC_PATH).unwrap();
+        assert_eq!(&expected[..], cursor.scoped_buffer());
+
+        let child = cursor.get_value_by_index(0).unwrap();
+        assert!(Arc::ptr_eq(&cursor.buffer.0, &child.buffer.0));
+        assert_eq!(
+            Cursor::new(&expected[..])
+                .unwrap()
+                .get_value_by_index(0)
+                .unwrap()
+                .scoped_buffer(),
+            child.scoped_buffer()
+        );
+    }
+
+    #[test]
+    fn test_open_mmap_errors() {
+        assert!(matches!(
+            unsafe { Cursor::open_mmap("/nonexistent/document.sbson") },
+            Err(OpenError::Io(_))
+        ));
+
+        let empty = std::env::temp_dir().join(format!("sbson-empty-{}", std::process::id()));
+        std::fs::write(&empty, b"").unwrap();
+        let result = unsafe { Cursor::open_mmap(&empty) };
+        std::fs::remove_file(&empty).unwrap();
+        let err = result.unwrap_err();
+        assert!(matches!(
+            err,
+            OpenError::Cursor(CursorError::DocumentTooShort)
+        ));
+        assert_eq!("failed to read document", err.to_string());
+        assert_eq!(
+            "document is too short",
+            std::error::Error::source(&err).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_open_mmap_mut() {
+        let path = std::env::temp_dir().join(format!("sbson-patch-{}", std::process::id()));
+        std::fs::write(&path, std::fs::read(DOC_PATH).unwrap()).unwrap();
+
+        let mut cursor = unsafe { CursorMut::open_mmap_mut(&path) }.unwrap();
+        let mut florp_x = cursor
+            .goto(
+                [
+                    crate::PathSegment::Key("FLORP"),
+                    crate::PathSegment::Key("X"),
+                ]
+                .into_iter(),
+            )
+            .unwrap();
+        florp_x.set_i64(-1).unwrap();
+        cursor.flush().unwrap();
+        drop(cursor);
+
+        let patched = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let cursor = Cursor::new(&patched[..]).unwrap();
+        let florp = cursor.get_value_by_key("FLORP").unwrap();
+        assert_eq!(Ok(-1), florp.get_value_by_key("X").unwrap().get_i64());
+    }
+}
@@ -0,0 +1,299 @@
+use crate::{Cursor, CursorError, ElementTypeCode, GotoError, Path, PathSegment, MAP_TYPES};
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+/// A read-only view of a stack of documents, as if they were merged into one.
+///
+/// Keys are looked up in the topmost document that has them, and maps found at the same path
+/// in several documents are merged, so `iter_map` yields the union of their keys. Any other
+/// node shadows whatever the documents below it have at the same path. This is the same
+/// outcome as `sbson::merge` with the default policy, without writing the merged document.
+#[derive(Clone, Debug)]
+pub struct OverlayCursor<'a> {
+    /// The nodes at this path that are still visible, from the bottom layer up.
+    /// All of them are maps, unless there's just one.
+    layers: Vec<Cursor<&'a [u8]>>,
+}
+
+impl<'a> OverlayCursor<'a> {
+    /// Creates an overlay of the given documents, ordered from the bottom one up.
+    ///
+    /// Returns `None` if there are no documents.
+    pub fn new<T: Clone + AsRef<[u8]>>(layers: &'a [Cursor<T>]) -> Option<Self> {
+        let layers = visible(layers.iter().rev().map(Cursor::borrow));
+        (!layers.is_empty()).then_some(OverlayCursor { layers })
+    }
+
+    /// Returns the node of the topmost layer, which is what scalars should be read from.
+    pub fn as_cursor(&self) -> Cursor<&'a [u8]> {
+        self.top().clone()
+    }
+
+    /// Returns the nodes that make up this one, from the bottom layer up.
+    pub fn layers(&self) -> &[Cursor<&'a [u8]>] {
+        &self.layers
+    }
+
+    pub fn get_element_type(&self) -> ElementTypeCode {
+        self.top().get_element_type()
+    }
+
+    /// Returns the value of a key, from the topmost layer that has it.
+    pub fn get_value_by_key(&self, key: &str) -> Result<Self, CursorError> {
+        let mut children = Vec::new();
+        for layer in self.layers.iter().rev() {
+            match layer.get_value_by_key(key) {
+                Ok(child) => children.push(child),
+                Err(CursorError::KeyNotFound) => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let layers = visible(children.into_iter());
+        if layers.is_empty() {
+            return Err(CursorError::KeyNotFound);
+        }
+        Ok(OverlayCursor { layers })
+    }
+
+    /// Returns an item of an array, or the value of a map entry by its position in the topmost
+    /// layer, like `Cursor::get_value_by_index`.
+    ///
+    /// Arrays aren't merged, so their items always come from the topmost layer, while map
+    /// entries are looked up by key in every layer.
+    pub fn get_value_by_index(&self, index: usize) -> Result<Self, CursorError> {
+        let top = self.top();
+        if MAP_TYPES.contains(&top.get_element_type()) {
+            return self.get_value_by_key(top.get_storage_key_by_index(index)?);
+        }
+        Ok(OverlayCursor {
+            layers: alloc::vec![top.get_value_by_index(index)?],
+        })
+    }
+
+    /// Follows a sequence of path segments, the same way `Cursor::goto` does.
+    ///
+    /// The offset of the error is that of the node in the topmost layer.
+    pub fn goto<'p>(
+        &self,
+        path_segments: impl Iterator<Item = PathSegment<'p>>,
+    ) -> Result<Self, GotoError> {
+        let mut node = self.clone();
+        let mut path = Vec::new();
+        for segment in path_segments {
+            let child = match segment {
+                PathSegment::Key(key) => node.get_value_by_key(key),
+                PathSegment::Index(index) => node.get_value_by_index(index),
+            };
+            node = child.map_err(|kind| GotoError {
+                path: Path::from(core::mem::take(&mut path)),
+                segment: segment.into(),
+                offset: node.top().range.start,
+                kind,
+            })?;
+            path.push(segment.into());
+        }
+        Ok(node)
+    }
+
+    /// Iterates over the union of the keys of the maps at this path, ordered by key.
+    /// Malformed children are silently dropped.
+    pub fn iter_map(&self) -> Result<impl Iterator<Item = (&'a str, Self)>, CursorError> {
+        let top = self.top();
+        let actual = top.get_element_type();
+        if !MAP_TYPES.contains(&actual) {
+            return Err(CursorError::WrongElementType {
+                expected: MAP_TYPES,
+                actual,
+            });
+        }
+        let mut children: BTreeMap<&'a str, Vec<Cursor<&'a [u8]>>> = BTreeMap::new();
+        for layer in self.layers.iter().rev() {
+            for index in 0..layer.get_children_count() {
+                let (Ok(key), Ok(value)) = (
+                    layer.get_storage_key_by_index(index),
+                    layer.get_value_by_index(index),
+                ) else {
+                    continue;
+                };
+                children.entry(key).or_default().push(value);
+            }
+        }
+        Ok(children.into_iter().map(|(key, children)| {
+            let layers = visible(children.into_iter());
+            (key, OverlayCursor { layers })
+        }))
+    }
+
+    fn top(&self) -> &Cursor<&'a [u8]> {
+        self.layers
+            .last()
+            .expect("an overlay has at least one layer")
+    }
+}
+
+/// Given the nodes at a path from the topmost layer down, returns those that are visible,
+/// from the bottom layer up.
+///
+/// A map is merged with the maps right below it, while any other node shadows all of them.
+fn visible<'a>(nodes: impl Iterator<Item = Cursor<&'a [u8]>>) -> Vec<Cursor<&'a [u8]>> {
+    let mut visible = Vec::new();
+    for node in nodes {
+        let is_map = MAP_TYPES.contains(&node.get_element_type());
+        if visible.is_empty() || is_map {
+            visible.push(node);
+        }
+        if !is_map {
+            break;
+        }
+    }
+    visible.reverse();
+    visible
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::{serialize, DOC, DOC_PHF};
+    use crate::OwnedPathSegment;
+    use crate::Value;
+
+    fn keys<'a>(overlay: &OverlayCursor<'a>) -> Vec<&'a str> {
+        overlay.iter_map().unwrap().map(|(key, _)| key).collect()
+    }
+
+    #[test]
+    fn test_overlay() {
+        let overrides = serialize(&BTreeMap::from([
+            (
+                "FLORP",
+                Value::Map(BTreeMap::from([("Y".into(), Value::Int32(2))])),
+            ),
+            ("BLARG", Value::Array(vec![Value::Bool(true)])),
+            ("new", Value::Null),
+        ]));
+        let layers = [
+            Cursor::new(DOC).unwrap(),
+            Cursor::new(&overrides[..]).unwrap(),
+        ];
+        let overlay = OverlayCursor::new(&layers).unwrap();
+        assert_eq!(ElementTypeCode::Map, overlay.get_element_type());
+        assert_eq!(2, overlay.layers().len());
+
+        let base_keys = keys(&OverlayCursor::new(&layers[..1]).unwrap());
+        let mut expected = base_keys.clone();
+        expected.push("new");
+        expected.sort();
+        assert_eq!(expected, keys(&overlay));
+
+        // Maps are merged, and the topmost layer wins.
+        let florp = overlay.get_value_by_key("FLORP").unwrap();
+        assert_eq!(["X", "Y"], &keys(&florp)[..]);
+        let path = ["FLORP", "Y"].map(PathSegment::Key);
+        assert_eq!(
+            Ok(2),
+            overlay
+                .goto(path.into_iter())
+                .unwrap()
+                .as_cursor()
+                .get_i32()
+        );
+        let path = ["FLORP", "X"].map(PathSegment::Key);
+        assert_eq!(
+            layers[0].goto(path.into_iter()).unwrap().scoped_buffer(),
+            overlay
+                .goto(path.into_iter())
+                .unwrap()
+                .as_cursor()
+                .scoped_buffer()
+        );
+
+        // Indices select map entries by their position in the topmost layer.
+        let path = [PathSegment::Key("FLORP"), PathSegment::Index(0)];
+        assert_eq!(
+            Ok(2),
+            overlay
+                .goto(path.into_iter())
+                .unwrap()
+                .as_cursor()
+                .get_i32()
+        );
+
+        // Arrays are shadowed as a whole.
+        let blarg = overlay.get_value_by_key("BLARG").unwrap();
+        assert_eq!(1, blarg.layers().len());
+        assert_eq!(
+            Ok(true),
+            blarg.get_value_by_index(0).unwrap().as_cursor().get_bool()
+        );
+        assert_eq!(
+            Err(CursorError::ItemIndexOutOfBounds),
+            blarg.get_value_by_index(1).map(|_| ())
+        );
+        assert_eq!(
+            layers[0].get_value_by_key("3").unwrap().scoped_buffer(),
+            overlay
+                .get_value_by_key("3")
+                .unwrap()
+                .as_cursor()
+                .scoped_buffer()
+        );
+
+        let path = [PathSegment::Key("FLORP"), PathSegment::Key("Z")];
+        let err = overlay.goto(path.into_iter()).unwrap_err();
+        assert_eq!([OwnedPathSegment::Key("FLORP".into())], err.path.segments());
+        assert_eq!(CursorError::KeyNotFound, err.kind);
+        assert!(matches!(
+            overlay.get_value_by_key("new").unwrap().iter_map(),
+            Err(CursorError::WrongElementType { .. })
+        ));
+    }
+
+    #[test]
+    fn test_overlay_goto_like_cursor() {
+        let layers = [Cursor::new(DOC).unwrap()];
+        let overlay = OverlayCursor::new(&layers).unwrap();
+        let florp_position = (0..4)
+            .find(|&index| layers[0].get_key_by_index(index) == Ok("FLORP"))
+            .unwrap();
+        let paths = [
+            vec![PathSegment::Key("BLARG"), PathSegment::Index(1)],
+            vec![PathSegment::Key("BLARG"), PathSegment::Key("1")],
+            vec![PathSegment::Index(florp_position), PathSegment::Key("X")],
+            vec![PathSegment::Index(florp_position), PathSegment::Index(0)],
+            vec![PathSegment::Index(4)],
+            vec![PathSegment::Key("3"), PathSegment::Index(0)],
+        ];
+        for path in paths {
+            let expected = layers[0].goto(path.iter().copied());
+            let actual = overlay.goto(path.iter().copied());
+            assert_eq!(
+                expected.map(|node| node.scoped_buffer().to_vec()),
+                actual.map(|node| node.as_cursor().scoped_buffer().to_vec()),
+                "{path:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_overlay_shadowing() {
+        // A map on top of a leaf hides it, and a leaf on top of maps hides all of them.
+        let leaf = serialize(&BTreeMap::from([("FLORP", 1i32)]));
+        let map = serialize(&BTreeMap::from([("FLORP", BTreeMap::from([("Y", 1i32)]))]));
+        let layers = [DOC, &leaf, DOC_PHF, &map].map(|doc| Cursor::new(doc).unwrap());
+
+        let overlay = OverlayCursor::new(&layers).unwrap();
+        let florp = overlay.get_value_by_key("FLORP").unwrap();
+        assert_eq!(2, florp.layers().len());
+        assert_eq!(["X", "Y"], &keys(&florp)[..]);
+
+        let overlay = OverlayCursor::new(&layers[..2]).unwrap();
+        let florp = overlay.get_value_by_key("FLORP").unwrap();
+        assert_eq!(Ok(1), florp.as_cursor().get_i32());
+        assert!(florp.get_value_by_key("X").is_err());
+
+        let layers = [layers[0].clone(), Cursor::new(&b"\x0a"[..]).unwrap()];
+        let overlay = OverlayCursor::new(&layers).unwrap();
+        assert_eq!(ElementTypeCode::None, overlay.get_element_type());
+        assert!(OverlayCursor::new::<&[u8]>(&[]).is_none());
+    }
+}
@@ -0,0 +1,298 @@
+use crate::raw_cursor::ELEMENT_TYPE_SIZE;
+use crate::{Cursor, CursorError, ElementTypeCode};
+use core::marker::PhantomData;
+
+/// The size of a packed array's headers: its element type, item type and padding length.
+pub(crate) const PACKED_ARRAY_HEADER_SIZE: usize = ELEMENT_TYPE_SIZE + 2;
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// A numeric type that can be stored in a `PackedArray` node.
+///
+/// Only the numeric element types of the format can be packed: `f64`, `i32`, `u32`, `i64`
+/// and `u64`. There is no element type for single bytes; byte arrays are stored as `Binary`
+/// nodes instead, which `Cursor::get_binary` already reads as a borrowed slice.
+pub trait PackedItem: Copy + private::Sealed {
+    /// The element type recorded in the header of packed arrays of this type.
+    const ELEMENT_TYPE: ElementTypeCode;
+
+    type Bytes: AsRef<[u8]> + for<'a> TryFrom<&'a [u8]>;
+
+    fn from_le_bytes(bytes: Self::Bytes) -> Self;
+    fn to_le_bytes(self) -> Self::Bytes;
+}
+
+macro_rules! packed_item {
+    ($($ty:ty => $element_type:ident),*) => {$(
+        impl private::Sealed for $ty {}
+
+        impl PackedItem for $ty {
+            const ELEMENT_TYPE: ElementTypeCode = ElementTypeCode::$element_type;
+
+            type Bytes = [u8; core::mem::size_of::<$ty>()];
+
+            fn from_le_bytes(bytes: Self::Bytes) -> Self {
+                <$ty>::from_le_bytes(bytes)
+            }
+
+            fn to_le_bytes(self) -> Self::Bytes {
+                <$ty>::to_le_bytes(self)
+            }
+        }
+    )*};
+}
+
+packed_item!(f64 => Double, i32 => Int32, u32 => UInt32, i64 => Int64, u64 => UInt64);
+
+/// Returns the size of the items of a packed array with the given item type,
+/// or `None` if the item type can't be packed.
+pub(crate) fn packed_item_size(item_type: ElementTypeCode) -> Option<usize> {
+    match item_type {
+        ElementTypeCode::Int32 | ElementTypeCode::UInt32 => Some(4),
+        ElementTypeCode::Double | ElementTypeCode::Int64 | ElementTypeCode::UInt64 => Some(8),
+        _ => None,
+    }
+}
+
+/// Splits the payload of a packed array node into its item type and the bytes of its items.
+pub(crate) fn split_packed_array(payload: &[u8]) -> Result<(ElementTypeCode, &[u8]), CursorError> {
+    let [item_type, padding, rest @ ..] = payload else {
+        return Err(CursorError::DocumentTooShort);
+    };
+    let item_type = ElementTypeCode::try_from(*item_type)?;
+    let item_size =
+        packed_item_size(item_type).ok_or(CursorError::InvalidElementType(item_type as u8))?;
+    let items = rest
+        .get(*padding as usize..)
+        .ok_or(CursorError::DocumentTooShort)?;
+    // The last item is truncated.
+    if items.len() % item_size != 0 {
+        return Err(CursorError::DocumentTooShort);
+    }
+    Ok((item_type, items))
+}
+
+/// A borrowed view over the items of a `PackedArray` node.
+///
+/// Items are stored back to back in little-endian order, so they can be read in O(1)
+/// without creating a cursor per item.
+#[derive(Copy, Clone, Debug)]
+pub struct PackedArray<'a, E> {
+    bytes: &'a [u8],
+    marker: PhantomData<E>,
+}
+
+impl<'a, E: PackedItem> PackedArray<'a, E> {
+    pub(crate) fn new(payload: &'a [u8]) -> Result<Self, CursorError> {
+        let (item_type, bytes) = split_packed_array(payload)?;
+        if item_type != E::ELEMENT_TYPE {
+            return Err(CursorError::WrongElementType {
+                expected: E::ELEMENT_TYPE.as_slice(),
+                actual: item_type,
+            });
+        }
+        Ok(Self {
+            bytes,
+            marker: PhantomData,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len() / core::mem::size_of::<E>()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<E> {
+        let size = core::mem::size_of::<E>();
+        let start = index.checked_mul(size)?;
+        let bytes = self.bytes.get(start..start.checked_add(size)?)?;
+        Some(E::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Iterates over the items, decoding each of them from little-endian.
+    pub fn iter(&self) -> PackedIter<'a, E> {
+        PackedIter {
+            chunks: self.bytes.chunks_exact(core::mem::size_of::<E>()),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the raw little-endian representation of the items.
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// Returns the items as a native slice, without copying them.
+    ///
+    /// This is only possible on little-endian targets, and when the items happen to be aligned
+    /// in memory. The serializer pads items to their alignment relative to the start of the
+    /// document, so this holds for documents read from sufficiently aligned buffers,
+    /// such as memory-mapped files or most heap allocations.
+    ///
+    /// The padding is fixed once the node is written, so a packed array that is copied as-is
+    /// into another document may end up unaligned. The `edit` and `merge` modules, and
+    /// `DocumentBuilder`, re-pad the packed arrays they copy whenever that happens. Unaligned
+    /// arrays can still be read with [`iter`](Self::iter) and [`get`](Self::get).
+    pub fn as_slice(&self) -> Option<&'a [E]> {
+        if cfg!(target_endian = "big") {
+            return None;
+        }
+        // SAFETY: All packable types are plain integers and floats, for which any bit pattern
+        // is valid, and `align_to` takes care of the alignment.
+        let (prefix, items, suffix) = unsafe { self.bytes.align_to::<E>() };
+        (prefix.is_empty() && suffix.is_empty()).then_some(items)
+    }
+}
+
+impl<'a, E: PackedItem> IntoIterator for PackedArray<'a, E> {
+    type Item = E;
+    type IntoIter = PackedIter<'a, E>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// An iterator over the items of a [`PackedArray`].
+#[derive(Clone, Debug)]
+pub struct PackedIter<'a, E> {
+    chunks: core::slice::ChunksExact<'a, u8>,
+    marker: PhantomData<E>,
+}
+
+impl<E: PackedItem> Iterator for PackedIter<'_, E> {
+    type Item = E;
+
+    fn next(&mut self) -> Option<E> {
+        let chunk = self.chunks.next()?;
+        Some(E::from_le_bytes(chunk.try_into().ok()?))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.chunks.size_hint()
+    }
+}
+
+impl<E: PackedItem> DoubleEndedIterator for PackedIter<'_, E> {
+    fn next_back(&mut self) -> Option<E> {
+        let chunk = self.chunks.next_back()?;
+        Some(E::from_le_bytes(chunk.try_into().ok()?))
+    }
+}
+
+impl<E: PackedItem> ExactSizeIterator for PackedIter<'_, E> {}
+
+impl<T: Clone + AsRef<[u8]>> Cursor<T> {
+    /// Returns the type of the items of a packed array node.
+    pub fn get_packed_item_type(&self) -> Result<ElementTypeCode, CursorError> {
+        self.raw_cursor
+            .ensure_element_type(ElementTypeCode::PackedArray)?;
+        let (item_type, _items) = split_packed_array(self.payload_scoped_buffer())?;
+        Ok(item_type)
+    }
+
+    /// Returns a view over the items of a packed array node.
+    ///
+    /// Fails with `WrongElementType` if the items aren't of type `E`.
+    /// The returned view is lifetime-bound to the current cursor.
+    /// If the cursor is not an owning-cursor, a view bound to the backing storage
+    /// can be receieved by calling `get_storage_packed_array`.
+    pub fn get_packed_array<E: PackedItem>(&self) -> Result<PackedArray<'_, E>, CursorError> {
+        self.raw_cursor
+            .ensure_element_type(ElementTypeCode::PackedArray)?;
+        PackedArray::new(self.payload_scoped_buffer())
+    }
+}
+
+impl<'data> Cursor<&'data [u8]> {
+    /// Returns a view over the items of a packed array node.
+    ///
+    /// This view is lifetime-bound to the backing storage referenced by this cursor,
+    /// and may outlive the cursor.
+    pub fn get_storage_packed_array<E: PackedItem>(
+        &self,
+    ) -> Result<PackedArray<'data, E>, CursorError> {
+        self.raw_cursor
+            .ensure_element_type(ElementTypeCode::PackedArray)?;
+        let mut range = self.range.clone();
+        // Skip the first element as it is the element type
+        range.start += 1;
+        PackedArray::new(&self.buffer[range])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize;
+    use crate::serializer::Packed;
+
+    #[test]
+    fn test_packed_array() {
+        let samples: Vec<f64> = (0..1000).map(|i| i as f64 / 3.0).collect();
+        let buffer = serialize(&Packed(&samples[..]));
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        assert_eq!(ElementTypeCode::PackedArray, cursor.get_element_type());
+        assert_eq!(Ok(ElementTypeCode::Double), cursor.get_packed_item_type());
+        assert!(cursor.validate().is_ok());
+
+        let packed = cursor.get_storage_packed_array::<f64>().unwrap();
+        assert_eq!(samples.len(), packed.len());
+        assert_eq!(Some(samples[345]), packed.get(345));
+        assert_eq!(None, packed.get(1000));
+        assert_eq!(samples, packed.iter().collect::<Vec<_>>());
+        assert_eq!(samples.last().copied(), packed.iter().next_back());
+        assert_eq!(samples.len() * 8, packed.as_bytes().len());
+        if cfg!(target_endian = "little") && buffer.as_ptr().align_offset(8) == 0 {
+            assert_eq!(Some(&samples[..]), packed.as_slice());
+        }
+
+        assert_eq!(
+            Err(CursorError::WrongElementType {
+                expected: &[ElementTypeCode::Int32],
+                actual: ElementTypeCode::Double,
+            }),
+            cursor.get_packed_array::<i32>().map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_packed_array_alignment() {
+        // Items are padded to their alignment, even when nested.
+        let ints: Vec<i64> = vec![-1, 2, i64::MAX];
+        let buffer = serialize(&("abc", Packed(&ints[..])));
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        let packed = cursor.get_value_by_index(1).unwrap();
+        let items = packed.get_packed_array::<i64>().unwrap();
+        let offset = buffer.len() - items.as_bytes().len();
+        assert_eq!(0, offset % 8);
+        assert_eq!(ints, items.iter().collect::<Vec<_>>());
+
+        let empty = serialize(&Packed::<u32>(&[]));
+        let cursor = Cursor::new(&empty[..]).unwrap();
+        assert!(cursor.get_packed_array::<u32>().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_malformed_packed_array() {
+        for buffer in [
+            &b"\x06"[..],
+            b"\x06\x10",
+            b"\x06\x10\x02\x00",
+            b"\x06\x10\x00\x01\x02\x03",
+            b"\x06\x02\x00",
+            b"\x06\xff\x00",
+        ] {
+            let result = Cursor::new(buffer).and_then(|cursor| cursor.get_packed_item_type());
+            assert!(result.is_err(), "{buffer:?}");
+        }
+        let cursor = Cursor::new(&b"\x06\x10\x00\x01\x00\x00\x00\x02\x00\x00\x00"[..]).unwrap();
+        let items = cursor.get_packed_array::<i32>().unwrap();
+        assert_eq!(vec![1, 2], items.into_iter().collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,442 @@
+use crate::cursor::parse_array_index;
+use crate::{Cursor, GotoError, OwnedPathSegment, PathSegment};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// A parsed textual path into a document.
+///
+/// Two syntaxes are accepted by [`Path::parse`]:
+/// - JSON Pointers ([RFC 6901](https://www.rfc-editor.org/rfc/rfc6901)), such as
+///   `/top/item_0042/something/3`, where `~1` and `~0` stand for `/` and `~` inside keys.
+/// - Dotted paths, such as `top.item_0042.something[3]`.
+///
+/// Both syntaxes accept bracketed indices (`/something[3]`) and quoted keys (`top["a.b"]`).
+/// Since this makes `[` special, [`Path::from_json_pointer`] is available for parsing
+/// pointers strictly according to the RFC.
+///
+/// Segments that aren't bracketed are keys, but like in JSON Pointers, [`Cursor::query_path`]
+/// uses them as indices when it applies them to an array.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Path {
+    segments: Vec<OwnedPathSegment>,
+}
+
+/// Errors returned when parsing or following a textual path.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum PathError {
+    /// The path is malformed.
+    Syntax { offset: usize, reason: &'static str },
+
+    /// The path couldn't be followed.
+    Goto(GotoError),
+}
+
+impl core::fmt::Display for PathError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            PathError::Syntax { offset, reason } => {
+                write!(f, "invalid path at offset {offset}: {reason}")
+            }
+            PathError::Goto(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for PathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PathError::Syntax { .. } => None,
+            PathError::Goto(err) => std::error::Error::source(err),
+        }
+    }
+}
+
+impl From<GotoError> for PathError {
+    fn from(err: GotoError) -> Self {
+        PathError::Goto(err)
+    }
+}
+
+impl From<Vec<OwnedPathSegment>> for Path {
+    fn from(segments: Vec<OwnedPathSegment>) -> Self {
+        Path { segments }
+    }
+}
+
+/// Formats the path as a JSON Pointer.
+impl core::fmt::Display for Path {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        for segment in &self.segments {
+            match segment {
+                OwnedPathSegment::Key(key) => {
+                    write!(f, "/{}", key.replace('~', "~0").replace('/', "~1"))?
+                }
+                OwnedPathSegment::Index(index) => write!(f, "/{index}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Path {
+    /// Parses a path in either of the supported syntaxes.
+    ///
+    /// Paths starting with `/` are parsed as JSON Pointers, and the rest as dotted paths.
+    /// The empty path refers to the node it is applied to.
+    pub fn parse(text: &str) -> Result<Self, PathError> {
+        let mut parser = Parser::new(text);
+        if text.starts_with('/') {
+            parser.parse_pointer()?;
+        } else {
+            parser.parse_dotted()?;
+        }
+        Ok(Path {
+            segments: parser.segments,
+        })
+    }
+
+    /// Parses a JSON Pointer strictly according to RFC 6901.
+    pub fn from_json_pointer(text: &str) -> Result<Self, PathError> {
+        if !text.is_empty() && !text.starts_with('/') {
+            return Err(PathError::Syntax {
+                offset: 0,
+                reason: "JSON Pointers must start with `/`",
+            });
+        }
+        let mut segments = vec![];
+        let mut offset = 0;
+        for token in text.split('/').skip(1) {
+            offset += 1;
+            segments.push(OwnedPathSegment::Key(unescape_pointer_token(
+                token, offset,
+            )?));
+            offset += token.len();
+        }
+        Ok(Path { segments })
+    }
+
+    pub fn segments(&self) -> &[OwnedPathSegment] {
+        &self.segments
+    }
+
+    /// Returns the segments in a form accepted by [`Cursor::goto`].
+    ///
+    /// Unlike with [`Cursor::query_path`], keys aren't converted into indices when applied to arrays.
+    pub fn iter(&self) -> impl Iterator<Item = PathSegment<'_>> + Clone {
+        self.segments.iter().map(OwnedPathSegment::as_path_segment)
+    }
+}
+
+fn unescape_pointer_token(token: &str, offset: usize) -> Result<String, PathError> {
+    let mut unescaped = String::with_capacity(token.len());
+    let mut chars = token.char_indices();
+    while let Some((position, c)) = chars.next() {
+        if c != '~' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some((_, '0')) => unescaped.push('~'),
+            Some((_, '1')) => unescaped.push('/'),
+            _ => {
+                return Err(PathError::Syntax {
+                    offset: offset + position,
+                    reason: "`~` must be followed by `0` or `1`",
+                })
+            }
+        }
+    }
+    Ok(unescaped)
+}
+
+pub(crate) struct Parser<'a> {
+    pub(crate) text: &'a str,
+    pub(crate) position: usize,
+    pub(crate) segments: Vec<OwnedPathSegment>,
+}
+
+impl<'a> Parser<'a> {
+    pub(crate) fn new(text: &'a str) -> Self {
+        Self {
+            text,
+            position: 0,
+            segments: vec![],
+        }
+    }
+
+    pub(crate) fn error(&self, reason: &'static str) -> PathError {
+        PathError::Syntax {
+            offset: self.position,
+            reason,
+        }
+    }
+
+    pub(crate) fn peek(&self) -> Option<char> {
+        self.text[self.position..].chars().next()
+    }
+
+    /// Consumes text up to (but excluding) any of the given delimiters.
+    pub(crate) fn take_until(&mut self, delimiters: &[char]) -> &'a str {
+        let rest = &self.text[self.position..];
+        let length = rest.find(delimiters).unwrap_or(rest.len());
+        self.position += length;
+        &rest[..length]
+    }
+
+    fn parse_pointer(&mut self) -> Result<(), PathError> {
+        while self.position < self.text.len() {
+            // Skip the `/` separator.
+            self.position += 1;
+            let start = self.position;
+            let token = self.take_until(&['/', '[']);
+            let key = unescape_pointer_token(token, start)?;
+            if key.is_empty() && self.peek() == Some('[') {
+                // `/[3]` is the same as `[3]`.
+            } else {
+                self.segments.push(OwnedPathSegment::Key(key));
+            }
+            self.parse_brackets()?;
+            if !matches!(self.peek(), None | Some('/')) {
+                return Err(self.error("expected `/` or `[`"));
+            }
+        }
+        Ok(())
+    }
+
+    fn parse_dotted(&mut self) -> Result<(), PathError> {
+        if self.text.is_empty() {
+            return Ok(());
+        }
+        if self.peek() != Some('[') {
+            self.parse_dotted_key()?;
+        }
+        self.parse_brackets()?;
+        while self.position < self.text.len() {
+            if self.peek() != Some('.') {
+                return Err(self.error("expected `.` or `[`"));
+            }
+            self.position += 1;
+            self.parse_dotted_key()?;
+            self.parse_brackets()?;
+        }
+        Ok(())
+    }
+
+    fn parse_dotted_key(&mut self) -> Result<(), PathError> {
+        let key = self.take_until(&['.', '[']);
+        if key.is_empty() {
+            return Err(self.error("expected a key"));
+        }
+        let key = key.to_string();
+        self.segments.push(OwnedPathSegment::Key(key));
+        Ok(())
+    }
+
+    /// Parses any amount of `[3]` or `["key"]` segments.
+    pub(crate) fn parse_brackets(&mut self) -> Result<(), PathError> {
+        while self.peek() == Some('[') {
+            self.position += 1;
+            let segment = match self.peek() {
+                Some(quote @ ('"' | '\'')) => {
+                    self.position += 1;
+                    OwnedPathSegment::Key(self.parse_quoted(quote)?)
+                }
+                _ => {
+                    let index = self.take_until(&[']']);
+                    match parse_array_index(index) {
+                        Some(index) => OwnedPathSegment::Index(index),
+                        None => {
+                            self.position -= index.len();
+                            return Err(self.error("expected an index or a quoted key"));
+                        }
+                    }
+                }
+            };
+            if self.peek() != Some(']') {
+                return Err(self.error("expected `]`"));
+            }
+            self.position += 1;
+            self.segments.push(segment);
+        }
+        Ok(())
+    }
+
+    /// Parses the rest of a quoted key, in which `\` escapes the next character.
+    pub(crate) fn parse_quoted(&mut self, quote: char) -> Result<String, PathError> {
+        let mut key = String::new();
+        let mut chars = self.text[self.position..].char_indices();
+        while let Some((offset, c)) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some((_, escaped)) => key.push(escaped),
+                    None => break,
+                },
+                c if c == quote => {
+                    self.position += offset + c.len_utf8();
+                    return Ok(key);
+                }
+                c => key.push(c),
+            }
+        }
+        self.position = self.text.len();
+        Err(self.error("unterminated quoted key"))
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> Cursor<T> {
+    /// Parses a textual path (see [`Path`]) and follows it from this node.
+    pub fn query(&self, path: &str) -> Result<Self, PathError> {
+        Ok(self.query_path(&Path::parse(path)?)?)
+    }
+
+    /// Follows a parsed path from this node.
+    ///
+    /// Unlike with [`Cursor::goto`], keys that are valid array indices select an item when they
+    /// are applied to an array.
+    pub fn query_path(&self, path: &Path) -> Result<Self, GotoError> {
+        self.goto_with(path.iter(), true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::DOC;
+    use crate::{CursorError, ElementTypeCode, MAP_TYPES};
+
+    fn key(key: &str) -> OwnedPathSegment {
+        OwnedPathSegment::Key(key.into())
+    }
+
+    fn index(index: usize) -> OwnedPathSegment {
+        OwnedPathSegment::Index(index)
+    }
+
+    #[test]
+    fn test_parse() {
+        let cases = [
+            ("", vec![]),
+            ("/", vec![key("")]),
+            ("/a/b", vec![key("a"), key("b")]),
+            ("/a~1b/~0/3", vec![key("a/b"), key("~"), key("3")]),
+            (
+                "/top/something[3]",
+                vec![key("top"), key("something"), index(3)],
+            ),
+            ("/top/[3][4]", vec![key("top"), index(3), index(4)]),
+            ("/a/", vec![key("a"), key("")]),
+            ("top.item.3", vec![key("top"), key("item"), key("3")]),
+            ("top[3].x", vec![key("top"), index(3), key("x")]),
+            (
+                "[0]['a.b'][\"c\\\"\"]",
+                vec![index(0), key("a.b"), key("c\"")],
+            ),
+            ("a/b", vec![key("a/b")]),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(
+                Ok(expected),
+                Path::parse(text).map(|path| path.segments),
+                "{text}"
+            );
+        }
+
+        assert_eq!(
+            Ok(vec![key("a[0]"), key("")]),
+            Path::from_json_pointer("/a[0]/").map(|path| path.segments)
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("/a~2", 2, "`~` must be followed by `0` or `1`"),
+            ("/a[x]", 3, "expected an index or a quoted key"),
+            ("/a[01]", 3, "expected an index or a quoted key"),
+            ("/a[0", 4, "expected `]`"),
+            ("/a[0]b", 5, "expected `/` or `[`"),
+            ("a..b", 2, "expected a key"),
+            ("a.", 2, "expected a key"),
+            ("a[0]b", 4, "expected `.` or `[`"),
+            ("a['b", 4, "unterminated quoted key"),
+        ];
+        for (text, offset, reason) in cases {
+            assert_eq!(
+                Err(PathError::Syntax { offset, reason }),
+                Path::parse(text),
+                "{text}"
+            );
+        }
+        assert!(Path::from_json_pointer("a").is_err());
+        assert!(Path::from_json_pointer("/~").is_err());
+    }
+
+    #[test]
+    fn test_query() {
+        let cursor = Cursor::new(DOC).unwrap();
+        assert_eq!(Ok(255), cursor.query("/FLORP/X").unwrap().get_i64());
+        assert_eq!(Ok(255), cursor.query("FLORP.X").unwrap().get_i64());
+        assert_eq!(Ok(true), cursor.query("/BLARG/2").unwrap().get_bool());
+        assert_eq!(Ok(true), cursor.query("BLARG[2]").unwrap().get_bool());
+        assert_eq!(Ok(true), cursor.query("BLARG.2").unwrap().get_bool());
+        assert_eq!(
+            Ok(&b"beep boop"[..]),
+            cursor.query("['3']").unwrap().get_binary()
+        );
+        assert_eq!(
+            cursor.query("").unwrap().scoped_buffer(),
+            cursor.scoped_buffer()
+        );
+
+        let path = Path::parse("/BLARG/4").unwrap();
+        let blarg_4 = [PathSegment::Key("BLARG"), PathSegment::Index(4)];
+        assert_eq!(
+            cursor.query_path(&path).unwrap().scoped_buffer(),
+            cursor.goto(blarg_4.into_iter()).unwrap().scoped_buffer()
+        );
+        // `goto` itself never treats keys as indices.
+        assert_eq!(
+            CursorError::WrongElementType {
+                expected: MAP_TYPES,
+                actual: ElementTypeCode::Array
+            },
+            cursor.goto(path.iter()).unwrap_err().kind
+        );
+    }
+
+    #[test]
+    fn test_query_errors() {
+        let cursor = Cursor::new(DOC).unwrap();
+        let not_a_map = |actual| CursorError::WrongElementType {
+            expected: MAP_TYPES,
+            actual,
+        };
+        let cases = [
+            ("/FLORP/Y", 1, key("Y"), CursorError::KeyNotFound),
+            ("/BLARG[5]", 1, index(5), CursorError::ItemIndexOutOfBounds),
+            ("/BLARG/x", 1, key("x"), not_a_map(ElementTypeCode::Array)),
+            ("/BLARG/01", 1, key("01"), not_a_map(ElementTypeCode::Array)),
+            ("/FLORP/X/Y", 2, key("Y"), not_a_map(ElementTypeCode::Int64)),
+        ];
+        for (text, depth, segment, kind) in cases {
+            let path = Path::from(Path::parse(text).unwrap().segments[..depth].to_vec());
+            let offset = cursor.query_path(&path).unwrap().range.start;
+            assert_eq!(
+                Err(PathError::Goto(GotoError {
+                    path,
+                    segment,
+                    offset,
+                    kind
+                })),
+                cursor.query(text).map(|_| ()),
+                "{text}"
+            );
+        }
+        assert_eq!(
+            "key \"Y\" at offset 111 (path: /FLORP): key not found",
+            cursor.query("/FLORP/Y").unwrap_err().to_string()
+        );
+    }
+}
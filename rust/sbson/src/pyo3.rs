@@ -1,9 +1,28 @@
-use crate::CursorError;
-use pyo3::exceptions::PyValueError;
+use crate::{CursorError, GotoError, PathError};
+use pyo3::exceptions::{PyIndexError, PyKeyError, PyValueError};
 use pyo3::prelude::*;
 
 impl From<CursorError> for PyErr {
     fn from(err: CursorError) -> PyErr {
-        PyValueError::new_err(format!("{err:?}"))
+        PyValueError::new_err(err.to_string())
+    }
+}
+
+impl From<GotoError> for PyErr {
+    fn from(err: GotoError) -> PyErr {
+        match err.kind {
+            CursorError::KeyNotFound => PyKeyError::new_err(err.to_string()),
+            CursorError::ItemIndexOutOfBounds => PyIndexError::new_err(err.to_string()),
+            _ => PyValueError::new_err(err.to_string()),
+        }
+    }
+}
+
+impl From<PathError> for PyErr {
+    fn from(err: PathError) -> PyErr {
+        match err {
+            PathError::Goto(err) => err.into(),
+            PathError::Syntax { .. } => PyValueError::new_err(err.to_string()),
+        }
     }
 }
@@ -18,18 +18,19 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
 // SOFTWARE.
 
-use super::{CursorError, ElementTypeCode};
+use super::packed::PACKED_ARRAY_HEADER_SIZE;
+use super::{CursorError, ElementTypeCode, CONTAINER_TYPES, MAP_TYPES};
 use core::ops::Range;
 
 pub const ELEMENT_TYPE_SIZE: usize = 1;
-const U32_SIZE_BYTES: usize = core::mem::size_of::<u32>();
-const ARRAY_DESCRIPTOR_SIZE: usize = U32_SIZE_BYTES;
-const MAP_DESCRIPTOR_SIZE: usize = 2 * U32_SIZE_BYTES;
-
-struct MapDescriptor {
-    key_offset: usize,
-    key_length: usize,
-    value_offset: usize,
+pub const U32_SIZE_BYTES: usize = core::mem::size_of::<u32>();
+pub const ARRAY_DESCRIPTOR_SIZE: usize = U32_SIZE_BYTES;
+pub const MAP_DESCRIPTOR_SIZE: usize = 2 * U32_SIZE_BYTES;
+
+pub struct MapDescriptor {
+    pub key_offset: usize,
+    pub key_length: usize,
+    pub value_offset: usize,
 }
 
 pub fn get_byte_array_at<const N: usize>(
@@ -59,9 +60,9 @@ pub fn get_u32_pair_at_offset(buffer: &[u8], offset: usize) -> Result<(u32, u32)
     Ok((a, b))
 }
 
-fn get_map_descriptor(descriptors: &[u8], index: usize) -> Result<MapDescriptor, CursorError> {
+pub fn get_map_descriptor(descriptors: &[u8], index: usize) -> Result<MapDescriptor, CursorError> {
     let (key_data, value_offset) =
-        get_u32_pair_at_offset(descriptors, MAP_DESCRIPTOR_SIZE * index as usize)?;
+        get_u32_pair_at_offset(descriptors, MAP_DESCRIPTOR_SIZE * index)?;
     let key_offset = (key_data & 0x00FFFFFF) as usize;
     let key_length = (key_data >> 24) as usize;
     let value_offset = value_offset as usize;
@@ -72,11 +73,11 @@ fn get_map_descriptor(descriptors: &[u8], index: usize) -> Result<MapDescriptor,
     })
 }
 
-const fn calculate_bucket_count(child_count: u32) -> usize {
-    ((child_count + 4) / 5) as usize
+pub const fn calculate_bucket_count(child_count: u32) -> usize {
+    child_count.div_ceil(5) as usize
 }
 
-const fn calculate_chd_descriptors_offset(child_count: u32) -> usize {
+pub const fn calculate_chd_descriptors_offset(child_count: u32) -> usize {
     // From Python:
     // ```python
     // _element_type, item_count, _seed, = struct.unpack_from("<BII", view)
@@ -94,6 +95,78 @@ const fn calculate_chd_descriptors_offset(child_count: u32) -> usize {
     U32_SIZE_BYTES * 2 * bucket_count
 }
 
+/// Yields the (0-based) storage indices of an Eytzinger-ordered array of `len` items,
+/// in sorted order.
+///
+/// This is an in-order traversal of the implicit binary tree, where the children of
+/// the 1-based node `k` are `2k` and `2k + 1`.
+#[cfg(feature = "alloc")]
+#[derive(Debug, Clone)]
+pub struct EytzingerInOrder {
+    /// The current 1-based node; 0 once the traversal is over.
+    k: usize,
+    len: usize,
+}
+
+#[cfg(feature = "alloc")]
+impl EytzingerInOrder {
+    pub fn new(len: usize) -> Self {
+        let mut k = if len == 0 { 0 } else { 1 };
+        // Start at the leftmost (smallest) node.
+        while k != 0 && 2 * k <= len {
+            k *= 2;
+        }
+        Self { k, len }
+    }
+
+    /// Starts the traversal at the first item for which `is_before` returns false, given the
+    /// items are sorted and `is_before` holds for a prefix of them.
+    ///
+    /// This is a binary search down the implicit tree, visiting O(log(len)) items.
+    pub fn lower_bound<E>(
+        len: usize,
+        mut is_before: impl FnMut(usize) -> Result<bool, E>,
+    ) -> Result<Self, E> {
+        let mut k = 1;
+        while k <= len {
+            k = 2 * k + usize::from(is_before(k - 1)?);
+        }
+        // The answer is the last node we went left from; strip the trailing right-turns,
+        // then the left-turn itself. This leaves 0 if every item is before the bound.
+        k >>= k.trailing_ones() + 1;
+        Ok(Self { k, len })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Iterator for EytzingerInOrder {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        if self.k == 0 {
+            return None;
+        }
+        let current = self.k;
+
+        if 2 * self.k < self.len {
+            // Descend into the right subtree, then all the way to the left.
+            self.k = 2 * self.k + 1;
+            while 2 * self.k <= self.len {
+                self.k *= 2;
+            }
+        } else {
+            // Climb up for as long as we're a right child, then once more
+            // to reach the first ancestor we're to the left of.
+            while self.k & 1 == 1 {
+                self.k >>= 1;
+            }
+            self.k >>= 1;
+        }
+
+        Some(current - 1)
+    }
+}
+
 /// This cursor contains the functionality needed in order to traverse
 /// the document, but does not own, nor borrows the data.
 ///
@@ -106,6 +179,7 @@ pub(crate) struct RawCursor {
 }
 
 pub struct MapIter<'a> {
+    raw_cursor: RawCursor,
     index: u32,
     max: u32,
     descriptors: &'a [u8],
@@ -118,6 +192,7 @@ impl RawCursor {
     pub fn ensure_element_type(&self, expected_type: ElementTypeCode) -> Result<(), CursorError> {
         if self.element_type != expected_type {
             return Err(CursorError::WrongElementType {
+                expected: expected_type.as_slice(),
                 actual: self.element_type,
             });
         }
@@ -136,12 +211,41 @@ impl RawCursor {
             }
             _ => 0,
         };
-        // TODO: Make sure we have at least a valid amount of bytes for headers (array/map descriptors, etc.)
 
-        Ok(RawCursor {
+        let cursor = RawCursor {
             element_type,
             child_count,
-        })
+        };
+
+        // Make sure all of the fixed-size headers are present, so that reading a descriptor
+        // can only fail due to the offsets written inside of it.
+        if ELEMENT_TYPE_SIZE + buffer.len() < cursor.header_size() {
+            return Err(CursorError::DocumentTooShort);
+        }
+
+        Ok(cursor)
+    }
+
+    /// Returns the size of the node's headers, including the element type, the child count,
+    /// CHD displacements and child descriptors.
+    ///
+    /// Map keys are not considered part of the header.
+    pub fn header_size(&self) -> usize {
+        let child_count = self.child_count as usize;
+        match self.element_type {
+            ElementTypeCode::Array => (ELEMENT_TYPE_SIZE + U32_SIZE_BYTES)
+                .saturating_add(ARRAY_DESCRIPTOR_SIZE.saturating_mul(child_count)),
+            ElementTypeCode::Map => (ELEMENT_TYPE_SIZE + U32_SIZE_BYTES)
+                .saturating_add(MAP_DESCRIPTOR_SIZE.saturating_mul(child_count)),
+            ElementTypeCode::MapCHD => {
+                let bucket_count = calculate_bucket_count(self.child_count);
+                (ELEMENT_TYPE_SIZE + 2 * U32_SIZE_BYTES)
+                    .saturating_add((2 * U32_SIZE_BYTES).saturating_mul(bucket_count))
+                    .saturating_add(MAP_DESCRIPTOR_SIZE.saturating_mul(child_count))
+            }
+            ElementTypeCode::PackedArray => PACKED_ARRAY_HEADER_SIZE,
+            _ => ELEMENT_TYPE_SIZE,
+        }
     }
 
     /// Returns a subcursor by indexing into a specific array/map item.
@@ -150,6 +254,21 @@ impl RawCursor {
         buffer: &[u8],
         index: usize,
     ) -> Result<(Range<usize>, RawCursor), CursorError> {
+        let range = self.get_range_by_index(buffer, index)?;
+        let buffer = buffer
+            .get(range.clone())
+            .ok_or(CursorError::DocumentTooShort)?;
+        Ok((range, RawCursor::new(buffer)?))
+    }
+
+    /// Returns the range of a specific array/map item, relative to the node's buffer.
+    ///
+    /// The range is read from the descriptors as-is, and is not guaranteed to be within the buffer.
+    pub fn get_range_by_index(
+        &self,
+        buffer: &[u8],
+        index: usize,
+    ) -> Result<Range<usize>, CursorError> {
         let (descriptors_offset, descriptor_size, value_offset_within_header) =
             match self.element_type {
                 ElementTypeCode::Array => {
@@ -167,6 +286,7 @@ impl RawCursor {
                 ),
                 _ => {
                     return Err(CursorError::WrongElementType {
+                        expected: CONTAINER_TYPES,
                         actual: self.element_type,
                     })
                 }
@@ -190,10 +310,26 @@ impl RawCursor {
             item_offset_start..next_item_offset_start
         };
 
-        let buffer = buffer
-            .get(range.clone())
-            .ok_or(CursorError::DocumentTooShort)?;
-        Ok((range, RawCursor::new(buffer)?))
+        self.check_child_range(range, buffer.len())
+    }
+
+    /// Makes sure that a child's range, as read from the descriptors, is non-empty and lies
+    /// between the end of the node's headers and the end of the node.
+    ///
+    /// Requiring children to start after the headers guarantees every child is strictly smaller
+    /// than its parent, so recursive traversals of malformed documents always terminate.
+    fn check_child_range(
+        &self,
+        range: Range<usize>,
+        buffer_length: usize,
+    ) -> Result<Range<usize>, CursorError> {
+        if range.start < self.header_size() || range.start >= range.end {
+            return Err(CursorError::MalformedDescriptor);
+        }
+        if range.end > buffer_length {
+            return Err(CursorError::EmbeddedOffsetOutOfBounds);
+        }
+        Ok(range)
     }
 
     fn get_key_buffer_by_index<'a>(
@@ -223,15 +359,16 @@ impl RawCursor {
         index: usize,
     ) -> Result<&'a str, CursorError> {
         self.get_key_buffer_by_index(buffer, index)
-            .and_then(|key_buf| std::str::from_utf8(key_buf).map_err(|_| CursorError::Utf8Error))
+            .and_then(|key_buf| core::str::from_utf8(key_buf).map_err(|_| CursorError::Utf8Error))
     }
 
-    fn get_map_descriptors<'a>(&self, buffer: &'a [u8]) -> Result<&'a [u8], CursorError> {
+    pub fn get_map_descriptors<'a>(&self, buffer: &'a [u8]) -> Result<&'a [u8], CursorError> {
         let descriptor_start = match self.element_type {
             ElementTypeCode::Map => ELEMENT_TYPE_SIZE + U32_SIZE_BYTES,
             ElementTypeCode::MapCHD => calculate_chd_descriptors_offset(self.child_count),
             _ => {
                 return Err(CursorError::WrongElementType {
+                    expected: MAP_TYPES,
                     actual: self.element_type,
                 })
             }
@@ -268,20 +405,7 @@ impl RawCursor {
         buffer: &[u8],
         key: &str,
     ) -> Result<(usize, Range<usize>, RawCursor), CursorError> {
-        let chd_seed_offset = ELEMENT_TYPE_SIZE + U32_SIZE_BYTES;
-        let chd_displacement_start = chd_seed_offset + U32_SIZE_BYTES;
-        let bucket_count = calculate_bucket_count(self.child_count);
-
-        // Retrieve the seed and displacemente values.
-        let seed = get_u32_at_offset(buffer, chd_seed_offset)? as u64;
-        let hashes = phf_shared::hash(key, &seed);
-        let bucket_index = hashes.g as usize % bucket_count;
-        let bucket_offset = chd_displacement_start + (U32_SIZE_BYTES * 2) * bucket_index;
-        let (d1, d2) = get_u32_pair_at_offset(buffer, bucket_offset)?;
-
-        // Displace to get an item index.
-        let index = phf_shared::displace(hashes.f1, hashes.f2, d1, d2) % self.child_count;
-        let index = index as usize;
+        let index = self.get_chd_index_by_key(buffer, key.as_bytes())?;
 
         // Equate the stored key to the requested key; any non-existent key
         // will also reach *some* index.
@@ -297,6 +421,31 @@ impl RawCursor {
         }
     }
 
+    /// Derives the only index at which `key` may be stored in a CHD map node.
+    ///
+    /// The key stored at the returned index must still be compared against `key`.
+    pub fn get_chd_index_by_key(&self, buffer: &[u8], key: &[u8]) -> Result<usize, CursorError> {
+        self.ensure_element_type(ElementTypeCode::MapCHD)?;
+        if self.child_count == 0 {
+            return Err(CursorError::KeyNotFound);
+        }
+
+        let chd_seed_offset = ELEMENT_TYPE_SIZE + U32_SIZE_BYTES;
+        let chd_displacement_start = chd_seed_offset + U32_SIZE_BYTES;
+        let bucket_count = calculate_bucket_count(self.child_count);
+
+        // Retrieve the seed and displacemente values.
+        let seed = get_u32_at_offset(buffer, chd_seed_offset)? as u64;
+        let hashes = phf_shared::hash(key, &seed);
+        let bucket_index = hashes.g as usize % bucket_count;
+        let bucket_offset = chd_displacement_start + (U32_SIZE_BYTES * 2) * bucket_index;
+        let (d1, d2) = get_u32_pair_at_offset(buffer, bucket_offset)?;
+
+        // Displace to get an item index.
+        let index = phf_shared::displace(hashes.f1, hashes.f2, d1, d2) % self.child_count;
+        Ok(index as usize)
+    }
+
     /// Searches a map item by key, and return the item's index and cursor.
     /// The index can be used with `get_value_by_index`, or saved into a path-vector.
     pub fn get_value_and_index_by_key(
@@ -307,8 +456,12 @@ impl RawCursor {
         if self.element_type == ElementTypeCode::MapCHD {
             return self.get_value_and_index_by_key_chd(buffer, key);
         }
-
-        self.ensure_element_type(ElementTypeCode::Map)?;
+        if self.element_type != ElementTypeCode::Map {
+            return Err(CursorError::WrongElementType {
+                expected: MAP_TYPES,
+                actual: self.element_type,
+            });
+        }
         let descriptors = self.get_map_descriptors(buffer)?;
 
         // Eytzinger scheme uses 1-based indicies. We decrease 1 just before indexing
@@ -327,23 +480,21 @@ impl RawCursor {
                 .ok_or(CursorError::EmbeddedOffsetOutOfBounds)?;
 
             match key.cmp(current_key) {
-                std::cmp::Ordering::Less => k = k * 2,
-                std::cmp::Ordering::Greater => k = k * 2 + 1,
-                std::cmp::Ordering::Equal => {
+                core::cmp::Ordering::Less => k *= 2,
+                core::cmp::Ordering::Greater => k = k * 2 + 1,
+                core::cmp::Ordering::Equal => {
                     // We already have the value offset, we just need to get the offset of the next value / buffer end.
                     let mut value_end = buffer.len();
                     if index + 1 < self.child_count as usize {
                         value_end = get_u32_at_offset(
                             descriptors,
                             MAP_DESCRIPTOR_SIZE * (index + 1) + U32_SIZE_BYTES,
-                        )
-                        .unwrap() as usize;
+                        )? as usize;
                     }
-                    let value_range = value_offset as usize..value_end;
-                    let buffer = buffer
-                        .get(value_range.clone())
-                        .ok_or(CursorError::DocumentTooShort)?;
-                    return RawCursor::new(buffer).map(|cursor| (index, value_range, cursor));
+                    let value_range =
+                        self.check_child_range(value_offset..value_end, buffer.len())?;
+                    return RawCursor::new(&buffer[value_range.clone()])
+                        .map(|cursor| (index, value_range, cursor));
                 }
             }
         }
@@ -357,6 +508,7 @@ impl RawCursor {
         buffer: &'a [u8],
     ) -> Result<MapIter<'a>, CursorError> {
         Ok(MapIter {
+            raw_cursor: self.clone(),
             index: 0,
             max: self.child_count,
             descriptors: self.get_map_descriptors(buffer)?,
@@ -369,7 +521,7 @@ impl RawCursor {
         &self,
         self_range: Range<usize>,
         buffer: &'a [u8],
-    ) -> Result<impl Iterator<Item = Range<usize>> + 'a, CursorError> {
+    ) -> Result<impl Iterator<Item = Result<Range<usize>, CursorError>> + 'a, CursorError> {
         self.ensure_element_type(ElementTypeCode::Array)?;
         let descriptor_start = ELEMENT_TYPE_SIZE + U32_SIZE_BYTES;
         let descriptor_end = descriptor_start + ARRAY_DESCRIPTOR_SIZE * self.child_count as usize;
@@ -387,10 +539,12 @@ impl RawCursor {
             .skip(1)
             .chain(Some(self_range.len() as u32));
 
+        let raw_cursor = self.clone();
         let self_offset = self_range.start;
-        Ok(start_offsets
-            .zip(end_offsets)
-            .map(move |(start, end)| self_offset + start as usize..self_offset + end as usize))
+        Ok(start_offsets.zip(end_offsets).map(move |(start, end)| {
+            let range = raw_cursor.check_child_range(start as usize..end as usize, buffer.len())?;
+            Ok(self_offset + range.start..self_offset + range.end)
+        }))
     }
 }
 
@@ -402,19 +556,25 @@ impl<'a> MapIter<'a> {
             value_offset,
         } = get_map_descriptor(self.descriptors, self.index as usize)?;
 
-        let key = &self.whole_buffer[key_offset..key_offset + key_length];
+        let key = self
+            .whole_buffer
+            .get(key_offset..key_offset + key_length)
+            .ok_or(CursorError::EmbeddedOffsetOutOfBounds)?;
         let key = core::str::from_utf8(key).map_err(|_| CursorError::Utf8Error)?;
 
-        let next_value_offset = if self.index < self.max - 1 {
+        let next_value_offset = if self.index + 1 < self.max {
             let MapDescriptor { value_offset, .. } =
                 get_map_descriptor(self.descriptors, self.index as usize + 1)?;
-            value_offset as usize
+            value_offset
         } else {
             self.whole_buffer.len()
         };
+        let range = self
+            .raw_cursor
+            .check_child_range(value_offset..next_value_offset, self.whole_buffer.len())?;
         Ok((
             key,
-            self.self_offset + value_offset..self.self_offset + next_value_offset,
+            self.self_offset + range.start..self.self_offset + range.end,
         ))
     }
 }
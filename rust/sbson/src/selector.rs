@@ -0,0 +1,663 @@
+use crate::path::Parser;
+use crate::{Cursor, ElementTypeCode, OwnedPathSegment, Path, PathError};
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+
+/// A JSONPath-like query, selecting any number of nodes out of a document.
+///
+/// The supported syntax is a subset of [RFC 9535](https://www.rfc-editor.org/rfc/rfc9535):
+/// - `$` stands for the node the query is applied to, and may be omitted.
+/// - `.key` or `['key']` select a map entry, and `[3]` or `[-1]` an array item.
+/// - `.*` or `[*]` select every child of a map or an array.
+/// - `[start:end:step]` selects a slice of an array, with Python's semantics.
+/// - `..` applies the selector following it to a node and all of its descendants,
+///   as in `..key`, `..*` or `..[0]`.
+/// - `[?(@.path.to.value == 1)]` selects the children for which a comparison holds.
+///   Values can be compared (`==`, `!=`, `<`, `<=`, `>`, `>=`) against numbers, quoted strings,
+///   `true`, `false` and `null`. Without a comparison, the filter checks that the path exists.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Selector {
+    steps: Vec<Step>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Step {
+    /// Whether the selector is applied to all descendants, rather than just to children.
+    descendants: bool,
+    selector: ChildSelector,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum ChildSelector {
+    Key(String),
+    Index(i64),
+    Wildcard,
+    Slice {
+        start: Option<i64>,
+        end: Option<i64>,
+        step: i64,
+    },
+    Filter(Filter),
+}
+
+#[derive(Clone, PartialEq, Debug)]
+struct Filter {
+    path: Path,
+    comparison: Option<(Comparison, Literal)>,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Literal {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+}
+
+impl Selector {
+    pub fn parse(text: &str) -> Result<Self, PathError> {
+        let mut parser = Parser::new(text);
+        let mut steps = vec![];
+        match parser.peek() {
+            Some('$') => parser.position += 1,
+            // Like in dotted paths, the first key may appear without a leading `.`.
+            Some(c) if c != '.' && c != '[' => steps.push(Step {
+                descendants: false,
+                selector: parser.parse_dotted_selector()?,
+            }),
+            _ => {}
+        }
+
+        while let Some(c) = parser.peek() {
+            let step = match c {
+                '.' if parser.text[parser.position..].starts_with("..") => {
+                    parser.position += 2;
+                    let selector = match parser.peek() {
+                        Some('[') => parser.parse_bracketed_selector()?,
+                        _ => parser.parse_dotted_selector()?,
+                    };
+                    Step {
+                        descendants: true,
+                        selector,
+                    }
+                }
+                '.' => {
+                    parser.position += 1;
+                    Step {
+                        descendants: false,
+                        selector: parser.parse_dotted_selector()?,
+                    }
+                }
+                '[' => Step {
+                    descendants: false,
+                    selector: parser.parse_bracketed_selector()?,
+                },
+                _ => return Err(parser.error("expected `.`, `..` or `[`")),
+            };
+            steps.push(step);
+        }
+        Ok(Selector { steps })
+    }
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, token: &str, reason: &'static str) -> Result<(), PathError> {
+        if !self.text[self.position..].starts_with(token) {
+            return Err(self.error(reason));
+        }
+        self.position += token.len();
+        Ok(())
+    }
+
+    /// Parses the selector following a `.` or a `..`.
+    fn parse_dotted_selector(&mut self) -> Result<ChildSelector, PathError> {
+        if self.peek() == Some('*') {
+            self.position += 1;
+            return Ok(ChildSelector::Wildcard);
+        }
+        let key = self.take_until(&['.', '[']);
+        if key.is_empty() {
+            return Err(self.error("expected a key or `*`"));
+        }
+        Ok(ChildSelector::Key(key.into()))
+    }
+
+    fn parse_bracketed_selector(&mut self) -> Result<ChildSelector, PathError> {
+        // Skip the `[`.
+        self.position += 1;
+        self.skip_whitespace();
+        let selector = match self.peek() {
+            Some('*') => {
+                self.position += 1;
+                ChildSelector::Wildcard
+            }
+            Some(quote @ ('"' | '\'')) => {
+                self.position += 1;
+                ChildSelector::Key(self.parse_quoted(quote)?)
+            }
+            Some('?') => {
+                self.position += 1;
+                ChildSelector::Filter(self.parse_filter()?)
+            }
+            _ => self.parse_index_or_slice()?,
+        };
+        self.skip_whitespace();
+        self.expect("]", "expected `]`")?;
+        Ok(selector)
+    }
+
+    fn parse_integer(&mut self) -> Result<Option<i64>, PathError> {
+        self.skip_whitespace();
+        let rest = &self.text[self.position..];
+        let length = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '-'))
+            .unwrap_or(rest.len());
+        if length == 0 {
+            return Ok(None);
+        }
+        let integer = rest[..length]
+            .parse()
+            .map_err(|_| self.error("invalid integer"))?;
+        self.position += length;
+        self.skip_whitespace();
+        Ok(Some(integer))
+    }
+
+    fn parse_index_or_slice(&mut self) -> Result<ChildSelector, PathError> {
+        let start = self.parse_integer()?;
+        if self.peek() != Some(':') {
+            return start
+                .map(ChildSelector::Index)
+                .ok_or_else(|| self.error("expected a selector"));
+        }
+        self.position += 1;
+        let end = self.parse_integer()?;
+        let mut step = 1;
+        if self.peek() == Some(':') {
+            self.position += 1;
+            step = self.parse_integer()?.unwrap_or(1);
+        }
+        Ok(ChildSelector::Slice { start, end, step })
+    }
+
+    /// Parses a filter, following its `?`.
+    fn parse_filter(&mut self) -> Result<Filter, PathError> {
+        let parenthesized = self.peek() == Some('(');
+        if parenthesized {
+            self.position += 1;
+        }
+        self.skip_whitespace();
+        self.expect("@", "expected `@`")?;
+
+        self.segments.clear();
+        loop {
+            match self.peek() {
+                Some('.') => {
+                    self.position += 1;
+                    let key = self.take_until(&['.', '[', ' ', '=', '!', '<', '>', ')', ']']);
+                    if key.is_empty() {
+                        return Err(self.error("expected a key"));
+                    }
+                    self.segments.push(OwnedPathSegment::Key(key.into()));
+                }
+                Some('[') => self.parse_brackets()?,
+                _ => break,
+            }
+        }
+        let path = Path::from(core::mem::take(&mut self.segments));
+
+        self.skip_whitespace();
+        let comparison = match self.parse_comparison() {
+            Some(comparison) => {
+                self.skip_whitespace();
+                Some((comparison, self.parse_literal()?))
+            }
+            None => None,
+        };
+        self.skip_whitespace();
+        if parenthesized {
+            self.expect(")", "expected `)`")?;
+        }
+        Ok(Filter { path, comparison })
+    }
+
+    fn parse_comparison(&mut self) -> Option<Comparison> {
+        let operators = [
+            ("==", Comparison::Eq),
+            ("!=", Comparison::Ne),
+            ("<=", Comparison::Le),
+            (">=", Comparison::Ge),
+            ("<", Comparison::Lt),
+            (">", Comparison::Gt),
+        ];
+        let rest = &self.text[self.position..];
+        let (operator, comparison) = operators
+            .into_iter()
+            .find(|(operator, _)| rest.starts_with(operator))?;
+        self.position += operator.len();
+        Some(comparison)
+    }
+
+    fn parse_literal(&mut self) -> Result<Literal, PathError> {
+        if let Some(quote @ ('"' | '\'')) = self.peek() {
+            self.position += 1;
+            return Ok(Literal::String(self.parse_quoted(quote)?));
+        }
+        let start = self.position;
+        let literal = match self.take_until(&[' ', ')', ']']) {
+            "true" => Literal::Bool(true),
+            "false" => Literal::Bool(false),
+            "null" => Literal::Null,
+            token => match token.parse() {
+                Ok(number) => Literal::Number(number),
+                Err(_) => {
+                    self.position = start;
+                    return Err(self.error("expected a literal"));
+                }
+            },
+        };
+        Ok(literal)
+    }
+}
+
+impl Filter {
+    fn matches<T: Clone + AsRef<[u8]>>(&self, node: &Cursor<T>) -> bool {
+        let target = node.query_path(&self.path).ok();
+        let Some((comparison, literal)) = &self.comparison else {
+            return target.is_some();
+        };
+        // Missing values can't be ordered against anything, so only `!=` holds for them.
+        let ordering = target.and_then(|target| compare(&target, literal));
+        match comparison {
+            Comparison::Eq => ordering == Some(Ordering::Equal),
+            Comparison::Ne => ordering != Some(Ordering::Equal),
+            Comparison::Lt => ordering == Some(Ordering::Less),
+            Comparison::Le => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+            Comparison::Gt => ordering == Some(Ordering::Greater),
+            Comparison::Ge => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        }
+    }
+}
+
+/// Compares a node against a literal, returning `None` if they can't be ordered.
+fn compare<T: Clone + AsRef<[u8]>>(node: &Cursor<T>, literal: &Literal) -> Option<Ordering> {
+    match (node.get_element_type(), literal) {
+        (ElementTypeCode::None, Literal::Null) => Some(Ordering::Equal),
+        (ElementTypeCode::True | ElementTypeCode::False, Literal::Bool(b)) => {
+            (node.get_bool().ok()? == *b).then_some(Ordering::Equal)
+        }
+        (ElementTypeCode::String, Literal::String(s)) => Some(node.get_str().ok()?.cmp(s)),
+        (_, Literal::Number(n)) => {
+            let value = match node.get_element_type() {
+                ElementTypeCode::Double => node.get_double().ok()?,
+                ElementTypeCode::Int32 => node.get_i32().ok()? as f64,
+                ElementTypeCode::UInt32 => node.get_u32().ok()? as f64,
+                ElementTypeCode::Int64 => node.get_i64().ok()? as f64,
+                ElementTypeCode::UInt64 => node.get_u64().ok()? as f64,
+                _ => return None,
+            };
+            value.partial_cmp(n)
+        }
+        _ => None,
+    }
+}
+
+/// The indices of the children picked by a selector.
+struct Indices {
+    next: i64,
+    end: i64,
+    step: i64,
+}
+
+impl Indices {
+    fn range(start: usize, end: usize) -> Self {
+        Indices {
+            next: start as i64,
+            end: end as i64,
+            step: 1,
+        }
+    }
+
+    fn empty() -> Self {
+        Indices::range(0, 0)
+    }
+
+    /// Computes the indices of an array slice, as defined by RFC 9535.
+    fn slice(start: Option<i64>, end: Option<i64>, step: i64, len: i64) -> Self {
+        let normalize = |index: i64| if index < 0 { len + index } else { index };
+        match step.cmp(&0) {
+            Ordering::Equal => Indices::empty(),
+            Ordering::Greater => Indices {
+                next: start.map_or(0, normalize).clamp(0, len),
+                end: end.map_or(len, normalize).clamp(0, len),
+                step,
+            },
+            Ordering::Less => Indices {
+                next: start.map_or(len - 1, normalize).clamp(-1, len - 1),
+                end: end.map_or(-1, normalize).clamp(-1, len - 1),
+                step,
+            },
+        }
+    }
+}
+
+impl Iterator for Indices {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        let in_range = match self.step > 0 {
+            true => self.next < self.end,
+            false => self.next > self.end,
+        };
+        if !in_range {
+            return None;
+        }
+        let index = self.next;
+        // A step past the bounds of an `i64` is also past the end of the slice.
+        self.next = self.next.checked_add(self.step).unwrap_or(self.end);
+        Some(index as usize)
+    }
+}
+
+fn is_map(element_type: ElementTypeCode) -> bool {
+    matches!(element_type, ElementTypeCode::Map | ElementTypeCode::MapCHD)
+}
+
+fn candidates<T: Clone + AsRef<[u8]>>(selector: &ChildSelector, cursor: &Cursor<T>) -> Indices {
+    let element_type = cursor.get_element_type();
+    let is_map = is_map(element_type);
+    let is_array = element_type == ElementTypeCode::Array;
+    let len = cursor.get_children_count();
+    match selector {
+        ChildSelector::Key(key) if is_map => match cursor.get_value_and_index_by_key(key) {
+            Ok((index, _)) => Indices::range(index, index + 1),
+            Err(_) => Indices::empty(),
+        },
+        ChildSelector::Index(index) if is_array => {
+            let index = if *index < 0 {
+                len as i64 + index
+            } else {
+                *index
+            };
+            match usize::try_from(index) {
+                Ok(index) if index < len => Indices::range(index, index + 1),
+                _ => Indices::empty(),
+            }
+        }
+        ChildSelector::Wildcard | ChildSelector::Filter(_) if is_map || is_array => {
+            Indices::range(0, len)
+        }
+        ChildSelector::Slice { start, end, step } if is_array => {
+            Indices::slice(*start, *end, *step, len as i64)
+        }
+        _ => Indices::empty(),
+    }
+}
+
+/// A node being visited by a [`Selection`].
+struct Frame<T> {
+    /// The index of the step applied to this node.
+    step: usize,
+    cursor: Cursor<T>,
+    path: Vec<OwnedPathSegment>,
+    /// The children picked by the step's selector, which continue on to the next step.
+    matches: Indices,
+    /// For `..` steps, all of the children, to which the same step is applied again.
+    descendants: Indices,
+}
+
+/// An iterator over the nodes matched by a [`Selector`], returned by [`Cursor::select`].
+pub struct Selection<'s, T> {
+    selector: &'s Selector,
+    root: Option<Cursor<T>>,
+    stack: Vec<Frame<T>>,
+}
+
+impl<T: Clone + AsRef<[u8]>> Selection<'_, T> {
+    fn push(&mut self, step: usize, cursor: Cursor<T>, path: Vec<OwnedPathSegment>) {
+        let Step {
+            descendants,
+            selector,
+        } = &self.selector.steps[step];
+        let element_type = cursor.get_element_type();
+        let descendants =
+            if *descendants && (is_map(element_type) || element_type == ElementTypeCode::Array) {
+                Indices::range(0, cursor.get_children_count())
+            } else {
+                Indices::empty()
+            };
+        self.stack.push(Frame {
+            step,
+            matches: candidates(selector, &cursor),
+            descendants,
+            cursor,
+            path,
+        });
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> Iterator for Selection<'_, T> {
+    type Item = (Path, Cursor<T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(root) = self.root.take() {
+            if self.selector.steps.is_empty() {
+                return Some((Path::default(), root));
+            }
+            self.push(0, root, vec![]);
+        }
+
+        loop {
+            let frame = self.stack.last_mut()?;
+            let (index, next_step) = if let Some(index) = frame.matches.next() {
+                (index, frame.step + 1)
+            } else if let Some(index) = frame.descendants.next() {
+                (index, frame.step)
+            } else {
+                self.stack.pop();
+                continue;
+            };
+
+            // Malformed children are skipped, like `iter_map` and `iter_array` do.
+            let Ok(child) = frame.cursor.get_value_by_index(index) else {
+                continue;
+            };
+            if next_step > frame.step {
+                if let ChildSelector::Filter(filter) = &self.selector.steps[frame.step].selector {
+                    if !filter.matches(&child) {
+                        continue;
+                    }
+                }
+            }
+            let segment = match frame.cursor.get_element_type() {
+                ElementTypeCode::Array => OwnedPathSegment::Index(index),
+                _ => match frame.cursor.get_key_by_index(index) {
+                    Ok(key) => OwnedPathSegment::Key(key.into()),
+                    Err(_) => continue,
+                },
+            };
+            let mut path = frame.path.clone();
+            path.push(segment);
+
+            if next_step == self.selector.steps.len() {
+                return Some((Path::from(path), child));
+            }
+            self.push(next_step, child, path);
+        }
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> Cursor<T> {
+    /// Lazily finds every node matching the given selector, along with its path from this node.
+    ///
+    /// Map entries are visited in the order they're stored in, and malformed nodes are skipped.
+    pub fn select<'s>(&self, selector: &'s Selector) -> Selection<'s, T> {
+        Selection {
+            selector,
+            root: Some(self.clone()),
+            stack: vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize_with;
+    use crate::serializer::SerializationOptions;
+    use serde_json::json;
+
+    fn document(chd_threshold: usize) -> Vec<u8> {
+        let json = json!({
+            "top": {
+                "item_0001": {"something": [0, 1, 2, 3, 4], "enabled": true, "name": "a"},
+                "item_0002": {"something": [10, 11, 12, 13], "enabled": false, "name": "b"},
+                "other": {"something": [20, 21, 22, 23]},
+            },
+            "list": [{"id": 1, "tags": ["x"]}, {"id": 2}, {"id": 3.5, "tags": []}],
+        });
+        serialize_with(&json, &SerializationOptions { chd_threshold })
+    }
+
+    /// Returns the selected nodes as sorted JSON Pointers and their values.
+    fn select(buffer: &[u8], query: &str) -> Vec<(String, serde_json::Value)> {
+        let cursor = Cursor::new(buffer).unwrap();
+        let selector = Selector::parse(query).unwrap();
+        let mut results: Vec<_> = cursor
+            .select(&selector)
+            .map(|(path, node)| {
+                let value = crate::from_bytes(node.scoped_buffer()).unwrap();
+                (path.to_string(), value)
+            })
+            .collect();
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+        results
+    }
+
+    fn values(buffer: &[u8], query: &str) -> Vec<serde_json::Value> {
+        select(buffer, query)
+            .into_iter()
+            .map(|(_path, value)| value)
+            .collect()
+    }
+
+    #[test]
+    fn test_select() {
+        for chd_threshold in [1, 100] {
+            let buffer = document(chd_threshold);
+            assert_eq!(
+                vec![
+                    ("/top/item_0001/something/3".into(), 3.into()),
+                    ("/top/item_0002/something/3".into(), 13.into()),
+                    ("/top/other/something/3".into(), 23.into()),
+                ],
+                select(&buffer, "$.top.*.something[3]")
+            );
+            assert_eq!(
+                select(&buffer, "$.top.*.something[3]"),
+                select(&buffer, "..something[3]")
+            );
+            assert_eq!(
+                vec![json!("a")],
+                values(&buffer, "top[?(@.enabled == true)].name")
+            );
+            assert_eq!(
+                vec![json!("b")],
+                values(&buffer, "top[?@.enabled == false].name")
+            );
+            assert_eq!(
+                vec![json!("b")],
+                values(&buffer, "top[?(@.name > 'a')].name")
+            );
+            // Entries lacking the compared key only match `!=`.
+            assert_eq!(3, values(&buffer, "top[?(@.enabled != 1)]").len());
+            assert_eq!(
+                vec![json!(1), json!(3.5)],
+                values(&buffer, "list[?(@.tags)].id")
+            );
+            assert_eq!(
+                vec![json!(2), json!(3.5)],
+                values(&buffer, "list[?(@.id >= 2)].id")
+            );
+            assert_eq!(
+                vec![json!(1)],
+                values(&buffer, "list[?(@.tags[0] == \"x\")].id")
+            );
+            assert_eq!(vec![json!(3.5)], values(&buffer, "list[-1].id"));
+            assert_eq!(
+                vec![json!(1), json!(2), json!(3.5)],
+                values(&buffer, "$..id")
+            );
+            assert_eq!(3, values(&buffer, "list[*]").len());
+            assert_eq!(2, values(&buffer, "$.*").len());
+            assert!(values(&buffer, "top.missing.*").is_empty());
+            assert!(values(&buffer, "list.id").is_empty());
+
+            let root = select(&buffer, "$");
+            assert_eq!(1, root.len());
+            assert_eq!("", root[0].0);
+        }
+    }
+
+    #[test]
+    fn test_slices() {
+        let buffer = document(100);
+        let slice = |query: &str| values(&buffer, &format!("top.item_0001.something{query}"));
+        assert_eq!(vec![json!(1), json!(3)], slice("[1:4:2]"));
+        assert_eq!(vec![json!(3), json!(4)], slice("[-2:]"));
+        assert_eq!(vec![json!(0), json!(1)], slice("[:2]"));
+        assert_eq!(5, slice("[::-1]").len());
+        assert_eq!(5, slice("[:]").len());
+        assert!(slice("[::0]").is_empty());
+        assert!(slice("[3:1]").is_empty());
+        assert_eq!(vec![json!(1)], slice("[1::9223372036854775807]"));
+        assert_eq!(vec![json!(4)], slice("[::-9223372036854775808]"));
+
+        // Reverse slices are yielded in reverse order.
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        let selector = Selector::parse("top.item_0001.something[3:0:-2]").unwrap();
+        let items: Vec<_> = cursor
+            .select(&selector)
+            .map(|(_path, node)| node.get_u64().unwrap())
+            .collect();
+        assert_eq!(vec![3, 1], items);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let cases = [
+            ("top.", 4, "expected a key or `*`"),
+            ("top[", 4, "expected a selector"),
+            ("top[1", 5, "expected `]`"),
+            ("top[1:2:3:4]", 9, "expected `]`"),
+            ("top[?(@.a == )]", 13, "expected a literal"),
+            ("top[?(a)]", 6, "expected `@`"),
+            ("top[?(@.a == 1]", 14, "expected `)`"),
+            ("$x", 1, "expected `.`, `..` or `[`"),
+        ];
+        for (text, offset, reason) in cases {
+            assert_eq!(
+                Err(PathError::Syntax { offset, reason }),
+                Selector::parse(text),
+                "{text}"
+            );
+        }
+    }
+}
@@ -1,20 +1,51 @@
-#![allow(unused_variables)]
-
-use std::fmt::Debug;
-
-use crate::{Cursor, CursorError, ElementTypeCode};
+use crate::packed::{packed_item_size, split_packed_array};
+use crate::raw_cursor::get_byte_array_at;
+use crate::{Cursor, CursorError, ElementTypeCode, CONTAINER_TYPES, MAP_TYPES, RECURSION_LIMIT};
+use alloc::string::ToString;
+use core::result;
 use serde::{
-    de::{value::StrDeserializer, MapAccess, SeqAccess, Visitor},
-    Deserialize,
+    de::{
+        value::BorrowedStrDeserializer, EnumAccess, Error as _, IntoDeserializer, MapAccess,
+        SeqAccess, VariantAccess, Visitor,
+    },
+    forward_to_deserialize_any, Deserialize,
 };
 
-type Result<T> = std::result::Result<T, CursorError>;
+type Result<T> = result::Result<T, CursorError>;
+
+const INTEGER_TYPES: &[ElementTypeCode] = &[
+    ElementTypeCode::Int32,
+    ElementTypeCode::UInt32,
+    ElementTypeCode::Int64,
+    ElementTypeCode::UInt64,
+];
+
+#[derive(Clone, Debug, Default)]
+pub struct DeserializationOptions {
+    /// Deserialize structs by looking up each of their declared fields by key,
+    /// instead of walking over every entry of the map.
+    ///
+    /// This makes deserializing a small struct out of a large map cost
+    /// `O(fields * log(n))` instead of `O(n)`, but since undeclared keys are never
+    /// visited, `#[serde(deny_unknown_fields)]` can't reject them.
+    pub lookup_struct_fields: bool,
+}
 
 pub fn from_bytes<'a, T>(input: &'a [u8]) -> Result<T>
 where
     T: Deserialize<'a>,
 {
-    let mut deserializer = Deserializer::from_bytes(input)?;
+    from_bytes_with_options(input, &DeserializationOptions::default())
+}
+
+pub fn from_bytes_with_options<'a, T>(
+    input: &'a [u8],
+    options: &DeserializationOptions,
+) -> Result<T>
+where
+    T: Deserialize<'a>,
+{
+    let mut deserializer = Deserializer::from_bytes(input)?.with_options(options.clone());
     let value = T::deserialize(&mut deserializer)?;
 
     Ok(value)
@@ -22,56 +53,104 @@ where
 
 pub struct Deserializer<'de> {
     cursor: Cursor<&'de [u8]>,
+    options: DeserializationOptions,
+    /// The amount of nesting levels allowed below the current node.
+    remaining_depth: usize,
 }
 
 impl<'de> Deserializer<'de> {
     pub fn from_bytes(input: &'de [u8]) -> Result<Self> {
+        Ok(Self::from_cursor(Cursor::new(input)?))
+    }
+
+    /// Creates a deserializer for the node pointed to by the given cursor.
+    pub fn from_cursor(cursor: Cursor<&'de [u8]>) -> Self {
+        Self {
+            cursor,
+            options: DeserializationOptions::default(),
+            remaining_depth: RECURSION_LIMIT,
+        }
+    }
+
+    pub fn with_options(mut self, options: DeserializationOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Creates a deserializer for a child of the current node.
+    fn child(&self, cursor: Cursor<&'de [u8]>) -> Result<Self> {
+        if self.remaining_depth == 0 {
+            return Err(CursorError::RecursionLimitExceeded);
+        }
         Ok(Self {
-            cursor: Cursor::new(input)?,
+            cursor,
+            options: self.options.clone(),
+            remaining_depth: self.remaining_depth - 1,
         })
     }
-}
 
-impl std::fmt::Display for CursorError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        <CursorError as Debug>::fmt(self, f)
+    fn wrong_element_type(&self, expected: &'static [ElementTypeCode]) -> CursorError {
+        CursorError::WrongElementType {
+            expected,
+            actual: self.cursor.get_element_type(),
+        }
+    }
+
+    /// Visits an integer node of any width.
+    ///
+    /// Range checks are left to the visitor, so that e.g. a `u8` can be read from
+    /// a `UInt32` node, or an `i32` from an `Int64` node written by another encoder.
+    fn deserialize_integer<V>(&mut self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Int32 => visitor.visit_i32(self.cursor.get_i32()?),
+            ElementTypeCode::UInt32 => visitor.visit_u32(self.cursor.get_u32()?),
+            ElementTypeCode::Int64 => visitor.visit_i64(self.cursor.get_i64()?),
+            ElementTypeCode::UInt64 => visitor.visit_u64(self.cursor.get_u64()?),
+            _ => Err(self.wrong_element_type(INTEGER_TYPES)),
+        }
     }
 }
 
+// With `std`, this is `std::error::Error`, which is implemented next to `CursorError`.
+#[cfg(not(feature = "std"))]
 impl serde::de::StdError for CursorError {}
 
 impl serde::de::Error for CursorError {
     fn custom<T>(msg: T) -> Self
     where
-        T: std::fmt::Display,
+        T: core::fmt::Display,
     {
-        todo!()
+        CursorError::Custom(msg.to_string())
     }
 }
 
-impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
+impl<'de> serde::de::Deserializer<'de> for &mut Deserializer<'de> {
     // TODO: Maybe something a bit more serde-specific.
     type Error = CursorError;
     fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let f = match self.cursor.get_element_type() {
-            crate::ElementTypeCode::Double => todo!(),
-            crate::ElementTypeCode::String => visitor.visit_str(self.cursor.get_str()?)?,
-            crate::ElementTypeCode::Map => todo!(),
-            crate::ElementTypeCode::Array => todo!(),
-            crate::ElementTypeCode::Binary => visitor.visit_bytes(self.cursor.get_binary()?)?,
-            crate::ElementTypeCode::False => visitor.visit_bool(false)?,
-            crate::ElementTypeCode::True => visitor.visit_bool(true)?,
-            crate::ElementTypeCode::None => visitor.visit_none()?,
-            crate::ElementTypeCode::Int32 => visitor.visit_i32(self.cursor.get_i32()?)?,
-            crate::ElementTypeCode::UInt32 => todo!(),
-            crate::ElementTypeCode::Int64 => visitor.visit_i64(self.cursor.get_i64()?)?,
-            crate::ElementTypeCode::UInt64 => todo!(),
-            crate::ElementTypeCode::MapCHD => todo!(),
-        };
-        Ok(f)
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Double => visitor.visit_f64(self.cursor.get_double()?),
+            ElementTypeCode::String => visitor.visit_borrowed_str(self.cursor.get_storage_str()?),
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => self.deserialize_map(visitor),
+            ElementTypeCode::Array | ElementTypeCode::PackedArray => self.deserialize_seq(visitor),
+            ElementTypeCode::Binary => {
+                visitor.visit_borrowed_bytes(self.cursor.get_storage_binary()?)
+            }
+            ElementTypeCode::False => visitor.visit_bool(false),
+            ElementTypeCode::True => visitor.visit_bool(true),
+            // Like JSON's `null`, this can stand for both `None` and `()`.
+            ElementTypeCode::None => visitor.visit_unit(),
+            ElementTypeCode::Int32
+            | ElementTypeCode::UInt32
+            | ElementTypeCode::Int64
+            | ElementTypeCode::UInt64 => self.deserialize_integer(visitor),
+        }
     }
 
     fn deserialize_bool<V>(self, visitor: V) -> Result<V::Value>
@@ -85,14 +164,14 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i32(self.cursor.get_i32()?)
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_i64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_i64(self.cursor.get_i64()?)
+        self.deserialize_integer(visitor)
     }
 
     // Refer to the "Understanding deserializer lifetimes" page for information
@@ -111,199 +190,266 @@ impl<'de, 'a> serde::de::Deserializer<'de> for &'a mut Deserializer<'de> {
         visitor.visit_borrowed_str(self.cursor.get_storage_str()?)
     }
 
-    // The `Serializer` implementation on the previous page serialized byte
-    // arrays as JSON arrays of bytes. Handle that representation here.
     fn deserialize_bytes<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_bytes(self.cursor.get_binary()?)
+        visitor.visit_borrowed_bytes(self.cursor.get_storage_binary()?)
     }
 
     fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        let element_type = self.cursor.get_element_type();
-        if element_type != ElementTypeCode::Array {
-            return Err(CursorError::WrongElementType {
-                actual: element_type,
-            });
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Array => visitor.visit_seq(ArrayIteator { de: self, index: 0 }),
+            ElementTypeCode::PackedArray => {
+                let cursor = &self.cursor;
+                let payload = &cursor.buffer[cursor.range.start + 1..cursor.range.end];
+                let (item_type, items) = split_packed_array(payload)?;
+                let item_size = packed_item_size(item_type).unwrap_or(1);
+                visitor.visit_seq(PackedItems {
+                    item_type,
+                    items: items.chunks_exact(item_size),
+                })
+            }
+            actual => Err(CursorError::WrongElementType {
+                expected: &[ElementTypeCode::Array, ElementTypeCode::PackedArray],
+                actual,
+            }),
         }
-        visitor.visit_seq(ArrayIteator { de: self, index: 0 })
     }
 
     fn deserialize_i8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_i16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u8<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u16<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_u64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_integer(visitor)
     }
 
     fn deserialize_f32<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_f64(visitor)
     }
 
     fn deserialize_f64<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Double => visitor.visit_f64(self.cursor.get_double()?),
+            _ => self.deserialize_integer(visitor),
+        }
     }
 
     fn deserialize_char<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_str(visitor)
     }
 
     fn deserialize_byte_buf<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_bytes(visitor)
     }
 
     fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        match self.cursor.get_element_type() {
+            ElementTypeCode::None => visitor.visit_none(),
+            _ => visitor.visit_some(self),
+        }
     }
 
     fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.cursor.get_none()?;
+        visitor.visit_unit()
     }
 
-    fn deserialize_unit_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
+    fn deserialize_unit_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_unit(visitor)
     }
 
     fn deserialize_newtype_struct<V>(self, name: &'static str, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        if name == crate::lazy::LAZY_TOKEN {
+            let node = &self.cursor.buffer[self.cursor.range.clone()];
+            return visitor.visit_seq(crate::lazy::Handshake::new(node, &self.options));
+        }
+        visitor.visit_newtype_struct(self)
     }
 
     fn deserialize_tuple<V>(self, len: usize, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        if len != self.cursor.get_children_count() {
-            println!(
-                "{} != {} ({:?})",
-                len,
-                self.cursor.get_children_count(),
-                self.cursor.get_element_type()
-            );
-            //// HAAAA WRONG ERRORR HAHAHAHAHHA (TODO)
-            Err(CursorError::ItemIndexOutOfBounds)
-        } else {
-            self.deserialize_seq(visitor)
+        let children_count = match self.cursor.get_element_type() {
+            ElementTypeCode::Array => self.cursor.get_children_count(),
+            ElementTypeCode::PackedArray => {
+                let (item_type, items) = split_packed_array(self.cursor.payload_scoped_buffer())?;
+                items.len() / packed_item_size(item_type).unwrap_or(1)
+            }
+            actual => {
+                return Err(CursorError::WrongElementType {
+                    expected: &[ElementTypeCode::Array, ElementTypeCode::PackedArray],
+                    actual,
+                })
+            }
+        };
+        if len != children_count {
+            return Err(CursorError::invalid_length(children_count, &visitor));
         }
+        self.deserialize_seq(visitor)
     }
 
     fn deserialize_tuple_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         len: usize,
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_tuple(len, visitor)
     }
 
     fn deserialize_map<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_map(MapIterator { de: self, index: 0 })
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                visitor.visit_map(MapIterator { de: self, index: 0 })
+            }
+            _ => Err(self.wrong_element_type(MAP_TYPES)),
+        }
     }
 
     fn deserialize_struct<V>(
         self,
-        name: &'static str,
+        _name: &'static str,
         fields: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        visitor.visit_map(MapIterator { de: self, index: 0 })
+        match self.cursor.get_element_type() {
+            ElementTypeCode::Map | ElementTypeCode::MapCHD if self.options.lookup_struct_fields => {
+                visitor.visit_map(StructFieldLookup {
+                    de: self,
+                    fields,
+                    value: None,
+                })
+            }
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                visitor.visit_map(MapIterator { de: self, index: 0 })
+            }
+            ElementTypeCode::Array => self.deserialize_seq(visitor),
+            _ => Err(self.wrong_element_type(CONTAINER_TYPES)),
+        }
     }
 
+    /// Enums are expected in their externally tagged form: either a string holding
+    /// the name of a unit variant, or a single-item map of `{variant: value}`.
     fn deserialize_enum<V>(
         self,
-        name: &'static str,
-        variants: &'static [&'static str],
+        _name: &'static str,
+        _variants: &'static [&'static str],
         visitor: V,
     ) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        match self.cursor.get_element_type() {
+            ElementTypeCode::String => {
+                visitor.visit_enum(BorrowedStrDeserializer::new(self.cursor.get_storage_str()?))
+            }
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                let children_count = self.cursor.get_children_count();
+                if children_count != 1 {
+                    return Err(CursorError::invalid_length(
+                        children_count,
+                        &"a map with a single variant",
+                    ));
+                }
+                let variant = self.cursor.get_storage_key_by_index(0)?;
+                let value = self.child(self.cursor.get_value_by_index(0)?)?;
+                visitor.visit_enum(VariantAccessor { variant, de: value })
+            }
+            _ => Err(self.wrong_element_type(&[
+                ElementTypeCode::String,
+                ElementTypeCode::Map,
+                ElementTypeCode::MapCHD,
+            ])),
+        }
     }
 
     fn deserialize_identifier<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        self.deserialize_str(visitor)
     }
 
+    /// Since nodes can be skipped over without parsing them, there's no need to look
+    /// at ignored values at all.
     fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value>
     where
         V: Visitor<'de>,
     {
-        todo!()
+        visitor.visit_unit()
     }
 }
 
@@ -325,7 +471,48 @@ impl<'de, 'a> SeqAccess<'de> for ArrayIteator<'a, 'de> {
         let index = self.index;
         self.index += 1;
         let cursor = self.de.cursor.get_value_by_index(index)?;
-        seed.deserialize(&mut Deserializer { cursor }).map(Some)
+        seed.deserialize(&mut self.de.child(cursor)?).map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.de.cursor.get_children_count() - self.index)
+    }
+}
+
+/// Visits the items of a packed array as if they were separate nodes.
+struct PackedItems<'de> {
+    item_type: ElementTypeCode,
+    items: core::slice::ChunksExact<'de, u8>,
+}
+
+impl<'de> SeqAccess<'de> for PackedItems<'de> {
+    type Error = CursorError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        let Some(item) = self.items.next() else {
+            return Ok(None);
+        };
+        let value = match self.item_type {
+            ElementTypeCode::Double => seed
+                .deserialize(f64::from_le_bytes(get_byte_array_at(item, 0)?).into_deserializer()),
+            ElementTypeCode::Int32 => seed
+                .deserialize(i32::from_le_bytes(get_byte_array_at(item, 0)?).into_deserializer()),
+            ElementTypeCode::UInt32 => seed
+                .deserialize(u32::from_le_bytes(get_byte_array_at(item, 0)?).into_deserializer()),
+            ElementTypeCode::Int64 => seed
+                .deserialize(i64::from_le_bytes(get_byte_array_at(item, 0)?).into_deserializer()),
+            ElementTypeCode::UInt64 => seed
+                .deserialize(u64::from_le_bytes(get_byte_array_at(item, 0)?).into_deserializer()),
+            item_type => Err(CursorError::InvalidElementType(item_type as u8)),
+        };
+        value.map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
     }
 }
 
@@ -340,13 +527,12 @@ impl<'de, 'a> MapAccess<'de> for MapIterator<'a, 'de> {
     fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
     where
         K: serde::de::DeserializeSeed<'de>,
-        // K::Value= &'de str
     {
         if self.index >= self.de.cursor.get_children_count() {
             return Ok(None);
         }
-        let key = self.de.cursor.get_key_by_index(self.index)?;
-        seed.deserialize(StrDeserializer::new(key)).map(Some)
+        let key = self.de.cursor.get_storage_key_by_index(self.index)?;
+        seed.deserialize(MapKeyDeserializer { key }).map(Some)
     }
 
     fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
@@ -358,13 +544,180 @@ impl<'de, 'a> MapAccess<'de> for MapIterator<'a, 'de> {
         }
         let cursor = self.de.cursor.get_value_by_index(self.index)?;
         self.index += 1;
-        seed.deserialize(&mut Deserializer { cursor })
+        seed.deserialize(&mut self.de.child(cursor)?)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.de.cursor.get_children_count() - self.index)
+    }
+}
+
+/// Visits the declared fields of a struct by looking each of them up in the map,
+/// skipping fields that are missing and never touching undeclared keys.
+struct StructFieldLookup<'a, 'de: 'a> {
+    de: &'a mut Deserializer<'de>,
+    /// The fields that weren't looked up yet.
+    fields: &'static [&'static str],
+    value: Option<Cursor<&'de [u8]>>,
+}
+
+impl<'de, 'a> MapAccess<'de> for StructFieldLookup<'a, 'de> {
+    type Error = CursorError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>>
+    where
+        K: serde::de::DeserializeSeed<'de>,
+    {
+        while let Some((field, rest)) = self.fields.split_first() {
+            self.fields = rest;
+            match self.de.cursor.get_value_by_key(field) {
+                Ok(value) => {
+                    self.value = Some(value);
+                    return seed
+                        .deserialize(MapKeyDeserializer { key: field })
+                        .map(Some);
+                }
+                Err(CursorError::KeyNotFound) => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let cursor = self.value.take().ok_or(CursorError::ItemIndexOutOfBounds)?;
+        seed.deserialize(&mut self.de.child(cursor)?)
+    }
+}
+
+/// Accesses the content of an enum stored as `{variant: value}`.
+struct VariantAccessor<'de> {
+    variant: &'de str,
+    de: Deserializer<'de>,
+}
+
+impl<'de> EnumAccess<'de> for VariantAccessor<'de> {
+    type Error = CursorError;
+    type Variant = Self;
+
+    fn variant_seed<V>(self, seed: V) -> Result<(V::Value, Self)>
+    where
+        V: serde::de::DeserializeSeed<'de>,
+    {
+        let variant = seed.deserialize(BorrowedStrDeserializer::new(self.variant))?;
+        Ok((variant, self))
+    }
+}
+
+impl<'de> VariantAccess<'de> for VariantAccessor<'de> {
+    type Error = CursorError;
+
+    fn unit_variant(mut self) -> Result<()> {
+        <()>::deserialize(&mut self.de)
+    }
+
+    fn newtype_variant_seed<T>(mut self, seed: T) -> Result<T::Value>
+    where
+        T: serde::de::DeserializeSeed<'de>,
+    {
+        seed.deserialize(&mut self.de)
+    }
+
+    fn tuple_variant<V>(mut self, len: usize, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        serde::de::Deserializer::deserialize_tuple(&mut self.de, len, visitor)
+    }
+
+    fn struct_variant<V>(mut self, fields: &'static [&'static str], visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        serde::de::Deserializer::deserialize_struct(&mut self.de, "", fields, visitor)
+    }
+}
+
+/// Deserializes map keys.
+///
+/// Keys are always strings, but like `serde_json`, integer keys are parsed out of them
+/// so that maps such as `HashMap<u32, T>` can be deserialized.
+struct MapKeyDeserializer<'de> {
+    key: &'de str,
+}
+
+macro_rules! deserialize_parsed_key {
+    ($method:ident, $visit:ident) => {
+        fn $method<V>(self, visitor: V) -> Result<V::Value>
+        where
+            V: Visitor<'de>,
+        {
+            match self.key.parse() {
+                Ok(value) => visitor.$visit(value),
+                Err(_) => visitor.visit_borrowed_str(self.key),
+            }
+        }
+    };
+}
+
+impl<'de> serde::de::Deserializer<'de> for MapKeyDeserializer<'de> {
+    type Error = CursorError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_borrowed_str(self.key)
+    }
+
+    deserialize_parsed_key!(deserialize_i8, visit_i8);
+    deserialize_parsed_key!(deserialize_i16, visit_i16);
+    deserialize_parsed_key!(deserialize_i32, visit_i32);
+    deserialize_parsed_key!(deserialize_i64, visit_i64);
+    deserialize_parsed_key!(deserialize_u8, visit_u8);
+    deserialize_parsed_key!(deserialize_u16, visit_u16);
+    deserialize_parsed_key!(deserialize_u32, visit_u32);
+    deserialize_parsed_key!(deserialize_u64, visit_u64);
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V>(self, _name: &'static str, visitor: V) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_enum(BorrowedStrDeserializer::new(self.key))
+    }
+
+    forward_to_deserialize_any! {
+        bool i128 u128 f32 f64 char str string bytes byte_buf unit unit_struct seq tuple
+        tuple_struct map struct identifier ignored_any
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     #[test]
     fn test_serde_simple() {
@@ -395,6 +748,19 @@ mod tests {
         assert_eq!(Ok([0i32, 16]), from_bytes::<[i32; 2]>(&buf));
     }
 
+    #[test]
+    fn test_serde_packed_array() {
+        use crate::serializer::fixtures::serialize;
+        use crate::serializer::Packed;
+
+        let buf = serialize(&Packed(&[0.5f64, -1.0, 2.0][..]));
+        assert_eq!(Ok(vec![0.5, -1.0, 2.0]), from_bytes::<Vec<f64>>(&buf));
+        assert_eq!(Ok([0.5, -1.0, 2.0]), from_bytes::<[f64; 3]>(&buf));
+        assert_eq!(Ok((0.5, -1.0, 2.0)), from_bytes::<(f64, f64, f64)>(&buf));
+        assert!(from_bytes::<[f64; 2]>(&buf).is_err());
+        assert!(from_bytes::<[f64; 3]>(b"\x0a").is_err());
+    }
+
     #[test]
     fn test_serde_struct() {
         let buf = [
@@ -410,4 +776,197 @@ mod tests {
         }
         assert_eq!(Ok(Florp { a: 0, b: 1 }), from_bytes(&buf));
     }
+
+    fn round_trip<T>(value: &T) -> T
+    where
+        T: serde::Serialize + serde::de::DeserializeOwned,
+    {
+        from_bytes(&crate::to_vec(value).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_serde_primitives() {
+        assert_eq!(Ok(Some(1i32)), from_bytes(b"\x10\x01\x00\x00\x00"));
+        assert_eq!(Ok(None::<i32>), from_bytes(b"\x0a"));
+        assert_eq!(Ok(()), from_bytes(b"\x0a"));
+        assert_eq!(Ok(7u8), from_bytes(b"\x11\x07\x00\x00\x00"));
+        assert_eq!(
+            Ok(7i32),
+            from_bytes(b"\x12\x07\x00\x00\x00\x00\x00\x00\x00")
+        );
+        assert_eq!(Ok(7.0f64), from_bytes(b"\x10\x07\x00\x00\x00"));
+        assert_eq!(Ok("ab"), from_bytes::<&str>(b"\x02ab\x00"));
+        assert_eq!(Ok('a'), from_bytes(b"\x02a\x00"));
+        assert_eq!(Ok(&b"ab\x00"[..]), from_bytes::<&[u8]>(b"\x05ab\x00"));
+
+        assert!(from_bytes::<u8>(b"\x11\x00\x01\x00\x00").is_err());
+        assert_eq!(
+            Err(CursorError::WrongElementType {
+                expected: INTEGER_TYPES,
+                actual: ElementTypeCode::String
+            }),
+            from_bytes::<i32>(b"\x02ab\x00")
+        );
+        assert!(from_bytes::<(i32, i32, i32)>(&crate::to_vec(&(1, 2)).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_serde_enums() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        enum External {
+            Unit,
+            Newtype(i32),
+            Tuple(i32, String),
+            Struct { a: bool },
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(tag = "type")]
+        enum Internal {
+            A { value: i64 },
+            B,
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        #[serde(tag = "t", content = "c")]
+        enum Adjacent {
+            A(Vec<i32>),
+            B,
+        }
+
+        for value in [
+            External::Unit,
+            External::Newtype(5),
+            External::Tuple(5, "five".into()),
+            External::Struct { a: true },
+        ] {
+            assert_eq!(value, round_trip(&value));
+        }
+        for value in [Internal::A { value: -3 }, Internal::B] {
+            assert_eq!(value, round_trip(&value));
+        }
+        for value in [Adjacent::A(vec![1, 2]), Adjacent::B] {
+            assert_eq!(value, round_trip(&value));
+        }
+    }
+
+    #[test]
+    fn test_serde_struct_shapes() {
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Flattened {
+            id: u32,
+            #[serde(flatten)]
+            rest: HashMap<String, i64>,
+        }
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Unit;
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Wrapper(Option<i32>);
+
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Pair(i32, bool);
+
+        let flattened = Flattened {
+            id: 1,
+            rest: HashMap::from([("x".into(), 1), ("y".into(), 2)]),
+        };
+        assert_eq!(flattened, round_trip(&flattened));
+        assert_eq!(Wrapper(Some(2)), round_trip(&Wrapper(Some(2))));
+        assert_eq!(Unit, round_trip(&Unit));
+        assert_eq!(Wrapper(None), round_trip(&Wrapper(None)));
+        assert_eq!(Pair(3, true), round_trip(&Pair(3, true)));
+
+        let keyed = HashMap::from([(1u32, "one".to_string()), (20, "twenty".into())]);
+        assert_eq!(keyed, round_trip(&keyed));
+
+        #[derive(serde::Serialize)]
+        struct Full {
+            a: i32,
+            unknown: Vec<HashMap<String, String>>,
+        }
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Partial {
+            a: i32,
+        }
+        let full = crate::to_vec(&Full {
+            a: 4,
+            unknown: vec![HashMap::new()],
+        })
+        .unwrap();
+        assert_eq!(Ok(Partial { a: 4 }), from_bytes(&full));
+    }
+
+    #[test]
+    fn test_serde_json_value() {
+        let value = serde_json::json!({
+            "name": "sbson",
+            "list": [1, -2, 3.5, null, true, {"nested": "map"}],
+            "big": u64::MAX,
+        });
+        assert_eq!(value, round_trip(&value));
+    }
+
+    #[test]
+    fn test_serde_recursion_limit() {
+        let mut value = serde_json::json!(1);
+        for _ in 0..RECURSION_LIMIT + 1 {
+            value = serde_json::json!([value]);
+        }
+        let buffer = crate::to_vec(&value).unwrap();
+        assert_eq!(
+            Err(CursorError::RecursionLimitExceeded),
+            from_bytes::<serde_json::Value>(&buffer)
+        );
+    }
+
+    #[test]
+    fn test_serde_struct_field_lookup() {
+        #[derive(Deserialize, PartialEq, Debug)]
+        struct Selected<'a> {
+            #[serde(rename = "item_0042")]
+            item: i64,
+            #[serde(alias = "item_0007")]
+            seventh: i64,
+            #[serde(default)]
+            missing: Option<&'a str>,
+        }
+
+        let map: HashMap<String, i64> = (0..2000).map(|i| (format!("item_{i:04}"), i)).collect();
+        let expected = Selected {
+            item: 42,
+            seventh: 7,
+            missing: None,
+        };
+        let lookup = DeserializationOptions {
+            lookup_struct_fields: true,
+        };
+        for chd_threshold in [100, 10000] {
+            let options = crate::serializer::SerializationOptions { chd_threshold };
+            let buffer = crate::serializer::to_vec_with_options(&map, &options).unwrap();
+            assert_eq!(Ok(&expected), from_bytes(&buffer).as_ref());
+            assert_eq!(
+                Ok(&expected),
+                from_bytes_with_options(&buffer, &lookup).as_ref()
+            );
+        }
+
+        // Undeclared entries aren't visited at all, so corrupting one doesn't matter.
+        #[derive(serde::Serialize, Deserialize, PartialEq, Debug)]
+        struct Small {
+            a: i32,
+            z: i32,
+        }
+        let mut buffer = crate::to_vec(&HashMap::from([("a", 1), ("m", 2), ("z", 3)])).unwrap();
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        let (index, _) = cursor.get_value_and_index_by_key("m").unwrap();
+        let offset = cursor.get_value_by_index(index).unwrap().range.start;
+        buffer[offset] = 0xff;
+        assert!(from_bytes::<Small>(&buffer).is_err());
+        assert_eq!(
+            Ok(Small { a: 1, z: 3 }),
+            from_bytes_with_options(&buffer, &lookup)
+        );
+    }
 }
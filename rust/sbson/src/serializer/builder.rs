@@ -0,0 +1,684 @@
+use crate::serializer::{
+    encode_u32, serialize_array, serialize_map, write_bytes, write_zeros, Output,
+    SerializationOptions, Serialize, Stored, ARRAY_DESCRIPTORS_PER_PATCH, MAX_KEY_LEN,
+};
+use crate::{Cursor, ElementTypeCode};
+use std::ops::Range;
+
+#[derive(Debug)]
+pub enum BuilderError {
+    Io(std::io::Error),
+
+    /// `key` was called while the innermost open container isn't a map.
+    KeyOutsideMap,
+
+    /// A map entry was written without calling `key` first.
+    ExpectedKey,
+
+    /// `key` was followed by another `key` or by `end`, instead of by a value.
+    ExpectedValue,
+
+    /// The same key was written more than once into a single map.
+    DuplicateKey(String),
+
+    /// Map keys are null-terminated, and cannot contain embedded nulls.
+    EmbeddedNul,
+
+    /// Map keys are limited to 255 bytes.
+    KeyTooLong {
+        len: usize,
+    },
+
+    /// `end` was called without an open container.
+    NoOpenContainer,
+
+    /// A value was written after the root node was already complete.
+    RootAlreadyWritten,
+
+    /// An array was given a different amount of items than the length passed to `begin_array`.
+    ArrayLengthMismatch {
+        expected: usize,
+        actual: usize,
+    },
+
+    /// A previous write into the output failed midway, leaving a partially written node behind.
+    Poisoned,
+
+    /// `finish` was called before the root node was complete.
+    Incomplete,
+}
+
+impl std::fmt::Display for BuilderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuilderError::Io(_) => write!(f, "failed to write the document"),
+            BuilderError::KeyOutsideMap => write!(f, "a key can only be written into a map"),
+            BuilderError::ExpectedKey => write!(f, "expected a key before the map entry"),
+            BuilderError::ExpectedValue => write!(f, "expected a value after the key"),
+            BuilderError::DuplicateKey(key) => write!(f, "key {key:?} was written more than once"),
+            BuilderError::EmbeddedNul => write!(f, "keys cannot contain a nul character"),
+            BuilderError::KeyTooLong { len } => {
+                write!(
+                    f,
+                    "keys are limited to {MAX_KEY_LEN} bytes, found one of {len}"
+                )
+            }
+            BuilderError::NoOpenContainer => write!(f, "there is no open container to end"),
+            BuilderError::RootAlreadyWritten => write!(f, "the root node was already written"),
+            BuilderError::ArrayLengthMismatch { expected, actual } => write!(
+                f,
+                "the array was declared with {expected} items, but {actual} were written"
+            ),
+            BuilderError::Poisoned => {
+                write!(f, "the document is unusable after a previous write failed")
+            }
+            BuilderError::Incomplete => write!(f, "the root node is not complete"),
+        }
+    }
+}
+
+impl std::error::Error for BuilderError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BuilderError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for BuilderError {
+    fn from(err: std::io::Error) -> Self {
+        BuilderError::Io(err)
+    }
+}
+
+type Result<T> = std::result::Result<T, BuilderError>;
+
+/// An array whose length is known upfront, written straight into its final position.
+struct ArrayFrame {
+    /// The position of the array node, in the output it's written into.
+    start: u64,
+    len: usize,
+    /// The position of the first descriptor that wasn't patched yet.
+    descriptors_position: u64,
+    /// The descriptors of the items written since the last patch.
+    descriptors: Vec<u8>,
+    written: usize,
+}
+
+/// A map, or an array of unknown length, whose children are buffered until it's closed.
+struct BufferedFrame {
+    /// Set for maps; holds the key of each child, in the order they were written.
+    keys: Option<Vec<String>>,
+    /// The key of the next child, for maps.
+    pending_key: Option<String>,
+    /// The children, serialized back to back.
+    data: Vec<u8>,
+    /// The range of each child inside `data`.
+    children: Vec<Range<usize>>,
+}
+
+/// An array or a map that is still being built.
+enum Frame {
+    InPlace(ArrayFrame),
+    Buffered(BufferedFrame),
+}
+
+/// A push-style writer for building documents incrementally.
+///
+/// Containers are opened with [`begin_map`](Self::begin_map) or [`begin_array`](Self::begin_array)
+/// and closed with [`end`](Self::end). Every map entry is written by calling [`key`](Self::key)
+/// followed by either [`value`](Self::value) or a nested container.
+///
+/// Arrays whose length is passed to `begin_array` are written directly into the output, and
+/// their descriptors are patched in once they're closed, just like [`serialize_array`] does.
+/// Together with a [`SeekWriter`](super::SeekWriter), they allow streaming documents that
+/// don't fit in memory.
+///
+/// Maps, and arrays of an unknown length, are buffered until they're closed and then copied
+/// into place once. A map's values are laid out in the order of its search tree or hash table,
+/// which can't be known before all of its keys are. Map entries may be written in any order;
+/// each map is sorted and laid out as either an Eytzinger or a CHD map according to the
+/// options.
+///
+/// Packed arrays inside a buffered container are re-padded as needed when it's copied, so their
+/// items stay aligned in the finished document.
+///
+/// A failed write into a buffered container is rolled back, leaving the builder as it was.
+/// If writing into the output itself fails, a partially written node is left behind and every
+/// further call returns [`BuilderError::Poisoned`].
+pub struct DocumentBuilder<O = Vec<u8>> {
+    options: SerializationOptions,
+    output: O,
+    stack: Vec<Frame>,
+    root_written: bool,
+    poisoned: bool,
+}
+
+impl DocumentBuilder {
+    /// Creates a builder that writes the document into memory.
+    pub fn new(options: SerializationOptions) -> Self {
+        Self::with_output(options, vec![])
+    }
+}
+
+impl<O: Output> DocumentBuilder<O> {
+    /// Creates a builder that writes the document into the given output.
+    pub fn with_output(options: SerializationOptions, output: O) -> Self {
+        Self {
+            options,
+            output,
+            stack: vec![],
+            root_written: false,
+            poisoned: false,
+        }
+    }
+
+    /// Sets the key under which the next value of the current map is stored.
+    pub fn key(&mut self, key: &str) -> Result<()> {
+        if memchr::memchr(0, key.as_bytes()).is_some() {
+            return Err(BuilderError::EmbeddedNul);
+        }
+        if key.len() > MAX_KEY_LEN {
+            return Err(BuilderError::KeyTooLong { len: key.len() });
+        }
+        let Some(Frame::Buffered(frame)) = self.stack.last_mut() else {
+            return Err(BuilderError::KeyOutsideMap);
+        };
+        if frame.keys.is_none() {
+            return Err(BuilderError::KeyOutsideMap);
+        }
+        if frame.pending_key.is_some() {
+            return Err(BuilderError::ExpectedValue);
+        }
+        frame.pending_key = Some(key.into());
+        Ok(())
+    }
+
+    /// Writes a complete value into the current container.
+    pub fn value(&mut self, value: impl Serialize) -> Result<()> {
+        self.check_slot()?;
+        let options = self.options.clone();
+        let start = self.write(|output| value.serialize(&options, output))?;
+        self.fill_slot(start)
+    }
+
+    /// Opens an array.
+    ///
+    /// Arrays of a known length are written in place, and must be given exactly `len` items.
+    pub fn begin_array(&mut self, len: Option<usize>) -> Result<()> {
+        self.check_slot()?;
+        let Some(len) = len else {
+            self.stack.push(Frame::Buffered(BufferedFrame {
+                keys: None,
+                pending_key: None,
+                data: vec![],
+                children: vec![],
+            }));
+            return Ok(());
+        };
+
+        let start = self.write(|output| {
+            Ok(write_bytes(output, &[ElementTypeCode::Array as u8])?
+                + write_bytes(output, &encode_u32(len)?.to_le_bytes())?
+                + write_zeros(output, 4 * len)?)
+        })?;
+        self.stack.push(Frame::InPlace(ArrayFrame {
+            start,
+            len,
+            descriptors_position: start + 5,
+            descriptors: Vec::with_capacity(4 * len.min(ARRAY_DESCRIPTORS_PER_PATCH)),
+            written: 0,
+        }));
+        Ok(())
+    }
+
+    pub fn begin_map(&mut self) -> Result<()> {
+        self.check_slot()?;
+        self.stack.push(Frame::Buffered(BufferedFrame {
+            keys: Some(vec![]),
+            pending_key: None,
+            data: vec![],
+            children: vec![],
+        }));
+        Ok(())
+    }
+
+    /// Closes the innermost open container.
+    ///
+    /// On failure the container is left open rather than dropped, so the document can't be
+    /// finished without it.
+    pub fn end(&mut self) -> Result<()> {
+        if self.poisoned {
+            return Err(BuilderError::Poisoned);
+        }
+        let frame = self.stack.pop().ok_or(BuilderError::NoOpenContainer)?;
+        let result = match &frame {
+            Frame::InPlace(frame) => self.end_array(frame),
+            Frame::Buffered(frame) => self.end_buffered(frame),
+        };
+        if result.is_err() {
+            self.stack.push(frame);
+        }
+        result
+    }
+
+    /// Returns the output, once the root node is complete.
+    pub fn finish(self) -> Result<O> {
+        if self.poisoned {
+            return Err(BuilderError::Poisoned);
+        }
+        if !self.stack.is_empty() || !self.root_written {
+            return Err(BuilderError::Incomplete);
+        }
+        Ok(self.output)
+    }
+
+    fn end_array(&mut self, frame: &ArrayFrame) -> Result<()> {
+        if frame.written != frame.len {
+            return Err(BuilderError::ArrayLengthMismatch {
+                expected: frame.len,
+                actual: frame.written,
+            });
+        }
+        let output = Self::target(&mut self.stack, &mut self.output);
+        if let Err(err) = output.patch(frame.descriptors_position, &frame.descriptors) {
+            self.poisoned = true;
+            return Err(err.into());
+        }
+        self.fill_slot(frame.start)
+    }
+
+    fn end_buffered(&mut self, frame: &BufferedFrame) -> Result<()> {
+        if frame.pending_key.is_some() {
+            return Err(BuilderError::ExpectedValue);
+        }
+        let options = self.options.clone();
+        // Children are copied with `Stored`, which re-pads the packed arrays inside them.
+        let children = frame
+            .children
+            .iter()
+            .map(|range| Cursor::new_with_range(&frame.data[..], range.clone()).map(Stored::new))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+
+        let start = match &frame.keys {
+            None => self.write(|output| serialize_array(children.iter(), &options, output))?,
+            Some(keys) => {
+                let mut entries: Vec<_> = keys.iter().map(String::as_str).zip(&children).collect();
+                // Make sure the resulting layout doesn't depend on the order of insertion.
+                entries.sort_by_key(|(key, _)| *key);
+                // Duplicate keys cannot be represented in either map layout.
+                if let Some(window) = entries.windows(2).find(|window| window[0].0 == window[1].0) {
+                    return Err(BuilderError::DuplicateKey(window[0].0.into()));
+                }
+                self.write(|output| {
+                    serialize_map(entries.len(), entries.into_iter(), &options, output)
+                })?
+            }
+        };
+        self.fill_slot(start)
+    }
+
+    /// Makes sure a new node can be written in the current position.
+    fn check_slot(&self) -> Result<()> {
+        if self.poisoned {
+            return Err(BuilderError::Poisoned);
+        }
+        match self.stack.last() {
+            None if self.root_written => Err(BuilderError::RootAlreadyWritten),
+            Some(Frame::InPlace(frame)) if frame.written == frame.len => {
+                Err(BuilderError::ArrayLengthMismatch {
+                    expected: frame.len,
+                    actual: frame.len + 1,
+                })
+            }
+            Some(Frame::Buffered(BufferedFrame {
+                keys: Some(_),
+                pending_key: None,
+                ..
+            })) => Err(BuilderError::ExpectedKey),
+            _ => Ok(()),
+        }
+    }
+
+    /// Records the node that was written at `start` as the next child of the current container,
+    /// or as the root node.
+    fn fill_slot(&mut self, start: u64) -> Result<()> {
+        let Some((frame, outer)) = self.stack.split_last_mut() else {
+            self.root_written = true;
+            return Ok(());
+        };
+        match frame {
+            Frame::Buffered(frame) => {
+                frame.children.push(start as usize..frame.data.len());
+                if let Some(keys) = &mut frame.keys {
+                    keys.extend(frame.pending_key.take());
+                }
+            }
+            Frame::InPlace(frame) => {
+                let output = Self::target(outer, &mut self.output);
+                let result = Self::push_descriptor(frame, start, output);
+                // The item can't be taken back once it's written, whichever output it's in.
+                self.poisoned |= result.is_err();
+                result?;
+            }
+        }
+        Ok(())
+    }
+
+    fn push_descriptor(frame: &mut ArrayFrame, start: u64, output: &mut dyn Output) -> Result<()> {
+        if frame.descriptors.len() == 4 * ARRAY_DESCRIPTORS_PER_PATCH {
+            output.patch(frame.descriptors_position, &frame.descriptors)?;
+            frame.descriptors_position += frame.descriptors.len() as u64;
+            frame.descriptors.clear();
+        }
+        let offset = usize::try_from(start - frame.start)
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::InvalidInput))?;
+        frame
+            .descriptors
+            .extend_from_slice(&encode_u32(offset)?.to_le_bytes());
+        frame.written += 1;
+        Ok(())
+    }
+
+    /// Writes a node into the output of the innermost open container, and returns the position
+    /// it starts at.
+    fn write(
+        &mut self,
+        write: impl FnOnce(&mut dyn Output) -> std::io::Result<usize>,
+    ) -> Result<u64> {
+        match self.stack.iter_mut().rev().find_map(Frame::buffer) {
+            Some(buffer) => {
+                let start = buffer.len();
+                if let Err(err) = write(buffer) {
+                    // Don't leave a partially written node behind.
+                    buffer.truncate(start);
+                    return Err(err.into());
+                }
+                Ok(start as u64)
+            }
+            None => {
+                let start = self.output.position();
+                if let Err(err) = write(&mut self.output) {
+                    self.poisoned = true;
+                    return Err(err.into());
+                }
+                Ok(start)
+            }
+        }
+    }
+
+    /// Returns the output that the children of the innermost frame in `frames` are written into:
+    /// the buffer of the innermost buffered container, or the output itself.
+    fn target<'b>(frames: &'b mut [Frame], output: &'b mut O) -> &'b mut dyn Output {
+        match frames.iter_mut().rev().find_map(Frame::buffer) {
+            Some(buffer) => buffer,
+            None => output,
+        }
+    }
+}
+
+impl Frame {
+    fn buffer(&mut self) -> Option<&mut Vec<u8>> {
+        match self {
+            Frame::InPlace(_) => None,
+            Frame::Buffered(frame) => Some(&mut frame.data),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Cursor, ElementTypeCode};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_builder() {
+        for chd_threshold in [2, 100] {
+            let options = SerializationOptions { chd_threshold };
+            let mut builder = DocumentBuilder::new(options.clone());
+            builder.begin_map().unwrap();
+            builder.key("name").unwrap();
+            builder.value("builder").unwrap();
+            builder.key("items").unwrap();
+            builder.begin_array(Some(4)).unwrap();
+            for i in 0..3i32 {
+                builder.value(i).unwrap();
+            }
+            builder.begin_map().unwrap();
+            builder.end().unwrap();
+            builder.end().unwrap();
+            builder.key("enabled").unwrap();
+            builder.value(true).unwrap();
+            builder.end().unwrap();
+            let document = builder.finish().unwrap();
+
+            let cursor = Cursor::new(&document[..]).unwrap();
+            let expected_type = if chd_threshold == 2 {
+                ElementTypeCode::MapCHD
+            } else {
+                ElementTypeCode::Map
+            };
+            assert_eq!(expected_type, cursor.get_element_type());
+            assert!(cursor.validate().is_ok());
+            assert_eq!(
+                Ok("builder"),
+                cursor.get_value_by_key("name").unwrap().get_str()
+            );
+            assert_eq!(
+                Ok(true),
+                cursor.get_value_by_key("enabled").unwrap().get_bool()
+            );
+            let items = cursor.get_value_by_key("items").unwrap();
+            assert_eq!(4, items.get_children_count());
+            assert_eq!(Ok(2), items.get_value_by_index(2).unwrap().get_i32());
+            assert_eq!(0, items.get_value_by_index(3).unwrap().get_children_count());
+        }
+    }
+
+    #[test]
+    fn test_builder_matches_serializer() {
+        let map: HashMap<String, i64> = (0..50).map(|i| (format!("key_{i}"), i)).collect();
+        for chd_threshold in [10, 100] {
+            let options = SerializationOptions { chd_threshold };
+            let mut expected = vec![];
+            map.serialize(&options, &mut expected).unwrap();
+
+            let mut builder = DocumentBuilder::new(options);
+            builder.begin_map().unwrap();
+            for (key, value) in &map {
+                builder.key(key).unwrap();
+                builder.value(*value).unwrap();
+            }
+            builder.end().unwrap();
+            let document = builder.finish().unwrap();
+            assert!(Cursor::new(&document[..]).unwrap().validate().is_ok());
+            if chd_threshold > map.len() {
+                // Eytzinger maps only depend on the set of keys.
+                assert_eq!(expected, document);
+            }
+        }
+    }
+
+    #[test]
+    fn test_builder_errors() {
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        assert!(matches!(builder.key("a"), Err(BuilderError::KeyOutsideMap)));
+        assert!(matches!(builder.end(), Err(BuilderError::NoOpenContainer)));
+        builder.begin_map().unwrap();
+        assert!(matches!(builder.value(1), Err(BuilderError::ExpectedKey)));
+        assert!(matches!(builder.key("a\0"), Err(BuilderError::EmbeddedNul)));
+        assert!(matches!(
+            builder.key(&"a".repeat(256)),
+            Err(BuilderError::KeyTooLong { len: 256 })
+        ));
+        builder.key(&"a".repeat(255)).unwrap();
+        builder.value(1).unwrap();
+        builder.key("a").unwrap();
+        assert!(matches!(builder.key("b"), Err(BuilderError::ExpectedValue)));
+        assert!(matches!(builder.end(), Err(BuilderError::ExpectedValue)));
+
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_map().unwrap();
+        builder.key("a").unwrap();
+        builder.value(1).unwrap();
+        builder.key("a").unwrap();
+        builder.value(2).unwrap();
+        assert!(matches!(builder.end(), Err(BuilderError::DuplicateKey(key)) if key == "a"));
+        // The map is still open after the error, and nothing was written in its place.
+        assert!(matches!(builder.end(), Err(BuilderError::DuplicateKey(_))));
+        assert!(matches!(builder.finish(), Err(BuilderError::Incomplete)));
+
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_array(None).unwrap();
+        builder.begin_map().unwrap();
+        builder.key("a").unwrap();
+        builder.value(1).unwrap();
+        builder.key("a").unwrap();
+        builder.value(2).unwrap();
+        assert!(matches!(builder.end(), Err(BuilderError::DuplicateKey(_))));
+        assert!(matches!(builder.end(), Err(BuilderError::DuplicateKey(_))));
+        assert!(matches!(builder.finish(), Err(BuilderError::Incomplete)));
+
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_array(None).unwrap();
+        builder.begin_array(Some(0)).unwrap();
+        builder.end().unwrap();
+        assert!(matches!(
+            DocumentBuilder::new(SerializationOptions::default()).finish(),
+            Err(BuilderError::Incomplete)
+        ));
+        builder.end().unwrap();
+        assert!(matches!(
+            builder.value(false),
+            Err(BuilderError::RootAlreadyWritten)
+        ));
+        assert_eq!(
+            b"\x04\x01\x00\x00\x00\x09\x00\x00\x00\x04\x00\x00\x00\x00",
+            &builder.finish().unwrap()[..]
+        );
+    }
+
+    /// A value whose serialization always fails halfway through.
+    struct Failing;
+
+    impl Serialize for Failing {
+        fn serialize(
+            &self,
+            _options: &SerializationOptions,
+            output: &mut dyn Output,
+        ) -> std::io::Result<usize> {
+            write_bytes(output, &[ElementTypeCode::Array as u8])?;
+            Err(std::io::ErrorKind::Other.into())
+        }
+    }
+
+    #[test]
+    fn test_builder_in_place_arrays() {
+        let items: Vec<Vec<u32>> = (0..3).map(|i| (0..i).collect()).collect();
+        let mut expected = vec![];
+        items
+            .serialize(&SerializationOptions::default(), &mut expected)
+            .unwrap();
+
+        // Start at a non-zero position to make sure descriptors are relative to the array.
+        let mut file = std::io::Cursor::new(b"prefix".to_vec());
+        file.set_position(6);
+        let writer = crate::serializer::SeekWriter::new(file).unwrap();
+        let mut builder = DocumentBuilder::with_output(SerializationOptions::default(), writer);
+        builder.begin_array(Some(items.len())).unwrap();
+        for item in &items {
+            builder.begin_array(Some(item.len())).unwrap();
+            for value in item {
+                builder.value(value).unwrap();
+            }
+            builder.end().unwrap();
+        }
+        builder.end().unwrap();
+        let file = builder.finish().unwrap().into_inner().into_inner();
+        assert_eq!(b"prefix", &file[..6]);
+        assert_eq!(expected, &file[6..]);
+
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_array(Some(1)).unwrap();
+        builder.value(1).unwrap();
+        assert!(matches!(
+            builder.value(2),
+            Err(BuilderError::ArrayLengthMismatch {
+                expected: 1,
+                actual: 2
+            })
+        ));
+        builder.begin_map().unwrap_err();
+        builder.end().unwrap();
+
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_map().unwrap();
+        builder.key("a").unwrap();
+        builder.begin_array(Some(2)).unwrap();
+        builder.value(1).unwrap();
+        assert!(matches!(
+            builder.end(),
+            Err(BuilderError::ArrayLengthMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        builder.value(2).unwrap();
+        builder.end().unwrap();
+        builder.end().unwrap();
+        let document = builder.finish().unwrap();
+        let cursor = Cursor::new(&document[..]).unwrap();
+        assert!(cursor.validate().is_ok());
+        let array = cursor.get_value_by_key("a").unwrap();
+        assert_eq!(Ok(2), array.get_value_by_index(1).unwrap().get_i32());
+    }
+
+    #[test]
+    fn test_builder_failed_writes() {
+        // Failures inside buffered containers are rolled back, and keep the pending key.
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_map().unwrap();
+        builder.key("a").unwrap();
+        assert!(matches!(builder.value(Failing), Err(BuilderError::Io(_))));
+        builder.begin_array(Some(1)).unwrap();
+        assert!(matches!(builder.value(Failing), Err(BuilderError::Io(_))));
+        builder.value(1).unwrap();
+        builder.end().unwrap();
+        builder.end().unwrap();
+        let document = builder.finish().unwrap();
+        let cursor = Cursor::new(&document[..]).unwrap();
+        assert!(cursor.validate().is_ok());
+        let array = cursor.get_value_by_key("a").unwrap();
+        assert_eq!(Ok(1), array.get_value_by_index(0).unwrap().get_i32());
+
+        // Failures in the output itself can't be taken back.
+        let mut builder = DocumentBuilder::new(SerializationOptions::default());
+        builder.begin_array(Some(2)).unwrap();
+        assert!(matches!(builder.value(Failing), Err(BuilderError::Io(_))));
+        assert!(matches!(builder.value(1), Err(BuilderError::Poisoned)));
+        assert!(matches!(builder.end(), Err(BuilderError::Poisoned)));
+        assert!(matches!(builder.finish(), Err(BuilderError::Poisoned)));
+    }
+
+    #[test]
+    fn test_builder_aligns_packed_arrays() {
+        let ints: Vec<i64> = vec![-1, 2, i64::MAX];
+        for key in ["a", "ab", "abc"] {
+            let mut builder = DocumentBuilder::new(SerializationOptions::default());
+            builder.begin_map().unwrap();
+            builder.key(key).unwrap();
+            builder.value(crate::serializer::Packed(&ints[..])).unwrap();
+            builder.end().unwrap();
+            let document = builder.finish().unwrap();
+
+            let cursor = Cursor::new(&document[..]).unwrap();
+            let packed = cursor.get_value_by_key(key).unwrap();
+            let items = packed.get_packed_array::<i64>().unwrap();
+            assert_eq!(0, (packed.range.end - items.as_bytes().len()) % 8, "{key}");
+            assert_eq!(ints, items.iter().collect::<Vec<_>>());
+        }
+    }
+}
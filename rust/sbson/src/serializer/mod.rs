@@ -1,8 +1,24 @@
-use super::ElementTypeCode;
-use std::collections::HashMap;
-use std::io::Write;
-
+use super::packed::PACKED_ARRAY_HEADER_SIZE;
+use super::{ElementTypeCode, PackedItem};
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+
+mod builder;
+mod output;
+#[cfg(feature = "serde")]
+mod serde_integration;
+#[cfg(feature = "json")]
 mod serde_json_integration;
+mod stored;
+
+pub use builder::{BuilderError, DocumentBuilder};
+pub use output::{Output, SeekWriter};
+pub(crate) use stored::{layout_options, Stored};
+
+#[cfg(feature = "serde")]
+pub use serde_integration::{
+    to_vec, to_vec_with_options, to_writer, to_writer_with_options, SerializeError, Serializer,
+};
 
 #[derive(Clone, Debug)]
 pub struct SerializationOptions {
@@ -26,10 +42,33 @@ pub trait Serialize {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize>;
 }
 
+/// Writes all of `bytes`, returning their length.
+pub(crate) fn write_bytes(output: &mut dyn Output, bytes: &[u8]) -> std::io::Result<usize> {
+    output.write_all(bytes)?;
+    Ok(bytes.len())
+}
+
+/// Writes `count` zero bytes, reserving room for descriptors that are patched in later.
+fn write_zeros(output: &mut dyn Output, count: usize) -> std::io::Result<usize> {
+    std::io::copy(&mut std::io::repeat(0).take(count as u64), output)?;
+    Ok(count)
+}
+
+/// Converts a child count or a node-relative offset into its 32-bit encoding.
+fn encode_u32(value: usize) -> std::io::Result<u32> {
+    u32::try_from(value).map_err(|_| std::io::ErrorKind::InvalidInput.into())
+}
+
+/// Key lengths are encoded in a single byte of the map descriptors.
+const MAX_KEY_LEN: usize = 0xFF;
+
+/// The amount of array descriptors buffered before patching them into the output.
+const ARRAY_DESCRIPTORS_PER_PATCH: usize = 0x4000;
+
 const DEFAULT_LAMBDA: usize = 5;
 struct CHDHashState {
     key: u32,
@@ -37,13 +76,23 @@ struct CHDHashState {
     map: Vec<usize>,
 }
 
-impl<T: Serialize> Serialize for &T {
+impl<T: Serialize + ?Sized> Serialize for &T {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
-        (*self).serialize(options, output)
+        (**self).serialize(options, output)
+    }
+}
+
+impl<T: Serialize + ?Sized> Serialize for Box<T> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        (**self).serialize(options, output)
     }
 }
 
@@ -53,9 +102,10 @@ macro_rules! serialize_integer {
             fn serialize(
                 &self,
                 _options: &SerializationOptions,
-                output: &mut Vec<u8>,
+                output: &mut dyn Output,
             ) -> std::io::Result<usize> {
-                Ok(output.write(&[$type_code as u8])? + output.write(&self.to_le_bytes())?)
+                Ok(write_bytes(output, &[$type_code as u8])?
+                    + write_bytes(output, &self.to_le_bytes())?)
             }
         }
     };
@@ -67,62 +117,274 @@ serialize_integer!(u32, ElementTypeCode::UInt32);
 serialize_integer!(i32, ElementTypeCode::Int32);
 serialize_integer!(f64, ElementTypeCode::Double);
 
-impl Serialize for &str {
+/// Types without an element type of their own are widened into the nearest one.
+macro_rules! serialize_widened {
+    ($narrow_ty:ty => $wide_ty:ty) => {
+        impl Serialize for $narrow_ty {
+            fn serialize(
+                &self,
+                options: &SerializationOptions,
+                output: &mut dyn Output,
+            ) -> std::io::Result<usize> {
+                (*self as $wide_ty).serialize(options, output)
+            }
+        }
+    };
+}
+
+serialize_widened!(u8 => u32);
+serialize_widened!(u16 => u32);
+serialize_widened!(usize => u64);
+serialize_widened!(i8 => i32);
+serialize_widened!(i16 => i32);
+serialize_widened!(isize => i64);
+serialize_widened!(f32 => f64);
+
+impl Serialize for str {
     fn serialize(
         &self,
         _options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
         let mut total = 0;
-        total += output.write(&[ElementTypeCode::String as u8])?;
-        total += output.write(self.as_bytes())?;
-        total += output.write(b"\x00")?;
+        total += write_bytes(output, &[ElementTypeCode::String as u8])?;
+        total += write_bytes(output, self.as_bytes())?;
+        total += write_bytes(output, b"\x00")?;
         Ok(total)
     }
 }
 
+impl Serialize for String {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        self.as_str().serialize(options, output)
+    }
+}
+
+impl Serialize for char {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        (*self.encode_utf8(&mut [0u8; 4])).serialize(options, output)
+    }
+}
+
+/// A binary blob.
+///
+/// Byte slices and vectors are serialized as arrays of integers like any other
+/// slice, so they must be wrapped in `Bytes` to be serialized as a `Binary` node.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Bytes<T>(pub T);
+
+impl<T: AsRef<[u8]>> Serialize for Bytes<T> {
+    fn serialize(
+        &self,
+        _options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        Ok(write_bytes(output, &[ElementTypeCode::Binary as u8])?
+            + write_bytes(output, self.0.as_ref())?)
+    }
+}
+
+/// A slice of numbers, serialized as a `PackedArray` node rather than as an array.
+///
+/// Packed arrays take up `size_of::<E>()` bytes per item, with no per-item descriptors or
+/// element types, and can be read back without creating a cursor per item.
+///
+/// Packing is opt-in: plain slices and vectors of numbers are serialized as regular arrays,
+/// and so are all sequences serialized through serde. Packed arrays deserialize into any
+/// sequence, array or tuple of numbers, though.
+///
+/// Items are padded to their alignment relative to the start of the output, so that
+/// `PackedArray::as_slice` can borrow them.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Packed<'a, E>(pub &'a [E]);
+
+/// The amount of items encoded at a time by `Packed`.
+const PACKED_ITEMS_PER_WRITE: usize = 0x400;
+
+impl<E: PackedItem> Serialize for Packed<'_, E> {
+    fn serialize(
+        &self,
+        _options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        let item_size = std::mem::size_of::<E>();
+        let mut written = write_packed_header(output, E::ELEMENT_TYPE, item_size)?;
+
+        let mut encoded = Vec::with_capacity(item_size * self.0.len().min(PACKED_ITEMS_PER_WRITE));
+        for chunk in self.0.chunks(PACKED_ITEMS_PER_WRITE) {
+            encoded.clear();
+            for item in chunk {
+                encoded.extend_from_slice(item.to_le_bytes().as_ref());
+            }
+            written += write_bytes(output, &encoded)?;
+        }
+        Ok(written)
+    }
+}
+
+/// Writes the headers of a packed array, padding its items so they're aligned relative to the
+/// start of the output.
+fn write_packed_header(
+    output: &mut dyn Output,
+    item_type: ElementTypeCode,
+    item_size: usize,
+) -> std::io::Result<usize> {
+    let items_start = output.position() + PACKED_ARRAY_HEADER_SIZE as u64;
+    let padding = (item_size - (items_start % item_size as u64) as usize) % item_size;
+    Ok(write_bytes(
+        output,
+        &[
+            ElementTypeCode::PackedArray as u8,
+            item_type as u8,
+            padding as u8,
+        ],
+    )? + write_zeros(output, padding)?)
+}
+
+impl Serialize for () {
+    fn serialize(
+        &self,
+        _options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        write_bytes(output, &[ElementTypeCode::None as u8])
+    }
+}
+
+impl<T: Serialize> Serialize for Option<T> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        match self {
+            Some(value) => value.serialize(options, output),
+            None => ().serialize(options, output),
+        }
+    }
+}
+
 impl Serialize for bool {
     fn serialize(
         &self,
         _options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
-        output.write(&[if *self {
-            ElementTypeCode::True
-        } else {
-            ElementTypeCode::False
-        } as u8])
+        write_bytes(
+            output,
+            &[if *self {
+                ElementTypeCode::True
+            } else {
+                ElementTypeCode::False
+            } as u8],
+        )
     }
 }
 
-impl<T: Serialize> Serialize for &[T] {
+impl<T: Serialize> Serialize for [T] {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
-        let total_descriptor_size = 4 * self.len();
+        serialize_array(self.iter(), options, output)
+    }
+}
 
-        let mut total = 0;
-        total += output.write(&[ElementTypeCode::Array as u8])?;
-        total += output.write(&(self.len() as u32).to_le_bytes())?;
-
-        let mut current_descriptor_offset = output.len();
-        output.extend(std::iter::repeat(0u8).take(total_descriptor_size));
-        total += total_descriptor_size;
-
-        let mut offset = total;
-        for item in self.iter() {
-            output[current_descriptor_offset..current_descriptor_offset + 4]
-                .copy_from_slice(&(offset as u32).to_le_bytes());
-            current_descriptor_offset += 4;
-            let value_size = item.serialize(options, output)?;
-            total += value_size;
-            offset += value_size;
+impl<T: Serialize, const N: usize> Serialize for [T; N] {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        self.as_slice().serialize(options, output)
+    }
+}
+
+impl<T: Serialize> Serialize for Vec<T> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        self.as_slice().serialize(options, output)
+    }
+}
+
+/// Tuples are serialized as arrays.
+macro_rules! serialize_tuple {
+    ($($name:ident)+) => {
+        impl<$($name: Serialize),+> Serialize for ($($name,)+) {
+            fn serialize(
+                &self,
+                options: &SerializationOptions,
+                output: &mut dyn Output,
+            ) -> std::io::Result<usize> {
+                #[allow(non_snake_case)]
+                let ($($name,)+) = self;
+                serialize_array([$($name as &dyn Serialize),+].into_iter(), options, output)
+            }
         }
+    };
+}
 
-        Ok(total)
+serialize_tuple!(A);
+serialize_tuple!(A B);
+serialize_tuple!(A B C);
+serialize_tuple!(A B C D);
+serialize_tuple!(A B C D E);
+serialize_tuple!(A B C D E F);
+serialize_tuple!(A B C D E F G);
+serialize_tuple!(A B C D E F G H);
+
+/// Serializes the given items as an array.
+///
+/// Items are pulled from the iterator one at a time and written right away,
+/// so they can be produced lazily while streaming a document into an [`Output`].
+pub fn serialize_array<T: Serialize>(
+    items: impl ExactSizeIterator<Item = T>,
+    options: &SerializationOptions,
+    output: &mut dyn Output,
+) -> std::io::Result<usize> {
+    let item_count = items.len();
+
+    let mut total = 0;
+    total += write_bytes(output, &[ElementTypeCode::Array as u8])?;
+    total += write_bytes(output, &encode_u32(item_count)?.to_le_bytes())?;
+
+    let mut descriptors_position = output.position();
+    total += write_zeros(output, 4 * item_count)?;
+
+    let mut descriptors = Vec::with_capacity(4 * item_count.min(ARRAY_DESCRIPTORS_PER_PATCH));
+    let mut written_items = 0;
+    for item in items {
+        // Guard against iterators that lie about their length.
+        if written_items == item_count {
+            Err(std::io::ErrorKind::InvalidInput)?;
+        }
+        if descriptors.len() == 4 * ARRAY_DESCRIPTORS_PER_PATCH {
+            output.patch(descriptors_position, &descriptors)?;
+            descriptors_position += descriptors.len() as u64;
+            descriptors.clear();
+        }
+        descriptors.extend_from_slice(&encode_u32(total)?.to_le_bytes());
+        total += item.serialize(options, output)?;
+        written_items += 1;
+    }
+    if written_items != item_count {
+        Err(std::io::ErrorKind::InvalidInput)?;
     }
+    output.patch(descriptors_position, &descriptors)?;
+
+    Ok(total)
 }
 
 /// This is a slightly modified version of the same function in the `phf_generator` crate.
@@ -142,7 +404,7 @@ fn try_generate_hash<'a>(entries: impl Iterator<Item = &'a str>, key: u32) -> Op
         .map(|entry| phf_shared::hash(entry, &(key as u64)))
         .collect();
 
-    let buckets_len = (hashes.len() + DEFAULT_LAMBDA - 1) / DEFAULT_LAMBDA;
+    let buckets_len = hashes.len().div_ceil(DEFAULT_LAMBDA);
     let mut buckets = (0..buckets_len)
         .map(|i| Bucket {
             idx: i,
@@ -226,7 +488,7 @@ fn encode_kvs<'a, V: Serialize + 'a>(
     kv_count: usize,
     key_value_pairs: impl Iterator<Item = &'a (&'a str, V)> + Clone,
     options: &SerializationOptions,
-    output: &mut Vec<u8>,
+    output: &mut dyn Output,
     descriptors_offset: usize,
 ) -> std::io::Result<usize> {
     let total_descriptor_size = 8 * kv_count;
@@ -238,33 +500,34 @@ fn encode_kvs<'a, V: Serialize + 'a>(
     let mut current_value_offset = current_key_offset + total_key_size;
     let mut total_written = 0;
 
-    // Save the current end of the buffer so we know where to return to later.
-    let mut current_descriptor_offset = output.len();
-    output.extend(std::iter::repeat(0u8).take(total_descriptor_size));
-    total_written += total_descriptor_size;
+    // Save the current position so we know where to return to later.
+    let descriptors_position = output.position();
+    total_written += write_zeros(output, total_descriptor_size)?;
 
     for (key, _value) in key_value_pairs.clone() {
-        total_written += output.write(key.as_bytes())?;
-        total_written += output.write(&[0u8])?;
+        total_written += write_bytes(output, key.as_bytes())?;
+        total_written += write_bytes(output, &[0u8])?;
     }
 
+    let mut descriptors = Vec::with_capacity(total_descriptor_size);
     for (key, value) in key_value_pairs {
         let key_length = key.len();
+        // Key lengths are encoded in a single byte, and key offsets in 24 bits.
+        if key_length > MAX_KEY_LEN || current_key_offset > 0x00FFFFFF {
+            Err(std::io::ErrorKind::InvalidInput)?;
+        }
 
         let value_length = value.serialize(options, output)?;
         total_written += value_length;
 
         let key_data = ((key_length as u32) << 24) | (current_key_offset as u32);
-        output[current_descriptor_offset..current_descriptor_offset + 4]
-            .copy_from_slice(&key_data.to_le_bytes());
-        current_descriptor_offset += 4;
-        output[current_descriptor_offset..current_descriptor_offset + 4]
-            .copy_from_slice(&(current_value_offset as u32).to_le_bytes());
-        current_descriptor_offset += 4;
+        descriptors.extend_from_slice(&key_data.to_le_bytes());
+        descriptors.extend_from_slice(&encode_u32(current_value_offset)?.to_le_bytes());
 
         current_key_offset += key_length + 1;
         current_value_offset += value_length;
     }
+    output.patch(descriptors_position, &descriptors)?;
 
     Ok(total_written)
 }
@@ -272,9 +535,9 @@ fn encode_kvs<'a, V: Serialize + 'a>(
 fn serialize_chd<'a, V: Serialize>(
     map: impl Iterator<Item = (&'a str, V)>,
     options: &SerializationOptions,
-    output: &mut Vec<u8>,
+    output: &mut dyn Output,
 ) -> std::io::Result<usize> {
-    let kvs: Vec<_> = map.map(|(k, v)| (k, v)).collect();
+    let kvs: Vec<_> = map.collect();
     let mut i = 0;
 
     // TODO: Make this retry loop a bit cleaner.
@@ -293,12 +556,12 @@ fn serialize_chd<'a, V: Serialize>(
         .map(|source_index| &kvs[*source_index]);
 
     let mut total_written = 0;
-    total_written += output.write(&[ElementTypeCode::MapCHD as u8])?;
-    total_written += output.write(&(kvs.len() as u32).to_le_bytes())?;
-    total_written += output.write(&hash_state.key.to_le_bytes())?;
+    total_written += write_bytes(output, &[ElementTypeCode::MapCHD as u8])?;
+    total_written += write_bytes(output, &encode_u32(kvs.len())?.to_le_bytes())?;
+    total_written += write_bytes(output, &hash_state.key.to_le_bytes())?;
     for (d1, d2) in hash_state.disps.into_iter() {
-        total_written += output.write(&d1.to_le_bytes())?;
-        total_written += output.write(&d2.to_le_bytes())?;
+        total_written += write_bytes(output, &d1.to_le_bytes())?;
+        total_written += write_bytes(output, &d2.to_le_bytes())?;
     }
 
     total_written += encode_kvs(kvs.len(), kvs_in_order, options, output, total_written)?;
@@ -309,35 +572,93 @@ fn serialize_chd<'a, V: Serialize>(
 fn serialize_eytzinger<'a, V: Serialize>(
     map: impl Iterator<Item = (&'a str, V)>,
     options: &SerializationOptions,
-    output: &mut Vec<u8>,
+    output: &mut dyn Output,
 ) -> std::io::Result<usize> {
-    let mut kvs: Vec<_> = map.map(|(k, v)| (k, v)).collect();
+    let mut kvs: Vec<_> = map.collect();
     kvs.sort_by_key(|(key, _value)| *key);
 
     let kvs_in_order =
         eytzinger::PermutationGenerator::new(kvs.len()).map(|source_index| &kvs[source_index]);
 
     let mut total_written = 0;
-    total_written += output.write(&[ElementTypeCode::Map as u8])?;
-    total_written += output.write(&(kvs.len() as u32).to_le_bytes())?;
+    total_written += write_bytes(output, &[ElementTypeCode::Map as u8])?;
+    total_written += write_bytes(output, &encode_u32(kvs.len())?.to_le_bytes())?;
 
     total_written += encode_kvs(kvs.len(), kvs_in_order, options, output, total_written)?;
 
     Ok(total_written)
 }
 
+/// Serializes the given key-value pairs as either an Eytzinger or a CHD map,
+/// according to `options.chd_threshold`.
+///
+/// Keys are collected up-front, but values are only serialized once it's their turn
+/// to be written, so they can be produced lazily while streaming into an [`Output`].
+pub fn serialize_map<'a, V: Serialize>(
+    kv_count: usize,
+    map: impl Iterator<Item = (&'a str, V)>,
+    options: &SerializationOptions,
+    output: &mut dyn Output,
+) -> std::io::Result<usize> {
+    if kv_count >= options.chd_threshold {
+        serialize_chd(map, options, output)
+    } else {
+        serialize_eytzinger(map, options, output)
+    }
+}
+
 impl<K: AsRef<str>, V: Serialize, HS> Serialize for HashMap<K, V, HS> {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
         let kvs = self.iter().map(|(k, v)| (k.as_ref(), v));
-        if self.len() >= options.chd_threshold {
-            serialize_chd(kvs, options, output)
-        } else {
-            serialize_eytzinger(kvs, options, output)
-        }
+        serialize_map(self.len(), kvs, options, output)
+    }
+}
+
+impl<K: AsRef<str>, V: Serialize> Serialize for BTreeMap<K, V> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        let kvs = self.iter().map(|(k, v)| (k.as_ref(), v));
+        serialize_map(self.len(), kvs, options, output)
+    }
+}
+
+/// Fixtures shared by the tests of the whole crate.
+#[cfg(test)]
+pub(crate) mod fixtures {
+    use super::{SerializationOptions, Serialize};
+
+    /// This buffer is the serialized representation of:
+    /// ```python
+    /// {
+    ///     '3': b'beep boop',
+    ///     'BLARG': [1, 2, True, False, None],
+    ///     'FLORP': {'X': 255},
+    ///     "help me i'm trapped in a format factory help me before they": '...'
+    /// }
+    /// ```
+    pub(crate) const DOC: &[u8] = include_bytes!("../../../../test_vectors/sanity.sbson");
+    /// The same document as `DOC`, with a CHD root instead of an Eytzinger one.
+    pub(crate) const DOC_PHF: &[u8] = include_bytes!("../../../../test_vectors/sanity_phf.sbson");
+
+    /// Serializes `value` into a new buffer, using the default options.
+    pub(crate) fn serialize(value: &(impl Serialize + ?Sized)) -> Vec<u8> {
+        serialize_with(value, &SerializationOptions::default())
+    }
+
+    pub(crate) fn serialize_with(
+        value: &(impl Serialize + ?Sized),
+        options: &SerializationOptions,
+    ) -> Vec<u8> {
+        let mut buffer = vec![];
+        value.serialize(options, &mut buffer).unwrap();
+        buffer
     }
 }
 
@@ -377,6 +698,42 @@ mod tests {
         assert_serialized_equals(&[true, false][..],    b"\x04\x02\x00\x00\x00\x0D\x00\x00\x00\x0E\x00\x00\x00\x09\x08");
     }
 
+    #[test]
+    #[rustfmt::skip]
+    fn test_rust_types_serialization() {
+        assert_serialized_equals(Bytes(b"a\x00b"),          b"\x05a\x00b");
+        assert_serialized_equals(Bytes(vec![1u8]),          b"\x05\x01");
+        assert_serialized_equals(Bytes([0u8; 0]),           b"\x05");
+        assert_serialized_equals(vec![1u8],                 b"\x04\x01\x00\x00\x00\x09\x00\x00\x00\x11\x01\x00\x00\x00");
+        assert_serialized_equals(7u8,                       b"\x11\x07\x00\x00\x00");
+        assert_serialized_equals(7u16,                      b"\x11\x07\x00\x00\x00");
+        assert_serialized_equals(7usize,                    b"\x13\x07\x00\x00\x00\x00\x00\x00\x00");
+        assert_serialized_equals(-2i8,                      b"\x10\xFE\xFF\xFF\xFF");
+        assert_serialized_equals(-2i16,                     b"\x10\xFE\xFF\xFF\xFF");
+        assert_serialized_equals(-2isize,                   b"\x12\xFE\xFF\xFF\xFF\xFF\xFF\xFF\xFF");
+        assert_serialized_equals(1f32,                      b"\x01\x00\x00\x00\x00\x00\x00\xf0\x3f");
+        assert_serialized_equals(String::from("ab"),        b"\x02ab\x00");
+        assert_serialized_equals('a',                       b"\x02a\x00");
+        assert_serialized_equals(Box::new(true),            b"\x09");
+        assert_serialized_equals((),                        b"\x0A");
+        assert_serialized_equals(None::<bool>,              b"\x0A");
+        assert_serialized_equals(Some(true),                b"\x09");
+        assert_serialized_equals([true, false],             b"\x04\x02\x00\x00\x00\x0D\x00\x00\x00\x0E\x00\x00\x00\x09\x08");
+        assert_serialized_equals(vec![true, false],         b"\x04\x02\x00\x00\x00\x0D\x00\x00\x00\x0E\x00\x00\x00\x09\x08");
+        assert_serialized_equals((true, ()),                b"\x04\x02\x00\x00\x00\x0D\x00\x00\x00\x0E\x00\x00\x00\x09\x0A");
+    }
+
+    #[test]
+    fn test_btree_map_serialization() {
+        let hash_map: HashMap<_, _> = (0..20).map(|i| (format!("{i}"), i)).collect();
+        let btree_map: BTreeMap<_, _> = hash_map.clone().into_iter().collect();
+        let mut expected = vec![];
+        hash_map
+            .serialize(&SerializationOptions::default(), &mut expected)
+            .unwrap();
+        assert_serialized_equals(btree_map, &expected);
+    }
+
     /// Test a super simple map to make sure it vaguely generates into
     /// our expected format.
     ///
@@ -422,9 +779,111 @@ mod tests {
 
             // Test random access
             for (k, v) in map.iter() {
-                let value_cursor = cursor.get_value_by_key(&k).unwrap();
+                let value_cursor = cursor.get_value_by_key(k).unwrap();
                 assert_eq!(value_cursor.get_u32().unwrap(), *v);
             }
         }
     }
+
+    /// A value that is only generated while it's being written.
+    struct Generated(u32);
+
+    impl Serialize for Generated {
+        fn serialize(
+            &self,
+            options: &SerializationOptions,
+            output: &mut dyn Output,
+        ) -> std::io::Result<usize> {
+            serialize_array((0..self.0).map(|i| i * 2), options, output)
+        }
+    }
+
+    #[test]
+    fn test_seek_writer() {
+        let mut map = HashMap::new();
+        for i in 0..100u32 {
+            map.insert(format!("item_{i}"), Generated(i));
+        }
+
+        for options in [
+            SerializationOptions { chd_threshold: 10 },
+            SerializationOptions::default(),
+        ] {
+            let mut expected = vec![];
+            map.serialize(&options, &mut expected).unwrap();
+
+            // Start at a non-zero position to make sure patches are relative to it.
+            let mut file = std::io::Cursor::new(b"prefix".to_vec());
+            file.set_position(6);
+            let mut writer = SeekWriter::new(std::io::BufWriter::new(file)).unwrap();
+            let written = map.serialize(&options, &mut writer).unwrap();
+            let file = writer.into_inner().into_inner().unwrap().into_inner();
+
+            assert_eq!(expected.len(), written);
+            assert_eq!(b"prefix", &file[..6]);
+            assert_eq!(expected, &file[6..]);
+
+            let cursor = Cursor::new(&file[6..]).unwrap();
+            let value = cursor.get_value_by_key("item_42").unwrap();
+            assert_eq!(42, value.get_children_count());
+            assert_eq!(Ok(82), value.get_value_by_index(41).unwrap().get_u32());
+        }
+    }
+
+    #[test]
+    fn test_seek_writer_large_array() {
+        // Large enough for descriptors to be patched in several batches.
+        let value = Generated(3 * ARRAY_DESCRIPTORS_PER_PATCH as u32 + 5);
+        let options = SerializationOptions::default();
+
+        let mut expected = vec![];
+        value.serialize(&options, &mut expected).unwrap();
+        let mut writer = SeekWriter::new(std::io::Cursor::new(vec![])).unwrap();
+        value.serialize(&options, &mut writer).unwrap();
+        assert_eq!(expected, writer.into_inner().into_inner());
+
+        let cursor = Cursor::new(&expected[..]).unwrap();
+        for index in [
+            0,
+            ARRAY_DESCRIPTORS_PER_PATCH,
+            3 * ARRAY_DESCRIPTORS_PER_PATCH + 4,
+        ] {
+            let item = cursor.get_value_by_index(index).unwrap();
+            assert_eq!(Ok(2 * index as u32), item.get_u32());
+        }
+    }
+
+    #[test]
+    fn test_array_length_mismatch() {
+        struct Lying {
+            claimed: usize,
+            actual: usize,
+        }
+
+        impl Iterator for Lying {
+            type Item = bool;
+
+            fn next(&mut self) -> Option<bool> {
+                self.actual = self.actual.checked_sub(1)?;
+                Some(true)
+            }
+        }
+
+        impl ExactSizeIterator for Lying {
+            fn len(&self) -> usize {
+                self.claimed
+            }
+        }
+
+        let options = SerializationOptions::default();
+        for (claimed, actual) in [(3, 4), (3, 2)] {
+            let items = Lying { claimed, actual };
+            assert!(serialize_array(items, &options, &mut vec![]).is_err());
+        }
+        let items = Lying {
+            claimed: 3,
+            actual: 3,
+        };
+        assert!(serialize_array(items, &options, &mut vec![]).is_ok());
+    }
 }
@@ -0,0 +1,84 @@
+use std::io::{Seek, SeekFrom, Write};
+
+/// A destination for serialized documents.
+///
+/// Nodes are preceded by descriptors pointing into their children, which are only known
+/// after the children were written, so outputs must support patching previously written bytes.
+pub trait Output: Write {
+    /// Returns the amount of bytes written so far.
+    fn position(&self) -> u64;
+
+    /// Overwrites bytes that were previously written at the given position.
+    fn patch(&mut self, position: u64, data: &[u8]) -> std::io::Result<()>;
+}
+
+impl Output for Vec<u8> {
+    fn position(&self) -> u64 {
+        self.len() as u64
+    }
+
+    fn patch(&mut self, position: u64, data: &[u8]) -> std::io::Result<()> {
+        let start = usize::try_from(position).map_err(|_| std::io::ErrorKind::InvalidInput)?;
+        self.get_mut(start..start + data.len())
+            .ok_or(std::io::ErrorKind::InvalidInput)?
+            .copy_from_slice(data);
+        Ok(())
+    }
+}
+
+/// An [`Output`] streaming into any seekable writer, such as a `File`.
+///
+/// Only descriptors are patched by seeking back, so documents far larger than the
+/// available memory can be written, as long as their values are produced lazily.
+/// Writing through a `BufWriter` is recommended, since every node's children
+/// are written in many small chunks.
+pub struct SeekWriter<W> {
+    inner: W,
+    /// The position of the writer, relative to where it was when this wrapper was created.
+    position: u64,
+    start: u64,
+}
+
+impl<W: Write + Seek> SeekWriter<W> {
+    pub fn new(mut inner: W) -> std::io::Result<Self> {
+        let start = inner.stream_position()?;
+        Ok(Self {
+            inner,
+            position: 0,
+            start,
+        })
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W: Write + Seek> Write for SeekWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.position += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write + Seek> Output for SeekWriter<W> {
+    fn position(&self) -> u64 {
+        self.position
+    }
+
+    fn patch(&mut self, position: u64, data: &[u8]) -> std::io::Result<()> {
+        if position + data.len() as u64 > self.position {
+            Err(std::io::ErrorKind::InvalidInput)?;
+        }
+        self.inner.seek(SeekFrom::Start(self.start + position))?;
+        self.inner.write_all(data)?;
+        self.inner
+            .seek(SeekFrom::Start(self.start + self.position))?;
+        Ok(())
+    }
+}
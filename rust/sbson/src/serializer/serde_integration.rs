@@ -0,0 +1,802 @@
+use crate::serializer::{
+    BuilderError, Bytes, DocumentBuilder, Output, SeekWriter, SerializationOptions, Serialize,
+    MAX_KEY_LEN,
+};
+use serde::ser;
+use std::io::{Seek, Write};
+
+type Result<T> = std::result::Result<T, SerializeError>;
+
+/// Serializes `value` into a new SBSON document, using the default options.
+pub fn to_vec<T: ser::Serialize + ?Sized>(value: &T) -> Result<Vec<u8>> {
+    to_vec_with_options(value, &SerializationOptions::default())
+}
+
+/// Serializes `value` into a new SBSON document.
+pub fn to_vec_with_options<T: ser::Serialize + ?Sized>(
+    value: &T,
+    options: &SerializationOptions,
+) -> Result<Vec<u8>> {
+    let mut builder = DocumentBuilder::new(options.clone());
+    value.serialize(Serializer::new(&mut builder))?;
+    Ok(builder.finish()?)
+}
+
+/// Serializes `value` as an SBSON document into `writer`, using the default options.
+///
+/// See [`to_writer_with_options`].
+pub fn to_writer<W: Write + Seek, T: ser::Serialize + ?Sized>(writer: W, value: &T) -> Result<()> {
+    to_writer_with_options(writer, value, &SerializationOptions::default())
+}
+
+/// Serializes `value` as an SBSON document into `writer`.
+///
+/// Sequences are written straight into the writer, and their descriptors are patched in by
+/// seeking back once they're complete. Maps, including structs and enum variants, are held in
+/// memory until they're complete though, since the order of their values depends on all of
+/// their keys; see [`DocumentBuilder`]. A document whose root is a map is therefore buffered
+/// in its entirety.
+pub fn to_writer_with_options<W: Write + Seek, T: ser::Serialize + ?Sized>(
+    writer: W,
+    value: &T,
+    options: &SerializationOptions,
+) -> Result<()> {
+    let mut builder = DocumentBuilder::with_output(options.clone(), SeekWriter::new(writer)?);
+    value.serialize(Serializer::new(&mut builder))?;
+    builder.finish()?.flush()?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum SerializeError {
+    Io(std::io::Error),
+
+    /// Map keys must be strings, chars or integers.
+    KeyMustBeAString,
+
+    /// The same key was serialized more than once into a single map.
+    DuplicateKey(String),
+
+    /// Strings and map keys are null-terminated, and cannot contain embedded nulls.
+    EmbeddedNul,
+
+    /// Map keys are limited to 255 bytes.
+    KeyTooLong {
+        len: usize,
+    },
+
+    /// A free-form error raised by a `serde::Serialize` implementation.
+    Custom(String),
+}
+
+impl std::fmt::Display for SerializeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SerializeError::Io(_) => write!(f, "failed to write the document"),
+            SerializeError::KeyMustBeAString => {
+                write!(f, "map keys must be strings, chars or integers")
+            }
+            SerializeError::DuplicateKey(key) => {
+                write!(f, "key {key:?} was serialized more than once")
+            }
+            SerializeError::EmbeddedNul => {
+                write!(f, "strings and keys cannot contain a nul character")
+            }
+            SerializeError::KeyTooLong { len } => {
+                write!(
+                    f,
+                    "keys are limited to {MAX_KEY_LEN} bytes, found one of {len}"
+                )
+            }
+            SerializeError::Custom(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for SerializeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SerializeError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl ser::Error for SerializeError {
+    fn custom<T: std::fmt::Display>(msg: T) -> Self {
+        SerializeError::Custom(msg.to_string())
+    }
+}
+
+impl From<std::io::Error> for SerializeError {
+    fn from(err: std::io::Error) -> Self {
+        SerializeError::Io(err)
+    }
+}
+
+impl From<BuilderError> for SerializeError {
+    fn from(err: BuilderError) -> Self {
+        match err {
+            BuilderError::Io(err) => SerializeError::Io(err),
+            BuilderError::DuplicateKey(key) => SerializeError::DuplicateKey(key),
+            BuilderError::EmbeddedNul => SerializeError::EmbeddedNul,
+            BuilderError::KeyTooLong { len } => SerializeError::KeyTooLong { len },
+            // The rest are only caused by `serde::Serialize` implementations that misuse
+            // the serializer, such as by giving a sequence the wrong length.
+            err => SerializeError::Custom(err.to_string()),
+        }
+    }
+}
+
+fn ensure_no_nul(s: &str) -> Result<()> {
+    match memchr::memchr(0, s.as_bytes()) {
+        Some(_) => Err(SerializeError::EmbeddedNul),
+        None => Ok(()),
+    }
+}
+
+/// A `serde::Serializer` writing a single SBSON node into a [`DocumentBuilder`].
+///
+/// Unit variants are serialized as their name, and other enum variants as a single-item map
+/// of `{variant: value}`, like `serde_json` does.
+pub struct Serializer<'a, O = Vec<u8>> {
+    builder: &'a mut DocumentBuilder<O>,
+}
+
+impl<'a, O: Output> Serializer<'a, O> {
+    pub fn new(builder: &'a mut DocumentBuilder<O>) -> Self {
+        Self { builder }
+    }
+
+    fn write_serialize<T: Serialize>(self, value: T) -> Result<()> {
+        Ok(self.builder.value(value)?)
+    }
+
+    /// Opens the single-item map that wraps an enum variant.
+    fn begin_variant(&mut self, variant: &'static str) -> Result<()> {
+        self.builder.begin_map()?;
+        Ok(self.builder.key(variant)?)
+    }
+}
+
+impl<'a, O: Output> ser::Serializer for Serializer<'a, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    type SerializeSeq = SerializeArray<'a, O>;
+    type SerializeTuple = SerializeArray<'a, O>;
+    type SerializeTupleStruct = SerializeArray<'a, O>;
+    type SerializeTupleVariant = SerializeArray<'a, O>;
+    type SerializeMap = SerializeMap<'a, O>;
+    type SerializeStruct = SerializeMap<'a, O>;
+    type SerializeStructVariant = SerializeMap<'a, O>;
+
+    fn serialize_bool(self, v: bool) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<()> {
+        self.write_serialize(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<()> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<()> {
+        ensure_no_nul(v)?;
+        self.write_serialize(v)
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<()> {
+        self.write_serialize(Bytes(v))
+    }
+
+    fn serialize_none(self) -> Result<()> {
+        self.write_serialize(())
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, value: &T) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<()> {
+        self.write_serialize(())
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<()> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<()> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.begin_variant(variant)?;
+        value.serialize(Serializer::new(&mut *self.builder))?;
+        Ok(self.builder.end()?)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SerializeArray<'a, O>> {
+        self.builder.begin_array(len)?;
+        Ok(SerializeArray {
+            builder: self.builder,
+            variant: false,
+        })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SerializeArray<'a, O>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray<'a, O>> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeArray<'a, O>> {
+        self.begin_variant(variant)?;
+        let mut array = self.serialize_seq(Some(len))?;
+        array.variant = true;
+        Ok(array)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<SerializeMap<'a, O>> {
+        self.builder.begin_map()?;
+        Ok(SerializeMap {
+            builder: self.builder,
+            variant: false,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<SerializeMap<'a, O>> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SerializeMap<'a, O>> {
+        self.begin_variant(variant)?;
+        let mut map = self.serialize_map(Some(len))?;
+        map.variant = true;
+        Ok(map)
+    }
+}
+
+pub struct SerializeArray<'a, O> {
+    builder: &'a mut DocumentBuilder<O>,
+    /// Set for tuple variants, which are wrapped in a single-item map.
+    variant: bool,
+}
+
+impl<O: Output> SerializeArray<'_, O> {
+    fn push<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(Serializer::new(&mut *self.builder))
+    }
+
+    fn finish(self) -> Result<()> {
+        self.builder.end()?;
+        if self.variant {
+            self.builder.end()?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: Output> ser::SerializeSeq for SerializeArray<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<O: Output> ser::SerializeTuple for SerializeArray<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_element<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<O: Output> ser::SerializeTupleStruct for SerializeArray<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<O: Output> ser::SerializeTupleVariant for SerializeArray<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+pub struct SerializeMap<'a, O> {
+    builder: &'a mut DocumentBuilder<O>,
+    /// Set for struct variants, which are wrapped in a single-item map.
+    variant: bool,
+}
+
+impl<O: Output> SerializeMap<'_, O> {
+    fn entry<T: ser::Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<()> {
+        self.builder.key(key)?;
+        value.serialize(Serializer::new(&mut *self.builder))
+    }
+
+    fn finish(self) -> Result<()> {
+        self.builder.end()?;
+        if self.variant {
+            self.builder.end()?;
+        }
+        Ok(())
+    }
+}
+
+impl<O: Output> ser::SerializeMap for SerializeMap<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_key<T: ser::Serialize + ?Sized>(&mut self, key: &T) -> Result<()> {
+        Ok(self.builder.key(&key.serialize(KeySerializer)?)?)
+    }
+
+    fn serialize_value<T: ser::Serialize + ?Sized>(&mut self, value: &T) -> Result<()> {
+        value.serialize(Serializer::new(&mut *self.builder))
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<O: Output> ser::SerializeStruct for SerializeMap<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+impl<O: Output> ser::SerializeStructVariant for SerializeMap<'_, O> {
+    type Ok = ();
+    type Error = SerializeError;
+
+    fn serialize_field<T: ser::Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<()> {
+        self.entry(key, value)
+    }
+
+    fn end(self) -> Result<()> {
+        self.finish()
+    }
+}
+
+/// Turns map keys into strings.
+///
+/// Like `serde_json`, integer and char keys are accepted and converted into their
+/// textual representation.
+struct KeySerializer;
+
+impl KeySerializer {
+    fn key(v: impl ToString) -> Result<String> {
+        let key = v.to_string();
+        ensure_no_nul(&key)?;
+        Ok(key)
+    }
+}
+
+impl ser::Serializer for KeySerializer {
+    type Ok = String;
+    type Error = SerializeError;
+
+    type SerializeSeq = ser::Impossible<String, SerializeError>;
+    type SerializeTuple = ser::Impossible<String, SerializeError>;
+    type SerializeTupleStruct = ser::Impossible<String, SerializeError>;
+    type SerializeTupleVariant = ser::Impossible<String, SerializeError>;
+    type SerializeMap = ser::Impossible<String, SerializeError>;
+    type SerializeStruct = ser::Impossible<String, SerializeError>;
+    type SerializeStructVariant = ser::Impossible<String, SerializeError>;
+
+    fn serialize_str(self, v: &str) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_char(self, v: char) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<String> {
+        Self::key(v)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String> {
+        Self::key(variant)
+    }
+
+    fn serialize_newtype_struct<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_f32(self, _v: f32) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_f64(self, _v: f64) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_none(self) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_some<T: ser::Serialize + ?Sized>(self, _value: &T) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_unit(self) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_newtype_variant<T: ser::Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_struct(self, _name: &'static str, _len: usize) -> Result<Self::SerializeStruct> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant> {
+        Err(SerializeError::KeyMustBeAString)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{from_bytes, Cursor, ElementTypeCode, PathSegment};
+    use serde::{Deserialize, Serialize as SerdeSerialize};
+    use std::collections::HashMap;
+
+    #[derive(SerdeSerialize, Deserialize, PartialEq, Debug)]
+    struct Inner {
+        enabled: bool,
+        weights: Vec<i64>,
+    }
+
+    #[derive(SerdeSerialize, Deserialize, PartialEq, Debug)]
+    struct Config {
+        name: String,
+        port: i32,
+        size: i64,
+        inner: Inner,
+        pair: (i32, i32),
+    }
+
+    #[test]
+    fn test_struct_roundtrip() {
+        let config = Config {
+            name: "florp".into(),
+            port: 8080,
+            size: -1,
+            inner: Inner {
+                enabled: true,
+                weights: vec![1, 2, 3],
+            },
+            pair: (4, 5),
+        };
+        let buf = to_vec(&config).unwrap();
+        assert_eq!(Cursor::new(&buf[..]).unwrap().validate(), Ok(()));
+        assert_eq!(from_bytes::<Config>(&buf), Ok(config));
+    }
+
+    #[test]
+    #[rustfmt::skip]
+    fn test_primitives() {
+        assert_eq!(to_vec(&true).unwrap(),          b"\x09");
+        assert_eq!(to_vec(&()).unwrap(),            b"\x0A");
+        assert_eq!(to_vec(&None::<i32>).unwrap(),   b"\x0A");
+        assert_eq!(to_vec(&Some(-2i8)).unwrap(),    b"\x10\xFE\xFF\xFF\xFF");
+        assert_eq!(to_vec(&7u16).unwrap(),          b"\x11\x07\x00\x00\x00");
+        assert_eq!(to_vec(&'x').unwrap(),           b"\x02x\x00");
+        assert!(matches!(to_vec("a\0b"), Err(SerializeError::EmbeddedNul)));
+        assert_eq!(to_vec("a\0b").unwrap_err().to_string(), "strings and keys cannot contain a nul character");
+        let long_key = HashMap::from([("a".repeat(300), 1)]);
+        assert!(matches!(to_vec(&long_key), Err(SerializeError::KeyTooLong { len: 300 })));
+        assert_eq!(to_vec(&long_key).unwrap_err().to_string(), "keys are limited to 255 bytes, found one of 300");
+    }
+
+    /// The serde serializer should generate the same maps as the crate's own `Serialize`.
+    #[test]
+    fn test_map_matches_native_serializer() {
+        for options in [
+            SerializationOptions { chd_threshold: 1 },
+            SerializationOptions::default(),
+        ] {
+            let map: HashMap<String, u32> = (0..100).map(|i| (format!("item_{i}"), i)).collect();
+            let buf = to_vec_with_options(&map, &options).unwrap();
+            let cursor = Cursor::new(&buf[..]).unwrap();
+            assert_eq!(cursor.validate(), Ok(()));
+            let expected_type = match options.chd_threshold {
+                1 => ElementTypeCode::MapCHD,
+                _ => ElementTypeCode::Map,
+            };
+            assert_eq!(cursor.get_element_type(), expected_type);
+            for (key, value) in map.iter() {
+                assert_eq!(cursor.get_value_by_key(key).unwrap().get_u32(), Ok(*value));
+            }
+
+            if expected_type == ElementTypeCode::Map {
+                let mut native = vec![];
+                Serialize::serialize(&map, &options, &mut native).unwrap();
+                assert_eq!(buf, native);
+            }
+        }
+    }
+
+    #[test]
+    fn test_enums() {
+        #[derive(SerdeSerialize)]
+        enum E {
+            Unit,
+            Newtype(i32),
+            Tuple(i32, bool),
+            Struct { a: i32 },
+        }
+
+        assert_eq!(to_vec(&E::Unit).unwrap(), to_vec("Unit").unwrap());
+
+        let buf = to_vec(&E::Newtype(3)).unwrap();
+        let cursor = Cursor::new(&buf[..]).unwrap();
+        assert_eq!(cursor.get_value_by_key("Newtype").unwrap().get_i32(), Ok(3));
+
+        let buf = to_vec(&E::Tuple(3, true)).unwrap();
+        let cursor = Cursor::new(&buf[..]).unwrap();
+        let tuple = cursor.get_value_by_key("Tuple").unwrap();
+        assert_eq!(tuple.get_value_by_index(1).unwrap().get_bool(), Ok(true));
+
+        let buf = to_vec(&E::Struct { a: 3 }).unwrap();
+        let cursor = Cursor::new(&buf[..]).unwrap();
+        let a = cursor
+            .goto([PathSegment::Key("Struct"), PathSegment::Key("a")].into_iter())
+            .unwrap();
+        assert_eq!(a.get_i32(), Ok(3));
+    }
+
+    #[test]
+    fn test_map_keys() {
+        let map = HashMap::from([(1u32, true), (20, false)]);
+        let buf = to_vec(&map).unwrap();
+        let cursor = Cursor::new(&buf[..]).unwrap();
+        assert_eq!(cursor.get_value_by_key("20").unwrap().get_bool(), Ok(false));
+
+        let map = HashMap::from([(true, 1)]);
+        assert!(matches!(
+            to_vec(&map),
+            Err(SerializeError::KeyMustBeAString)
+        ));
+    }
+
+    #[test]
+    fn test_to_writer() {
+        let value = (vec![1u32, 2], HashMap::from([("a", vec![3u32])]));
+        let mut output = std::io::Cursor::new(vec![]);
+        to_writer(&mut output, &value).unwrap();
+        assert_eq!(output.into_inner(), to_vec(&value).unwrap());
+    }
+
+    #[test]
+    fn test_sequence_length_mismatch() {
+        /// A sequence that claims more items than it yields.
+        struct Lying;
+
+        impl SerdeSerialize for Lying {
+            fn serialize<S: ser::Serializer>(
+                &self,
+                serializer: S,
+            ) -> std::result::Result<S::Ok, S::Error> {
+                use ser::SerializeSeq;
+                let mut seq = serializer.serialize_seq(Some(2))?;
+                seq.serialize_element(&1)?;
+                seq.end()
+            }
+        }
+
+        assert!(matches!(to_vec(&Lying), Err(SerializeError::Custom(_))));
+    }
+}
@@ -1,22 +1,17 @@
 use crate::{
-    serializer::{serialize_chd, serialize_eytzinger, SerializationOptions, Serialize},
+    serializer::{serialize_map, write_bytes, Output, SerializationOptions, Serialize},
     ElementTypeCode,
 };
 use serde_json::Value;
-use std::io::Write;
 
 impl Serialize for serde_json::Map<String, Value> {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
         let kvs = self.iter().map(|(k, v)| (k.as_ref(), v));
-        if self.len() >= options.chd_threshold {
-            serialize_chd(kvs, options, output)
-        } else {
-            serialize_eytzinger(kvs, options, output)
-        }
+        serialize_map(self.len(), kvs, options, output)
     }
 }
 
@@ -24,10 +19,10 @@ impl Serialize for Value {
     fn serialize(
         &self,
         options: &SerializationOptions,
-        output: &mut Vec<u8>,
+        output: &mut dyn Output,
     ) -> std::io::Result<usize> {
         match self {
-            Value::Null => output.write(&[ElementTypeCode::None as u8]),
+            Value::Null => write_bytes(output, &[ElementTypeCode::None as u8]),
             Value::Bool(b) => b.serialize(options, output),
             Value::String(s) => s.as_str().serialize(options, output),
             Value::Array(val) => val.as_slice().serialize(options, output),
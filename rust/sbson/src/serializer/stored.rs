@@ -0,0 +1,183 @@
+use crate::packed::{packed_item_size, split_packed_array};
+use crate::serializer::{
+    serialize_array, serialize_map, write_bytes, write_packed_header, Output, SerializationOptions,
+    Serialize,
+};
+use crate::{Cursor, CursorError, ElementTypeCode, RECURSION_LIMIT};
+
+/// A node copied from an existing document.
+///
+/// Nodes are copied byte-for-byte, unless that would leave the items of a packed array inside
+/// them unaligned. In that case the packed arrays are re-padded, and the containers leading to
+/// them are re-encoded with the same layout.
+pub(crate) struct Stored<'a> {
+    node: Cursor<&'a [u8]>,
+    remaining_depth: usize,
+}
+
+impl<'a> Stored<'a> {
+    pub(crate) fn new(node: Cursor<&'a [u8]>) -> Self {
+        Self {
+            node,
+            remaining_depth: RECURSION_LIMIT,
+        }
+    }
+
+    fn child(&self, index: usize) -> Result<Self, CursorError> {
+        Ok(Self {
+            node: self.node.get_value_by_index(index)?,
+            remaining_depth: self
+                .remaining_depth
+                .checked_sub(1)
+                .ok_or(CursorError::RecursionLimitExceeded)?,
+        })
+    }
+
+    /// Re-encodes the node, padding the packed arrays inside it relative to the output.
+    fn repad(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        let node = &self.node;
+        match node.get_element_type() {
+            ElementTypeCode::PackedArray => {
+                let (item_type, items) =
+                    split_packed_array(node.payload_scoped_buffer()).map_err(invalid_data)?;
+                let item_size =
+                    packed_item_size(item_type).ok_or(std::io::ErrorKind::InvalidData)?;
+                Ok(
+                    write_packed_header(output, item_type, item_size)?
+                        + write_bytes(output, items)?,
+                )
+            }
+            ElementTypeCode::Array => {
+                let items = (0..node.get_children_count())
+                    .map(|index| self.child(index))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(invalid_data)?;
+                serialize_array(items.iter(), options, output)
+            }
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                let entries = (0..node.get_children_count())
+                    .map(|index| Ok((node.get_key_by_index(index)?, self.child(index)?)))
+                    .collect::<Result<Vec<_>, CursorError>>()
+                    .map_err(invalid_data)?;
+                serialize_map(
+                    entries.len(),
+                    entries.into_iter(),
+                    &layout_options(node),
+                    output,
+                )
+            }
+            _ => write_bytes(output, node.scoped_buffer()),
+        }
+    }
+}
+
+impl Serialize for Stored<'_> {
+    fn serialize(
+        &self,
+        options: &SerializationOptions,
+        output: &mut dyn Output,
+    ) -> std::io::Result<usize> {
+        // Moving a node by a multiple of the largest item size keeps it as aligned as it was.
+        let shift = output.position().wrapping_sub(self.node.range.start as u64);
+        if shift.is_multiple_of(8)
+            || is_aligned(&self.node, shift, self.remaining_depth).map_err(invalid_data)?
+        {
+            return write_bytes(output, self.node.scoped_buffer());
+        }
+        self.repad(options, output)
+    }
+}
+
+/// Checks whether the items of every packed array inside `node` stay aligned once the node is
+/// moved by `shift` bytes.
+fn is_aligned(
+    node: &Cursor<&[u8]>,
+    shift: u64,
+    remaining_depth: usize,
+) -> Result<bool, CursorError> {
+    match node.get_element_type() {
+        ElementTypeCode::PackedArray => {
+            let (item_type, items) = split_packed_array(node.payload_scoped_buffer())?;
+            let item_size = packed_item_size(item_type)
+                .ok_or(CursorError::InvalidElementType(item_type as u8))?;
+            let items_position = (node.range.end - items.len()) as u64;
+            Ok(items_position
+                .wrapping_add(shift)
+                .is_multiple_of(item_size as u64))
+        }
+        ElementTypeCode::Array | ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+            let remaining_depth = remaining_depth
+                .checked_sub(1)
+                .ok_or(CursorError::RecursionLimitExceeded)?;
+            for index in 0..node.get_children_count() {
+                if !is_aligned(&node.get_value_by_index(index)?, shift, remaining_depth)? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+        _ => Ok(true),
+    }
+}
+
+/// Returns options that lay out a map the same way as `node`.
+pub(crate) fn layout_options(node: &Cursor<&[u8]>) -> SerializationOptions {
+    SerializationOptions {
+        chd_threshold: match node.get_element_type() {
+            ElementTypeCode::MapCHD => 0,
+            _ => usize::MAX,
+        },
+    }
+}
+
+fn invalid_data(err: CursorError) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize;
+    use crate::serializer::Packed;
+    use crate::{PathSegment, Value};
+    use std::collections::BTreeMap;
+
+    /// Returns the offset of the items of the packed array at `path` inside `buffer`.
+    fn items_offset(buffer: &[u8], path: &[PathSegment]) -> usize {
+        let cursor = Cursor::new(buffer).unwrap();
+        let packed = cursor.goto(path.iter().copied()).unwrap();
+        packed.range.end - packed.get_packed_array::<i64>().unwrap().as_bytes().len()
+    }
+
+    #[test]
+    fn test_stored_repads_packed_arrays() {
+        let ints: Vec<i64> = vec![-1, 2, i64::MAX];
+        let options = SerializationOptions::default();
+        let inner = BTreeMap::from([("ints", Packed(&ints[..]))]);
+        let source = serialize(&(true, &inner));
+        let source = Cursor::new(&source[..]).unwrap();
+        let node = source.get_value_by_index(1).unwrap();
+        let path = [PathSegment::Key("ints")];
+
+        for shift in 0..8 {
+            let mut output = vec![0; shift];
+            Stored::new(node.clone())
+                .serialize(&options, &mut output)
+                .unwrap();
+            let copied = &output[shift..];
+            assert_eq!(
+                Value::try_from(&node).unwrap(),
+                Cursor::new(copied).unwrap()
+            );
+            assert_eq!(0, (shift + items_offset(copied, &path)) % 8, "{shift}");
+            if (shift + 8 - node.range.start % 8) % 8 == 0 {
+                // Moving the node by a multiple of 8 keeps it as it was.
+                assert_eq!(node.scoped_buffer(), copied);
+            }
+        }
+    }
+}
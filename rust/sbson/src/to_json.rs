@@ -0,0 +1,181 @@
+use crate::{Cursor, CursorError, ElementTypeCode, PackedItem, RECURSION_LIMIT};
+#[cfg(feature = "json")]
+use alloc::string::ToString;
+
+/// Serializes the node pointed to by the cursor, streaming it without building an
+/// intermediate tree.
+///
+/// - Maps are serialized in the order of their keys, regardless of their layout.
+/// - Binary nodes are serialized with `serialize_bytes`, so formats with a native byte-string
+///   type keep them as is, while `serde_json` writes them as arrays of numbers.
+/// - Packed arrays are serialized as sequences of numbers.
+/// - Doubles are passed along as is, so it's up to the format to handle non-finite values;
+///   `serde_json` writes them as `null`.
+///
+/// Malformed nodes, and nodes nested deeper than the deserializer's recursion limit,
+/// fail the serialization.
+impl<T: Clone + AsRef<[u8]>> serde::Serialize for Cursor<T> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let node = SerializedNode {
+            cursor: self.borrow(),
+            remaining_depth: RECURSION_LIMIT,
+        };
+        node.serialize(serializer)
+    }
+}
+
+struct SerializedNode<'de> {
+    cursor: Cursor<&'de [u8]>,
+    /// The amount of nesting levels allowed below the current node.
+    remaining_depth: usize,
+}
+
+impl serde::Serialize for SerializedNode<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{Error as _, SerializeMap, SerializeSeq};
+
+        let cursor = &self.cursor;
+        let child = |cursor| {
+            Ok(SerializedNode {
+                cursor,
+                remaining_depth: self
+                    .remaining_depth
+                    .checked_sub(1)
+                    .ok_or(CursorError::RecursionLimitExceeded)?,
+            })
+        };
+        match cursor.get_element_type() {
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                let mut map = serializer.serialize_map(Some(cursor.get_children_count()))?;
+                for index in cursor
+                    .sorted_map_indices(|_key| false)
+                    .map_err(S::Error::custom)?
+                {
+                    let key = cursor.get_key_by_index(index).map_err(S::Error::custom)?;
+                    let value = cursor
+                        .get_value_by_index(index)
+                        .and_then(child)
+                        .map_err(S::Error::custom)?;
+                    map.serialize_entry(key, &value)?;
+                }
+                map.end()
+            }
+            ElementTypeCode::Array => {
+                let mut seq = serializer.serialize_seq(Some(cursor.get_children_count()))?;
+                for index in 0..cursor.get_children_count() {
+                    let item = cursor
+                        .get_value_by_index(index)
+                        .and_then(child)
+                        .map_err(S::Error::custom)?;
+                    seq.serialize_element(&item)?;
+                }
+                seq.end()
+            }
+            ElementTypeCode::PackedArray => {
+                match cursor.get_packed_item_type().map_err(S::Error::custom)? {
+                    ElementTypeCode::Double => serialize_packed::<f64, S>(cursor, serializer),
+                    ElementTypeCode::Int32 => serialize_packed::<i32, S>(cursor, serializer),
+                    ElementTypeCode::UInt32 => serialize_packed::<u32, S>(cursor, serializer),
+                    ElementTypeCode::Int64 => serialize_packed::<i64, S>(cursor, serializer),
+                    ElementTypeCode::UInt64 => serialize_packed::<u64, S>(cursor, serializer),
+                    item_type => Err(S::Error::custom(CursorError::InvalidElementType(
+                        item_type as u8,
+                    ))),
+                }
+            }
+            ElementTypeCode::Binary => {
+                serializer.serialize_bytes(cursor.get_binary().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::String => {
+                serializer.serialize_str(cursor.get_str().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::Double => {
+                serializer.serialize_f64(cursor.get_double().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::Int32 => {
+                serializer.serialize_i32(cursor.get_i32().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::UInt32 => {
+                serializer.serialize_u32(cursor.get_u32().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::Int64 => {
+                serializer.serialize_i64(cursor.get_i64().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::UInt64 => {
+                serializer.serialize_u64(cursor.get_u64().map_err(S::Error::custom)?)
+            }
+            ElementTypeCode::False => serializer.serialize_bool(false),
+            ElementTypeCode::True => serializer.serialize_bool(true),
+            ElementTypeCode::None => serializer.serialize_unit(),
+        }
+    }
+}
+
+fn serialize_packed<E, S>(cursor: &Cursor<&[u8]>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    E: PackedItem + serde::Serialize,
+    S: serde::Serializer,
+{
+    use serde::ser::Error as _;
+
+    let items = cursor.get_packed_array::<E>().map_err(S::Error::custom)?;
+    serializer.collect_seq(items)
+}
+
+#[cfg(feature = "json")]
+impl<T: Clone + AsRef<[u8]>> Cursor<T> {
+    /// Converts the node into a `serde_json::Value`.
+    ///
+    /// This follows the same conventions as serializing the cursor itself: maps are ordered
+    /// by key, binary nodes become arrays of bytes and non-finite doubles become `null`.
+    pub fn to_json_value(&self) -> Result<serde_json::Value, CursorError> {
+        serde_json::to_value(self).map_err(|err| CursorError::Custom(err.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::{serialize, DOC, DOC_PHF};
+    use crate::serializer::{Bytes, Packed};
+    use serde_json::json;
+
+    #[test]
+    fn test_serialize_cursor() {
+        let expected = json!({
+            "3": b"beep boop",
+            "BLARG": [1, 2, true, false, null],
+            "FLORP": {"X": 255},
+            "help me i'm trapped in a format factory help me before they": "...",
+        });
+        for doc in [DOC, DOC_PHF] {
+            let cursor = Cursor::new(doc).unwrap();
+            assert_eq!(Ok(expected.clone()), cursor.to_json_value());
+            // Keys come out sorted, whatever the layout of the map.
+            assert_eq!(
+                expected.to_string(),
+                serde_json::to_string(&cursor).unwrap()
+            );
+        }
+
+        let buffer = serialize(&(
+            f64::NAN,
+            f64::INFINITY,
+            -0.5,
+            Bytes(b"\x00\xff"),
+            Packed(&[1u32, 2, 3][..]),
+        ));
+        let cursor = Cursor::new(&buffer[..]).unwrap();
+        assert_eq!(
+            Ok(json!([null, null, -0.5, [0, 255], [1, 2, 3]])),
+            cursor.to_json_value()
+        );
+
+        let mut value = json!(1);
+        for _ in 0..RECURSION_LIMIT + 1 {
+            value = json!({ "a": value });
+        }
+        let buffer = crate::to_vec(&value).unwrap();
+        assert!(Cursor::new(&buffer[..]).unwrap().to_json_value().is_err());
+    }
+}
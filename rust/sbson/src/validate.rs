@@ -0,0 +1,511 @@
+use crate::packed::split_packed_array;
+use crate::raw_cursor::{
+    get_map_descriptor, get_u32_at_offset, EytzingerInOrder, MapDescriptor, RawCursor,
+    ARRAY_DESCRIPTOR_SIZE, ELEMENT_TYPE_SIZE, U32_SIZE_BYTES,
+};
+use crate::{Cursor, CursorError, ElementTypeCode, OwnedPathSegment, Path};
+use alloc::vec::Vec;
+use core::ffi::CStr;
+use core::ops::Range;
+
+/// The reason a node has failed validation.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ValidationErrorKind {
+    /// The node could not be read, e.g. due to an unknown element type or truncated headers.
+    Cursor(CursorError),
+
+    /// A fixed-size node (e.g. an integer) does not have the expected size.
+    InvalidLength { expected: usize, actual: usize },
+
+    /// The descriptor of the child at `index` points outside of the node, or into its headers.
+    DescriptorOutOfBounds { index: usize },
+
+    /// The value offset of the child at `index` is not greater than that of the previous child.
+    NonMonotonicDescriptors { index: usize },
+
+    /// The key at `index` is either missing its null-terminator or contains an embedded null.
+    UnterminatedKey { index: usize },
+
+    /// The key at `index` is not valid UTF-8.
+    KeyUtf8Error { index: usize },
+
+    /// The key at `index` of an Eytzinger map is not greater than its in-order predecessor.
+    UnsortedKeys { index: usize },
+
+    /// The key at `index` of a CHD map is not stored at the index its hash points to.
+    ChdMismatch { index: usize },
+}
+
+impl From<CursorError> for ValidationErrorKind {
+    fn from(err: CursorError) -> Self {
+        ValidationErrorKind::Cursor(err)
+    }
+}
+
+impl core::fmt::Display for ValidationErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ValidationErrorKind::Cursor(err) => write!(f, "{err}"),
+            ValidationErrorKind::InvalidLength { expected, actual } => {
+                write!(f, "node is {actual} bytes long instead of {expected}")
+            }
+            ValidationErrorKind::DescriptorOutOfBounds { index } => {
+                write!(f, "descriptor of child {index} points outside of the node")
+            }
+            ValidationErrorKind::NonMonotonicDescriptors { index } => write!(
+                f,
+                "child {index} starts before the end of the previous child"
+            ),
+            ValidationErrorKind::UnterminatedKey { index } => write!(
+                f,
+                "key {index} is missing its null-terminator or contains a null"
+            ),
+            ValidationErrorKind::KeyUtf8Error { index } => {
+                write!(f, "key {index} is not valid UTF-8")
+            }
+            ValidationErrorKind::UnsortedKeys { index } => {
+                write!(f, "key {index} is out of order")
+            }
+            ValidationErrorKind::ChdMismatch { index } => {
+                write!(f, "key {index} is not stored where its hash points to")
+            }
+        }
+    }
+}
+
+/// Describes the first malformed node found by `Cursor::validate`.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ValidationError {
+    /// The offset of the malformed node from the start of the underlying buffer.
+    pub offset: usize,
+    /// The path leading to the malformed node, relative to the validated cursor.
+    pub path: Path,
+    pub kind: ValidationErrorKind,
+}
+
+impl core::fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{} at offset {} (path: ", self.kind, self.offset)?;
+        if self.path.segments().is_empty() {
+            write!(f, "/")?;
+        }
+        write!(f, "{})", self.path)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ValidationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ValidationErrorKind::Cursor(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// A container node whose children are being validated.
+struct Frame {
+    /// The absolute range of the node inside the buffer.
+    range: Range<usize>,
+    raw_cursor: RawCursor,
+    /// The index of the next child to validate.
+    next_child: usize,
+}
+
+impl<T: Clone + AsRef<[u8]>> Cursor<T> {
+    /// Recursively validates the node pointed to by the cursor, and all of its descendants.
+    ///
+    /// This checks that all element types are known, all offsets and lengths are within bounds
+    /// and monotonic, all strings and keys are null-terminated UTF-8, and that map keys are stored
+    /// where lookups expect them (sorted for Eytzinger maps, matching their hash for CHD maps).
+    ///
+    /// A validated document can be navigated without running into malformed nodes.
+    /// Validation is linear in the size of the document, and does not recurse on the stack.
+    pub fn validate(&self) -> Result<(), ValidationError> {
+        let buffer = self.buffer.as_ref();
+        let mut stack: Vec<Frame> = Vec::new();
+        let mut pending = Some(self.range.clone());
+
+        loop {
+            if let Some(range) = pending.take() {
+                let raw_cursor = buffer
+                    .get(range.clone())
+                    .ok_or(CursorError::DocumentTooShort)
+                    .and_then(RawCursor::new)
+                    .map_err(ValidationErrorKind::from)
+                    .and_then(|raw_cursor| {
+                        validate_node(&raw_cursor, &buffer[range.clone()])?;
+                        Ok(raw_cursor)
+                    })
+                    .map_err(|kind| make_error(&stack, buffer, range.start, kind))?;
+
+                if raw_cursor.child_count > 0 {
+                    stack.push(Frame {
+                        range,
+                        raw_cursor,
+                        next_child: 0,
+                    });
+                }
+            }
+
+            let Some(frame) = stack.last() else {
+                return Ok(());
+            };
+            if frame.next_child >= frame.raw_cursor.child_count as usize {
+                stack.pop();
+                continue;
+            }
+
+            // Offsets were already validated along with the parent node.
+            let mut child_range = frame
+                .raw_cursor
+                .get_range_by_index(&buffer[frame.range.clone()], frame.next_child)
+                .map_err(|err| make_error(&stack, buffer, frame.range.start, err.into()))?;
+            child_range.start += frame.range.start;
+            child_range.end += frame.range.start;
+            if let Some(frame) = stack.last_mut() {
+                frame.next_child += 1;
+            }
+            pending = Some(child_range);
+        }
+    }
+}
+
+fn make_error(
+    stack: &[Frame],
+    buffer: &[u8],
+    offset: usize,
+    kind: ValidationErrorKind,
+) -> ValidationError {
+    let path = stack
+        .iter()
+        .map(|frame| {
+            let index = frame.next_child - 1;
+            match frame.raw_cursor.element_type {
+                ElementTypeCode::Array => OwnedPathSegment::Index(index),
+                _ => frame
+                    .raw_cursor
+                    .get_key_by_index(&buffer[frame.range.clone()], index)
+                    .map(|key| OwnedPathSegment::Key(key.into()))
+                    .unwrap_or(OwnedPathSegment::Index(index)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    ValidationError {
+        offset,
+        path: path.into(),
+        kind,
+    }
+}
+
+/// Validates a single node, without descending into its children.
+fn validate_node(raw_cursor: &RawCursor, buffer: &[u8]) -> Result<(), ValidationErrorKind> {
+    let expected = match raw_cursor.element_type {
+        ElementTypeCode::Double | ElementTypeCode::Int64 | ElementTypeCode::UInt64 => {
+            ELEMENT_TYPE_SIZE + 8
+        }
+        ElementTypeCode::Int32 | ElementTypeCode::UInt32 => ELEMENT_TYPE_SIZE + 4,
+        ElementTypeCode::False | ElementTypeCode::True | ElementTypeCode::None => ELEMENT_TYPE_SIZE,
+        ElementTypeCode::Binary => return Ok(()),
+        ElementTypeCode::PackedArray => {
+            split_packed_array(&buffer[ELEMENT_TYPE_SIZE..])?;
+            return Ok(());
+        }
+        ElementTypeCode::String => {
+            CStr::from_bytes_with_nul(&buffer[ELEMENT_TYPE_SIZE..])
+                .map_err(|_| CursorError::UnterminatedString)?
+                .to_str()
+                .map_err(|_| CursorError::Utf8Error)?;
+            return Ok(());
+        }
+        ElementTypeCode::Array => return validate_array(raw_cursor, buffer),
+        ElementTypeCode::Map | ElementTypeCode::MapCHD => return validate_map(raw_cursor, buffer),
+    };
+
+    if buffer.len() != expected {
+        return Err(ValidationErrorKind::InvalidLength {
+            expected,
+            actual: buffer.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Makes sure that the given child value offsets are strictly increasing, and that they
+/// all point at some byte between the end of the headers and the end of the node.
+fn validate_value_offsets(
+    value_offsets: impl Iterator<Item = Result<usize, CursorError>>,
+    headers_end: usize,
+    node_length: usize,
+) -> Result<(), ValidationErrorKind> {
+    let mut minimum_offset = headers_end;
+    for (index, value_offset) in value_offsets.enumerate() {
+        let value_offset = value_offset?;
+        if value_offset < headers_end || value_offset >= node_length {
+            return Err(ValidationErrorKind::DescriptorOutOfBounds { index });
+        }
+        if value_offset < minimum_offset {
+            return Err(ValidationErrorKind::NonMonotonicDescriptors { index });
+        }
+        // Every element is at least one byte long.
+        minimum_offset = value_offset + 1;
+    }
+    Ok(())
+}
+
+fn validate_array(raw_cursor: &RawCursor, buffer: &[u8]) -> Result<(), ValidationErrorKind> {
+    let descriptors_start = ELEMENT_TYPE_SIZE + U32_SIZE_BYTES;
+    let value_offsets = (0..raw_cursor.child_count as usize).map(|index| {
+        get_u32_at_offset(buffer, descriptors_start + ARRAY_DESCRIPTOR_SIZE * index)
+            .map(|offset| offset as usize)
+    });
+    validate_value_offsets(value_offsets, raw_cursor.header_size(), buffer.len())
+}
+
+fn validate_map(raw_cursor: &RawCursor, buffer: &[u8]) -> Result<(), ValidationErrorKind> {
+    let child_count = raw_cursor.child_count as usize;
+    let descriptors = raw_cursor.get_map_descriptors(buffer)?;
+    let headers_end = raw_cursor.header_size();
+
+    let value_offsets = (0..child_count)
+        .map(|index| get_map_descriptor(descriptors, index).map(|d| d.value_offset));
+    validate_value_offsets(value_offsets, headers_end, buffer.len())?;
+
+    // Keys are stored between the descriptors and the first value.
+    let keys_end = match child_count {
+        0 => headers_end,
+        _ => get_map_descriptor(descriptors, 0)?.value_offset,
+    };
+    let mut keys = Vec::with_capacity(child_count);
+    for index in 0..child_count {
+        let MapDescriptor {
+            key_offset,
+            key_length,
+            ..
+        } = get_map_descriptor(descriptors, index)?;
+
+        // The null-terminator must also be within bounds.
+        if key_offset < headers_end || key_offset + key_length >= keys_end {
+            return Err(ValidationErrorKind::DescriptorOutOfBounds { index });
+        }
+        let key = &buffer[key_offset..key_offset + key_length];
+        if buffer[key_offset + key_length] != 0 || memchr::memchr(0, key).is_some() {
+            return Err(ValidationErrorKind::UnterminatedKey { index });
+        }
+        if core::str::from_utf8(key).is_err() {
+            return Err(ValidationErrorKind::KeyUtf8Error { index });
+        }
+        keys.push(key);
+    }
+
+    if raw_cursor.element_type == ElementTypeCode::MapCHD {
+        for (index, key) in keys.iter().enumerate() {
+            if raw_cursor.get_chd_index_by_key(buffer, key)? != index {
+                return Err(ValidationErrorKind::ChdMismatch { index });
+            }
+        }
+    } else {
+        let mut previous_key: Option<&[u8]> = None;
+        for index in EytzingerInOrder::new(child_count) {
+            if previous_key.is_some_and(|previous_key| previous_key >= keys[index]) {
+                return Err(ValidationErrorKind::UnsortedKeys { index });
+            }
+            previous_key = Some(keys[index]);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::{serialize, serialize_with, DOC, DOC_PHF};
+    use crate::serializer::SerializationOptions;
+    use crate::PathSegment;
+    use std::collections::HashMap;
+
+    fn validate(buffer: &[u8]) -> Result<(), ValidationError> {
+        Cursor::new(buffer).unwrap().validate()
+    }
+
+    #[test]
+    fn test_valid_documents() {
+        assert_eq!(validate(DOC), Ok(()));
+        assert_eq!(validate(DOC_PHF), Ok(()));
+
+        for chd_threshold in [1, 1000] {
+            let mut map = HashMap::new();
+            for i in 0..100u32 {
+                map.insert(format!("item_{i}"), [i, i + 1]);
+            }
+            let map: HashMap<_, _> = map.iter().map(|(k, v)| (k, &v[..])).collect();
+            let buf = serialize_with(&map, &SerializationOptions { chd_threshold });
+            assert_eq!(validate(&buf), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_leaves() {
+        assert_eq!(validate(b"\x10\x01\x00\x00\x00"), Ok(()));
+        assert_eq!(
+            validate(b"\x10\x01\x00\x00\x00\x00"),
+            Err(ValidationError {
+                offset: 0,
+                path: Path::default(),
+                kind: ValidationErrorKind::InvalidLength {
+                    expected: 5,
+                    actual: 6
+                },
+            })
+        );
+        assert_eq!(
+            validate(b"\x02abc").unwrap_err().kind,
+            ValidationErrorKind::Cursor(CursorError::UnterminatedString)
+        );
+        assert_eq!(
+            validate(b"\x02\xff\x00").unwrap_err().kind,
+            ValidationErrorKind::Cursor(CursorError::Utf8Error)
+        );
+    }
+
+    #[test]
+    fn test_array_errors() {
+        // [false, true]
+        let valid = b"\x04\x02\x00\x00\x00\x0D\x00\x00\x00\x0E\x00\x00\x00\x08\x09";
+        assert_eq!(validate(valid), Ok(()));
+
+        let mut non_monotonic = *valid;
+        non_monotonic[9] = 0x0D;
+        assert_eq!(
+            validate(&non_monotonic).unwrap_err().kind,
+            ValidationErrorKind::NonMonotonicDescriptors { index: 1 }
+        );
+
+        let mut out_of_bounds = *valid;
+        out_of_bounds[9] = 0x20;
+        assert_eq!(
+            validate(&out_of_bounds).unwrap_err().kind,
+            ValidationErrorKind::DescriptorOutOfBounds { index: 1 }
+        );
+
+        let mut bad_child = *valid;
+        bad_child[14] = 0x07;
+        assert_eq!(
+            validate(&bad_child),
+            Err(ValidationError {
+                offset: 14,
+                path: vec![OwnedPathSegment::Index(1)].into(),
+                kind: ValidationErrorKind::Cursor(CursorError::InvalidElementType(0x07)),
+            })
+        );
+    }
+
+    #[test]
+    fn test_nested_error_path() {
+        let cursor = Cursor::new(DOC).unwrap();
+        let offset = cursor
+            .get_value_by_key("BLARG")
+            .unwrap()
+            .get_value_by_index(2)
+            .unwrap()
+            .range
+            .start;
+
+        let mut corrupted = DOC.to_vec();
+        corrupted[offset] = 0xFF;
+        assert_eq!(
+            validate(&corrupted),
+            Err(ValidationError {
+                offset,
+                path: vec![
+                    OwnedPathSegment::Key("BLARG".into()),
+                    OwnedPathSegment::Index(2)
+                ]
+                .into(),
+                kind: ValidationErrorKind::Cursor(CursorError::InvalidElementType(0xFF)),
+            })
+        );
+        assert_eq!(
+            alloc::format!("invalid element type 0xff at offset {offset} (path: /BLARG/2)"),
+            validate(&corrupted).unwrap_err().to_string()
+        );
+
+        // Keys in the path are escaped like in JSON Pointers.
+        let map = HashMap::from([("a/b~", HashMap::from([("c", 1)]))]);
+        let mut corrupted = serialize(&map);
+        let offset = Cursor::new(&corrupted[..])
+            .unwrap()
+            .goto(["a/b~", "c"].map(PathSegment::Key).into_iter())
+            .unwrap()
+            .range
+            .start;
+        corrupted[offset] = 0xFF;
+        assert_eq!(
+            alloc::format!("invalid element type 0xff at offset {offset} (path: /a~1b~0/c)"),
+            validate(&corrupted).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_map_key_errors() {
+        let map = HashMap::from([("a", true), ("b", false)]);
+        let valid = serialize(&map);
+        assert_eq!(validate(&valid), Ok(()));
+
+        // Keys are stored right after the descriptors, in Eytzinger order: "b", then "a".
+        let keys_offset = 1 + 4 + 2 * 8;
+        assert_eq!(&valid[keys_offset..keys_offset + 4], b"b\x00a\x00");
+
+        let mut unsorted = valid.clone();
+        unsorted[keys_offset + 2] = b'c';
+        assert_eq!(
+            validate(&unsorted).unwrap_err().kind,
+            ValidationErrorKind::UnsortedKeys { index: 0 }
+        );
+
+        let mut unterminated = valid.clone();
+        unterminated[keys_offset + 1] = b'x';
+        assert_eq!(
+            validate(&unterminated).unwrap_err().kind,
+            ValidationErrorKind::UnterminatedKey { index: 0 }
+        );
+
+        let mut invalid_utf8 = valid;
+        invalid_utf8[keys_offset] = 0xFF;
+        assert_eq!(
+            validate(&invalid_utf8).unwrap_err().kind,
+            ValidationErrorKind::KeyUtf8Error { index: 0 }
+        );
+    }
+
+    #[test]
+    fn test_chd_mismatch() {
+        let cursor = Cursor::new(DOC_PHF).unwrap();
+        let index = (0..cursor.get_children_count())
+            .find(|index| cursor.get_key_by_index(*index) == Ok("BLARG"))
+            .unwrap();
+
+        // Rename the key to another one with the same length.
+        let key_offset = DOC_PHF
+            .windows(6)
+            .position(|window| window == b"BLARG\x00")
+            .unwrap();
+        let mut corrupted = DOC_PHF.to_vec();
+        corrupted[key_offset..key_offset + 5].copy_from_slice(b"BLORG");
+        assert_eq!(
+            validate(&corrupted).unwrap_err().kind,
+            ValidationErrorKind::ChdMismatch { index }
+        );
+    }
+
+    #[test]
+    fn test_truncated_headers() {
+        // An array claiming to hold 1000 items.
+        assert_eq!(
+            Cursor::new(&b"\x04\xE8\x03\x00\x00\x00\x00\x00\x00"[..]).unwrap_err(),
+            CursorError::DocumentTooShort
+        );
+    }
+}
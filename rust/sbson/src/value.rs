@@ -0,0 +1,369 @@
+use crate::{Cursor, CursorError, ElementTypeCode, PackedItem, RECURSION_LIMIT};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::ops::{Index, IndexMut};
+
+/// An owned SBSON node, for when a subtree needs to outlive its document or be modified.
+///
+/// Unlike going through `serde_json::Value`, every element type keeps its exact encoding,
+/// so a value read from a document is written back the same way, up to the layout of its maps.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Double(f64),
+    String(String),
+    Map(BTreeMap<String, Value>),
+    Array(Vec<Value>),
+    Binary(Vec<u8>),
+    PackedArray(PackedValues),
+    Bool(bool),
+    Null,
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+}
+
+/// The items of a packed array, by their type.
+#[derive(Clone, PartialEq, Debug)]
+pub enum PackedValues {
+    Double(Vec<f64>),
+    Int32(Vec<i32>),
+    UInt32(Vec<u32>),
+    Int64(Vec<i64>),
+    UInt64(Vec<u64>),
+}
+
+static NULL: Value = Value::Null;
+
+impl Value {
+    /// Returns the element type this value is serialized as.
+    ///
+    /// Maps are reported as `Map`, as the layout is only chosen once they are serialized.
+    pub fn element_type(&self) -> ElementTypeCode {
+        match self {
+            Value::Double(_) => ElementTypeCode::Double,
+            Value::String(_) => ElementTypeCode::String,
+            Value::Map(_) => ElementTypeCode::Map,
+            Value::Array(_) => ElementTypeCode::Array,
+            Value::Binary(_) => ElementTypeCode::Binary,
+            Value::PackedArray(_) => ElementTypeCode::PackedArray,
+            Value::Bool(false) => ElementTypeCode::False,
+            Value::Bool(true) => ElementTypeCode::True,
+            Value::Null => ElementTypeCode::None,
+            Value::Int32(_) => ElementTypeCode::Int32,
+            Value::UInt32(_) => ElementTypeCode::UInt32,
+            Value::Int64(_) => ElementTypeCode::Int64,
+            Value::UInt64(_) => ElementTypeCode::UInt64,
+        }
+    }
+
+    /// Returns the value of a key in a map, or `None` if this isn't a map or the key is missing.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+
+    /// Returns the item at an index of an array, or `None` if this isn't an array
+    /// or the index is out of bounds.
+    pub fn get_index(&self, index: usize) -> Option<&Value> {
+        match self {
+            Value::Array(items) => items.get(index),
+            _ => None,
+        }
+    }
+
+    fn from_cursor<T: Clone + AsRef<[u8]>>(
+        cursor: &Cursor<T>,
+        remaining_depth: usize,
+    ) -> Result<Self, CursorError> {
+        let child_depth = || {
+            remaining_depth
+                .checked_sub(1)
+                .ok_or(CursorError::RecursionLimitExceeded)
+        };
+        Ok(match cursor.get_element_type() {
+            ElementTypeCode::Map | ElementTypeCode::MapCHD => {
+                let remaining_depth = child_depth()?;
+                let mut map = BTreeMap::new();
+                for index in 0..cursor.get_children_count() {
+                    let key = cursor.get_key_by_index(index)?;
+                    let value = cursor.get_value_by_index(index)?;
+                    map.insert(key.into(), Value::from_cursor(&value, remaining_depth)?);
+                }
+                Value::Map(map)
+            }
+            ElementTypeCode::Array => {
+                let remaining_depth = child_depth()?;
+                let items = (0..cursor.get_children_count())
+                    .map(|index| {
+                        Value::from_cursor(&cursor.get_value_by_index(index)?, remaining_depth)
+                    })
+                    .collect::<Result<_, _>>()?;
+                Value::Array(items)
+            }
+            ElementTypeCode::PackedArray => {
+                Value::PackedArray(match cursor.get_packed_item_type()? {
+                    ElementTypeCode::Double => PackedValues::Double(packed_items(cursor)?),
+                    ElementTypeCode::Int32 => PackedValues::Int32(packed_items(cursor)?),
+                    ElementTypeCode::UInt32 => PackedValues::UInt32(packed_items(cursor)?),
+                    ElementTypeCode::Int64 => PackedValues::Int64(packed_items(cursor)?),
+                    ElementTypeCode::UInt64 => PackedValues::UInt64(packed_items(cursor)?),
+                    item_type => return Err(CursorError::InvalidElementType(item_type as u8)),
+                })
+            }
+            ElementTypeCode::Binary => Value::Binary(cursor.get_binary()?.into()),
+            ElementTypeCode::String => Value::String(cursor.get_str()?.into()),
+            ElementTypeCode::Double => Value::Double(cursor.get_double()?),
+            ElementTypeCode::False => Value::Bool(false),
+            ElementTypeCode::True => Value::Bool(true),
+            ElementTypeCode::None => Value::Null,
+            ElementTypeCode::Int32 => Value::Int32(cursor.get_i32()?),
+            ElementTypeCode::UInt32 => Value::UInt32(cursor.get_u32()?),
+            ElementTypeCode::Int64 => Value::Int64(cursor.get_i64()?),
+            ElementTypeCode::UInt64 => Value::UInt64(cursor.get_u64()?),
+        })
+    }
+
+    /// Compares against a node without copying it; malformed nodes are never equal.
+    fn eq_cursor<T: Clone + AsRef<[u8]>>(&self, cursor: &Cursor<T>) -> bool {
+        match self {
+            Value::Map(map) => {
+                matches!(
+                    cursor.get_element_type(),
+                    ElementTypeCode::Map | ElementTypeCode::MapCHD
+                ) && cursor.get_children_count() == map.len()
+                    && map.iter().all(|(key, value)| {
+                        cursor
+                            .get_value_by_key(key)
+                            .is_ok_and(|child| value.eq_cursor(&child))
+                    })
+            }
+            Value::Array(items) => {
+                cursor.get_element_type() == ElementTypeCode::Array
+                    && cursor.get_children_count() == items.len()
+                    && items.iter().enumerate().all(|(index, item)| {
+                        cursor
+                            .get_value_by_index(index)
+                            .is_ok_and(|child| item.eq_cursor(&child))
+                    })
+            }
+            Value::PackedArray(items) => match items {
+                PackedValues::Double(items) => packed_eq(items, cursor),
+                PackedValues::Int32(items) => packed_eq(items, cursor),
+                PackedValues::UInt32(items) => packed_eq(items, cursor),
+                PackedValues::Int64(items) => packed_eq(items, cursor),
+                PackedValues::UInt64(items) => packed_eq(items, cursor),
+            },
+            Value::Binary(bytes) => cursor.get_binary() == Ok(bytes.as_slice()),
+            Value::String(s) => cursor.get_str() == Ok(s.as_str()),
+            Value::Double(v) => cursor.get_double() == Ok(*v),
+            Value::Bool(v) => cursor.get_bool() == Ok(*v),
+            Value::Null => cursor.get_none().is_ok(),
+            Value::Int32(v) => cursor.get_i32() == Ok(*v),
+            Value::UInt32(v) => cursor.get_u32() == Ok(*v),
+            Value::Int64(v) => cursor.get_i64() == Ok(*v),
+            Value::UInt64(v) => cursor.get_u64() == Ok(*v),
+        }
+    }
+}
+
+fn packed_items<E: PackedItem, T: Clone + AsRef<[u8]>>(
+    cursor: &Cursor<T>,
+) -> Result<Vec<E>, CursorError> {
+    Ok(cursor.get_packed_array::<E>()?.iter().collect())
+}
+
+fn packed_eq<E: PackedItem + PartialEq, T: Clone + AsRef<[u8]>>(
+    items: &[E],
+    cursor: &Cursor<T>,
+) -> bool {
+    cursor
+        .get_packed_array::<E>()
+        .is_ok_and(|packed| packed.iter().eq(items.iter().copied()))
+}
+
+/// Copies the node pointed to by the cursor, along with all of its children.
+///
+/// Integers keep their exact element type, and a `Value` never compares equal to an integer
+/// of a different type, even if it has the same numeric value.
+impl<T: Clone + AsRef<[u8]>> TryFrom<&Cursor<T>> for Value {
+    type Error = CursorError;
+
+    fn try_from(cursor: &Cursor<T>) -> Result<Self, CursorError> {
+        Value::from_cursor(cursor, RECURSION_LIMIT)
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> TryFrom<Cursor<T>> for Value {
+    type Error = CursorError;
+
+    fn try_from(cursor: Cursor<T>) -> Result<Self, CursorError> {
+        Value::try_from(&cursor)
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> PartialEq<Cursor<T>> for Value {
+    fn eq(&self, other: &Cursor<T>) -> bool {
+        self.eq_cursor(other)
+    }
+}
+
+impl<T: Clone + AsRef<[u8]>> PartialEq<Value> for Cursor<T> {
+    fn eq(&self, other: &Value) -> bool {
+        other.eq_cursor(self)
+    }
+}
+
+/// Indexes into a map by key, returning `Null` if this isn't a map or the key is missing.
+impl Index<&str> for Value {
+    type Output = Value;
+
+    fn index(&self, key: &str) -> &Value {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// Indexes into an array, returning `Null` if this isn't an array or the index is out of bounds.
+impl Index<usize> for Value {
+    type Output = Value;
+
+    fn index(&self, index: usize) -> &Value {
+        self.get_index(index).unwrap_or(&NULL)
+    }
+}
+
+/// Returns a mutable reference to the value of a key, inserting `Null` if it's missing.
+///
+/// Panics if this isn't a map.
+impl IndexMut<&str> for Value {
+    fn index_mut(&mut self, key: &str) -> &mut Value {
+        match self {
+            Value::Map(map) => map.entry(key.into()).or_insert(Value::Null),
+            _ => panic!("cannot index into {:?} by key", self.element_type()),
+        }
+    }
+}
+
+/// Returns a mutable reference to an item of an array.
+///
+/// Panics if this isn't an array, or if the index is out of bounds.
+impl IndexMut<usize> for Value {
+    fn index_mut(&mut self, index: usize) -> &mut Value {
+        match self {
+            Value::Array(items) => &mut items[index],
+            _ => panic!("cannot index into {:?} by position", self.element_type()),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl crate::serializer::Serialize for Value {
+    fn serialize(
+        &self,
+        options: &crate::serializer::SerializationOptions,
+        output: &mut dyn crate::serializer::Output,
+    ) -> std::io::Result<usize> {
+        use crate::serializer::{Bytes, Packed};
+
+        match self {
+            Value::Double(v) => v.serialize(options, output),
+            Value::String(s) => s.serialize(options, output),
+            Value::Map(map) => map.serialize(options, output),
+            Value::Array(items) => items.serialize(options, output),
+            Value::Binary(bytes) => Bytes(bytes).serialize(options, output),
+            Value::PackedArray(items) => match items {
+                PackedValues::Double(items) => Packed(items).serialize(options, output),
+                PackedValues::Int32(items) => Packed(items).serialize(options, output),
+                PackedValues::UInt32(items) => Packed(items).serialize(options, output),
+                PackedValues::Int64(items) => Packed(items).serialize(options, output),
+                PackedValues::UInt64(items) => Packed(items).serialize(options, output),
+            },
+            Value::Bool(v) => v.serialize(options, output),
+            Value::Null => ().serialize(options, output),
+            Value::Int32(v) => v.serialize(options, output),
+            Value::UInt32(v) => v.serialize(options, output),
+            Value::Int64(v) => v.serialize(options, output),
+            Value::UInt64(v) => v.serialize(options, output),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serializer::fixtures::serialize_with;
+    use crate::serializer::{Bytes, Packed, SerializationOptions};
+
+    #[test]
+    fn test_value_round_trip() {
+        let doc = serialize_with(
+            &(
+                (1i32, 2u32, -3i64, u64::MAX),
+                0.5,
+                "text",
+                Bytes(b"\x00\x01"),
+                Packed(&[1.5, 2.5][..]),
+                (true, false, ()),
+                BTreeMap::from([("a", 1i32), ("b", 2i32)]),
+            ),
+            &SerializationOptions {
+                chd_threshold: usize::MAX,
+            },
+        );
+        let cursor = Cursor::new(&doc[..]).unwrap();
+        let value = Value::try_from(&cursor).unwrap();
+        assert_eq!(value, cursor);
+        assert_eq!(Value::Int32(1), value[0][0]);
+        assert_eq!(Value::UInt64(u64::MAX), value[0][3]);
+        assert_eq!(Value::Binary(vec![0, 1]), value[3]);
+        assert_eq!(
+            Value::PackedArray(PackedValues::Double(vec![1.5, 2.5])),
+            value[4]
+        );
+        assert_eq!(Value::Int32(2), value[6]["b"]);
+        assert_eq!(Value::Null, value[6]["missing"]);
+        assert_eq!(Value::Null, value[100]);
+        assert_eq!(
+            doc,
+            serialize_with(
+                &value,
+                &SerializationOptions {
+                    chd_threshold: usize::MAX
+                }
+            )
+        );
+
+        // Maps compare equal whatever their layout, but integers must keep their type.
+        let chd = serialize_with(&value, &SerializationOptions { chd_threshold: 1 });
+        assert_eq!(Cursor::new(&chd[..]).unwrap(), value);
+        let mut changed = value.clone();
+        changed[0][0] = Value::Int64(1);
+        assert_ne!(changed, cursor);
+        changed[6]["c"] = Value::String("new".into());
+        assert_eq!(Some(&Value::String("new".into())), changed[6].get("c"));
+        assert_ne!(changed[6], cursor.get_value_by_index(6).unwrap());
+    }
+
+    #[test]
+    fn test_value_recursion_limit() {
+        let mut value = Value::Null;
+        for _ in 0..RECURSION_LIMIT + 1 {
+            value = Value::Array(vec![value]);
+        }
+        let doc = serialize_with(
+            &value,
+            &SerializationOptions {
+                chd_threshold: usize::MAX,
+            },
+        );
+        let cursor = Cursor::new(&doc[..]).unwrap();
+        assert_eq!(
+            Err(CursorError::RecursionLimitExceeded),
+            Value::try_from(cursor.clone())
+        );
+        assert_eq!(value, cursor);
+    }
+}